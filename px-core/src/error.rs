@@ -9,6 +9,10 @@ pub enum Error {
     TimeCacheError(TimeError),
     IO(std::io::Error),
     RangeError,
+    /// peer refused to upgrade the connection (STARTTLS), holds its reply
+    UpgradeRefused(String),
+    /// the schedule has no run left (`0 0 31 2 *`)
+    ScheduleExhausted,
 }
 
 // use super::netlib::parsers::nmap::Error as ParseErr;
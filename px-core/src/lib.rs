@@ -3,7 +3,9 @@
 pub mod error;
 pub mod model;
 pub mod pool;
+pub mod schedule;
 pub mod util;
+pub mod wrapper;
 
 #[cfg(feature = "include-test")]
 pub use pool::test as tests;
\ No newline at end of file
@@ -1,9 +1,14 @@
+use serde::{Serialize, Deserialize};
 
-#[derive(Debug, Eq, PartialEq, Hash, Copy, Clone)]
+#[derive(Debug, Eq, PartialEq, Hash, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum State {
     Closed,
     Filtered,
     Open,
+    /// nothing came back, which is all an open udp port might ever do
+    #[serde(rename = "open|filtered")]
+    OpenFiltered,
 }
 
 impl std::fmt::Display for State {
@@ -11,7 +16,8 @@ impl std::fmt::Display for State {
         let x = match self {
             State::Closed => "closed",
             State::Open => "open",
-            State::Filtered => "filtered"
+            State::Filtered => "filtered",
+            State::OpenFiltered => "open|filtered",
         };
         
         write!(f, "{}", x)?;
@@ -8,6 +8,9 @@ pub use worker::*;
 mod pool;
 pub use pool::Pool;
 
+mod observer;
+pub use observer::Observer;
+
 mod stash;
 
 
@@ -22,3 +25,21 @@ pub trait CRON: std::fmt::Debug {
     /// Run function, and then append to parent if more jobs are needed
     async fn exec(state: &mut Self::State) -> Result<JobCtrl<Self::Response>, Error>;
 }
+
+/// The host a job is ran against,
+/// used to keep track of time spent on each host
+pub trait Destination {
+    fn destination(&self) -> std::net::IpAddr;
+}
+
+impl Destination for std::net::SocketAddr {
+    fn destination(&self) -> std::net::IpAddr {
+        self.ip()
+    }
+}
+
+impl Destination for std::net::IpAddr {
+    fn destination(&self) -> std::net::IpAddr {
+        *self
+    }
+}
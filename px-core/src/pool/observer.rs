@@ -0,0 +1,31 @@
+use std::net::IpAddr;
+use super::worker::JobCtrl;
+
+/// Receives results and lifecycle events from a `Pool` as they happen,
+/// so embedders can plug in outputs, metrics, or chain jobs
+/// without owning the loop that drives the pool.
+///
+/// Every method does nothing by default.
+pub trait Observer<R, S>: Send {
+    /// A job finished, called once for every entry `Pool::tick` returns
+    fn on_result(&mut self, _ctrl: &JobCtrl<R>, _state: &S) {}
+
+    /// A job was handed to the worker
+    fn on_started(&mut self, _state: &S) {}
+
+    /// A job failed because a resource was blocked (no route, out of file descriptors),
+    /// and was stashed so it can be retried later
+    fn on_stashed(&mut self, _ctrl: &JobCtrl<R>, _state: &S) {}
+
+    /// A stashed job was released, and queued once again.
+    /// `attempt` counts from 1
+    fn on_retried(&mut self, _state: &S, _attempt: u8) {}
+
+    /// A job was stashed too many times and won't be retried again,
+    /// its last result is still passed along to `on_result`
+    fn on_given_up(&mut self, _ctrl: &JobCtrl<R>, _state: &S) {}
+
+    /// `host` ran out of its time budget,
+    /// its queued and stashed jobs were abandoned
+    fn on_host_timeout(&mut self, _host: &IpAddr) {}
+}
@@ -1,14 +1,20 @@
 use super::{
     stash::Stash,
+    observer::Observer,
+    Destination,
     worker::{Worker, JobCtrl, JobErr},
 };
 
-use tokio_stream::{self as stream, StreamExt};
-use std::fmt::Debug;
+use tokio_stream::{self as stream};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    net::IpAddr,
+    time::{Duration, Instant},
+};
 use super::CRON;
 //use crate::cli::input::combine::Feeder;
 
-
 pub struct Pool<J, R, S>
 where
     J: CRON<Response = R, State = S>,
@@ -17,24 +23,78 @@ where
 
 {
     pub pool: Worker<J, R, S>,
-    stash: Stash<S>,
+    // stashed jobs, along with the amount of times each was retried
+    stash: Stash<(S, u8)>,
+    // released from the stash, waiting on the worker
+    retrying: Vec<(S, u8)>,
+    observers: Vec<Box<dyn Observer<R, S>>>,
+
+    // jobs are retried forever unless set
+    max_retries: Option<u8>,
 
+    // wall time budget per destination,
+    // counted from the first job spawned against it
+    host_timeout: Option<Duration>,
+    host_started: HashMap<IpAddr, Instant>,
+    // jobs running, or stashed per destination
+    host_pending: HashMap<IpAddr, usize>,
+    timed_out: HashSet<IpAddr>,
+    // timed out hosts not yet taken by `take_timed_out`
+    timed_out_buf: Vec<IpAddr>,
 }
 
-impl<J, R, S> Pool<J, R, S> 
+impl<J, R, S> Pool<J, R, S>
 where
 	J: CRON<Response = R, State = S> + std::marker::Unpin,
 	R: Send + Sync + Clone + std::fmt::Debug + 'static,
-    S: Send + Sync + Clone + std::fmt::Debug + 'static,
-{   
+    S: Send + Sync + Clone + std::fmt::Debug + Destination + 'static,
+{
     #[inline]
     pub fn new(pool: Worker<J, R, S>) -> Self {
         Self {
             pool,
             stash: Stash::new(),
+            retrying: Vec::new(),
+            observers: Vec::new(),
+            max_retries: None,
+            host_timeout: None,
+            host_started: HashMap::new(),
+            host_pending: HashMap::new(),
+            timed_out: HashSet::new(),
+            timed_out_buf: Vec::new(),
         }
     }
 
+    /// Registers `observer` to receive every result and lifecycle event from here on
+    pub fn observe(&mut self, observer: Box<dyn Observer<R, S>>) {
+        self.observers.push(observer);
+    }
+
+    /// Gives up on a job once it was stashed more than `max_retries` times,
+    /// by default jobs are retried until they go through
+    #[inline]
+    pub fn set_max_retries(&mut self, max_retries: Option<u8>) {
+        self.max_retries = max_retries;
+    }
+
+    /// Caps the wall time spent on a single destination,
+    /// once spent, its queued and stashed jobs are abandoned
+    #[inline]
+    pub fn set_host_timeout(&mut self, host_timeout: Option<Duration>) {
+        self.host_timeout = host_timeout;
+    }
+
+    /// Hosts that ran out of their time budget since the last call
+    #[inline]
+    pub fn take_timed_out(&mut self) -> Vec<IpAddr> {
+        std::mem::take(&mut self.timed_out_buf)
+    }
+
+    #[inline]
+    pub fn is_timed_out(&self, host: &IpAddr) -> bool {
+        self.timed_out.contains(host)
+    }
+
     #[inline]
     pub fn calc_new_spawns(&self, buf_len: usize) -> usize {
         self.pool.calc_new_spawns(buf_len)
@@ -42,7 +102,15 @@ where
 
     #[inline]
     pub fn is_working(&self) -> bool {
-        self.pool.job_count() > 1 && self.stash.amount() > 0  
+        self.pool.job_count() > 1 && self.stash.amount() > 0
+    }
+
+    /// Nothing is running, stashed, or waiting to be retried
+    #[inline]
+    pub fn is_idle(&self) -> bool {
+        self.pool.job_count() <= 1
+            && self.stash.amount() == 0
+            && self.retrying.is_empty()
     }
 
     #[inline]
@@ -50,57 +118,227 @@ where
         self.pool.job_count()
     }
 
+    /// Releases every stashed job without waiting on its delay,
+    /// the next `spawn` runs them ahead of anything queued, keeping their retry count.
+    /// Returns the amount of jobs waiting to be retried
     #[inline]
-    pub fn flush_stash(&mut self, buf: &mut Vec<S>) -> usize {
-        self.stash.flush(buf)
+    pub fn flush_stash(&mut self) -> usize {
+        let start = self.retrying.len();
+        self.stash.flush(&mut self.retrying);
+        let released: Vec<(S, u8)> = self.retrying[start..].to_vec();
+        self.notify_retried(&released);
+        // released states are spawned again, and recounted
+        for (state, _attempt) in &released {
+            release_pending(&mut self.host_pending, &state.destination());
+        }
+
+        self.retrying.len()
     }
 
     #[inline]
     pub fn flush_channel(&mut self) -> Vec<(JobCtrl<R>, S)> {
-        self.pool.flush()
+        let mut results = self.pool.flush();
+        let timed_out = &self.timed_out;
+        results.retain(|(_ctrl, state)| !timed_out.contains(&state.destination()));
+        self.notify_results(&results);
+        results
     }
 
-    #[inline]
     pub fn spawn(&mut self, buf: &mut Vec<S>) -> usize {
-        self.pool.spawn(buf)
+        self.expire_hosts(buf);
+        if !self.timed_out.is_empty() {
+            let timed_out = &self.timed_out;
+            buf.retain(|state| !timed_out.contains(&state.destination()));
+        }
+
+        // released jobs go first
+        let retried = self.spawn_retries();
+
+        // only states the worker will accept are taken,
+        // the worker's job count only goes down in between
+        let amount = self.pool.calc_new_spawns(buf.len());
+        let mut batch: Vec<S> = buf.drain(..amount).collect();
+
+        for state in &batch {
+            self.admit(state);
+        }
+
+        retried + self.pool.spawn(&mut batch)
+    }
+
+    /// Spawns released jobs ahead of anything queued
+    fn spawn_retries(&mut self) -> usize {
+        let amount = self.pool.calc_new_spawns(self.retrying.len());
+        let mut batch: Vec<(S, u8)> = self.retrying.drain(..amount).collect();
+
+        for (state, _attempt) in &batch {
+            self.admit(state);
+        }
+
+        self.pool.spawn_retries(&mut batch)
+    }
+
+    fn admit(&mut self, state: &S) {
+        let host = state.destination();
+        self.host_started.entry(host).or_insert_with(Instant::now);
+        *self.host_pending.entry(host).or_insert(0) += 1;
+
+        for observer in self.observers.iter_mut() {
+            observer.on_started(state);
+        }
     }
 
     pub async fn tick(&mut self, queued: &mut Vec<S>) -> Vec<(JobCtrl<R>, S)> {
         const RESCHEDULE: u64 = 5;
 
-        self.stash.release(queued).await;
-        
+        let start = self.retrying.len();
+        self.stash.release(&mut self.retrying).await;
+        let released: Vec<(S, u8)> = self.retrying[start..].to_vec();
+        self.notify_retried(&released);
+        // released states are spawned again below, and recounted
+        for (state, _attempt) in &released {
+            release_pending(&mut self.host_pending, &state.destination());
+        }
+
+        if !self.retrying.is_empty() {
+            self.expire_hosts(&[]);
+            let timed_out = &self.timed_out;
+            self.retrying.retain(|(state, _attempt)| !timed_out.contains(&state.destination()));
+            self.spawn_retries();
+        }
+
         if queued.len() > 0 {
-            self.pool.spawn(queued);
+            self.spawn(queued);
 		}
 
         let mut ret_buf = Vec::new();
-        
-        while let Some(mut chunk) = self.pool.next().await {
-            ret_buf.extend(chunk.drain(..)
+
+        while let Some(mut chunk) = self.pool.take_finished() {
+            for (ctrl, state, attempt) in chunk.drain(..) {
+                // results of abandoned hosts are dropped,
+                // the host is reported as timed out instead
+                if self.timed_out.contains(&state.destination()) {
+                    continue
+                }
+
                 // if resource (nic) is blocked,
                 // stash and remove from results
                 // so the entries may be retried
-                .filter_map(|(ctrl, state)| {
-                    if should_stash(&ctrl) {
-                        self.stash.insert(
-                            state, 
-                            &std::time::Duration::from_secs(RESCHEDULE)
-                        );
-                        None
+                if !should_stash(&ctrl) {
+                    release_pending(&mut self.host_pending, &state.destination());
+                    ret_buf.push((ctrl, state));
+                    continue
+                }
+
+                let attempt = attempt.saturating_add(1);
+
+                if self.max_retries.is_some_and(|max| attempt > max) {
+                    release_pending(&mut self.host_pending, &state.destination());
+                    for observer in self.observers.iter_mut() {
+                        observer.on_given_up(&ctrl, &state);
                     }
-                    else { Some((ctrl, state)) }
-                }));
+                    ret_buf.push((ctrl, state));
+                }
+                else {
+                    for observer in self.observers.iter_mut() {
+                        observer.on_stashed(&ctrl, &state);
+                    }
+                    self.stash.insert(
+                        (state, attempt),
+                        &std::time::Duration::from_secs(RESCHEDULE)
+                    );
+                }
+            }
         }
 
+        self.expire_hosts(&[]);
+        self.notify_results(&ret_buf);
         ret_buf
     }
 
+    /// Marks hosts that overran their budget as timed out,
+    /// and abandons their stashed jobs.
+    /// Only hosts with pending jobs, or about to be spawned from `queued` are checked,
+    /// so hosts that already finished are left alone
+    fn expire_hosts(&mut self, queued: &[S]) {
+        let budget = match self.host_timeout {
+            Some(budget) => budget,
+            None => return
+        };
+
+        let (host_started, timed_out) = (&self.host_started, &self.timed_out);
+        let expired: HashSet<IpAddr> = self.host_pending.keys()
+            .copied()
+            .chain(queued.iter().map(|state| state.destination()))
+            .filter(|host| !timed_out.contains(host))
+            .filter(|host| host_started.get(host)
+                .map(|started| started.elapsed() >= budget)
+                .unwrap_or(false)
+            )
+            .collect();
+
+        for host in expired {
+            self.host_pending.remove(&host);
+            self.stash.retain(|(state, _attempt)| state.destination() != host);
+            self.retrying.retain(|(state, _attempt)| state.destination() != host);
+
+            self.timed_out.insert(host);
+            self.timed_out_buf.push(host);
+
+            for observer in self.observers.iter_mut() {
+                observer.on_host_timeout(&host);
+            }
+        }
+    }
+
+    /// Ticks until `queued`, the stash, and the worker are all empty.
+    /// Results are only handed to observers.
+    pub async fn run_until_idle(&mut self, queued: &mut Vec<S>) {
+        const TICK_NS: u64 = 500;
+
+        loop {
+            // once nothing is running, or stashed,
+            // one more tick collects whatever is left in the channel
+            let idle = queued.len() == 0 && self.is_idle();
+            self.tick(queued).await;
+            
+            if idle && queued.len() == 0 && self.is_idle() {
+                break
+            }
+            tokio::time::sleep(std::time::Duration::from_nanos(TICK_NS)).await;
+        }
+    }
+
+    fn notify_results(&mut self, results: &[(JobCtrl<R>, S)]) {
+        for (ctrl, state) in results {
+            for observer in self.observers.iter_mut() {
+                observer.on_result(ctrl, state);
+            }
+        }
+    }
+
+    fn notify_retried(&mut self, released: &[(S, u8)]) {
+        for (state, attempt) in released {
+            for observer in self.observers.iter_mut() {
+                observer.on_retried(state, *attempt);
+            }
+        }
+    }
+}
+
+fn release_pending(pending: &mut HashMap<IpAddr, usize>, host: &IpAddr) {
+    if let Some(count) = pending.get_mut(host) {
+        *count -= 1;
+        if *count == 0 {
+            pending.remove(host);
+        }
+    }
 }
 
 fn should_stash<T>(ctrl: &JobCtrl<T>) -> bool {
     match ctrl {
         JobCtrl::Error(JobErr::Errno(i)) => is_resource_blocked(*i),
+        JobCtrl::Error(JobErr::Transient(_)) => true,
         _ => false
     }
 }
@@ -3,12 +3,12 @@ use std::{
     time::Duration,
 };
 
-use tokio_util::time::DelayQueue;
+use tokio_util::time::{DelayQueue, delay_queue::Key};
 
 use tokio_stream::StreamExt;
 
 pub struct Stash<T> {
-    stash: HashMap<usize, T>,
+    stash: HashMap<usize, (T, Key)>,
     timer: DelayQueue<usize>,
 }
 
@@ -23,9 +23,6 @@ impl<T> Stash<T> {
 
     #[inline]
     pub fn insert(&mut self, state: T, delay_for: &Duration) {
-        // ignoring key bc we dont transverse `self.stash` to remove items from
-        // `self.timer`
-
         // todo(adam)
         // possible resource sink here
         let mut key: usize = rand::random();
@@ -34,8 +31,8 @@ impl<T> Stash<T> {
         }
         // --
 
-        let _key = self.timer.insert(key, *delay_for);
-        self.stash.insert(key, state);
+        let timer_key = self.timer.insert(key, *delay_for);
+        self.stash.insert(key, (state, timer_key));
 
     }
 
@@ -46,7 +43,7 @@ impl<T> Stash<T> {
         jobs.extend(
             self.stash
                 .drain()
-                .map(|(_id, state)| state)
+                .map(|(_id, (state, _key))| state)
         );
         
         self.timer.clear();
@@ -59,7 +56,7 @@ impl<T> Stash<T> {
     pub async fn release(&mut self, jobs: &mut Vec<T>) -> usize {
         let mut amount = 0;
         while let Some(Ok(res)) = self.timer.next().await {
-            if let Some(state) = self.stash.remove(res.get_ref()) {
+            if let Some((state, _key)) = self.stash.remove(res.get_ref()) {
                 jobs.push(state);
                 amount += 1;
             }
@@ -67,6 +64,23 @@ impl<T> Stash<T> {
         amount
     }
 
+    /// drops every stashed state `keep` returns false for,
+    /// returning the amount dropped
+    pub fn retain<F: FnMut(&T) -> bool>(&mut self, mut keep: F) -> usize {
+        let timer = &mut self.timer;
+        let before = self.stash.len();
+
+        self.stash.retain(|_id, (state, key)| {
+            if keep(state) {
+                return true
+            }
+            timer.remove(key);
+            false
+        });
+
+        before - self.stash.len()
+    }
+
     #[inline]
     pub fn amount(&self) -> usize {
         self.timer.len()
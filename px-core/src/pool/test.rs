@@ -42,9 +42,15 @@ pub mod noop {
         }
     }
 
-    #[derive(Debug, Default, Clone, Eq, PartialEq)]
+    #[derive(Debug, Default, Clone, Eq, PartialEq, Hash)]
     pub struct State;
 
+    impl crate::pool::Destination for State {
+        fn destination(&self) -> std::net::IpAddr {
+            std::net::Ipv4Addr::LOCALHOST.into()
+        }
+    }
+
     #[derive(Debug, Default, Clone, Eq, PartialEq)]
     pub struct Response;
 
@@ -234,3 +240,201 @@ fn pool_all_in_all_out() {
     assert_eq!(results.len(), 3);
 }
 
+
+use crate::pool::{Observer, JobErr};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Default)]
+struct Events {
+    started: usize,
+    results: usize,
+    stashed: usize,
+    retried: usize,
+    given_up: usize,
+    timed_out: usize,
+}
+
+struct Recorder(Arc<Mutex<Events>>);
+
+impl<R, S> Observer<R, S> for Recorder {
+    fn on_result(&mut self, _ctrl: &JobCtrl<R>, _state: &S) { self.0.lock().unwrap().results += 1 }
+    fn on_started(&mut self, _state: &S) { self.0.lock().unwrap().started += 1 }
+    fn on_stashed(&mut self, _ctrl: &JobCtrl<R>, _state: &S) { self.0.lock().unwrap().stashed += 1 }
+    fn on_retried(&mut self, _state: &S, _attempt: u8) { self.0.lock().unwrap().retried += 1 }
+    fn on_given_up(&mut self, _ctrl: &JobCtrl<R>, _state: &S) { self.0.lock().unwrap().given_up += 1 }
+    fn on_host_timeout(&mut self, _host: &std::net::IpAddr) { self.0.lock().unwrap().timed_out += 1 }
+}
+
+#[test]
+fn pool_observer_receives_results() {
+    let rt = Runtime::new().unwrap();
+    let events = Arc::new(Mutex::new(Events::default()));
+    
+    let worker: noop::NopWorker = Worker::new(Boundary::Unlimited, std::time::Duration::from_secs(5));
+    let mut pool = Pool::new(worker);
+    pool.observe(Box::new(Recorder(events.clone())));
+
+    let mut buf = vec![noop::State; 3];
+    rt.block_on(pool.run_until_idle(&mut buf));
+
+    let events = events.lock().unwrap();
+    assert_eq!(events.started, 3);
+    assert_eq!(events.results, 3);
+    assert_eq!(events.stashed, 0);
+}
+
+#[test]
+fn pool_observer_retries_then_gives_up() {
+    use noop as mock;
+
+    #[derive(Debug)]
+    struct Unreachable;
+
+    #[async_trait::async_trait]
+    impl CRON for Unreachable {
+        type State = mock::State;
+        type Response = mock::Response;
+
+        async fn exec(_state: &mut Self::State) -> Result<JobCtrl<Self::Response>, Error> {
+            // network unreachable
+            Ok(JobCtrl::Error(JobErr::Errno(101)))
+        }
+    }
+
+    let rt = Runtime::new().unwrap();
+    let events = Arc::new(Mutex::new(Events::default()));
+
+    let worker: Worker<Unreachable, mock::Response, mock::State> = Worker::new(
+        Boundary::Unlimited,
+        std::time::Duration::from_secs(5)
+    );
+    let mut pool = Pool::new(worker);
+    pool.set_max_retries(Some(1));
+    pool.observe(Box::new(Recorder(events.clone())));
+
+    // equal states are still separate jobs, each with its own count
+    let mut buf = vec![mock::State; 2];
+    rt.block_on(pool.run_until_idle(&mut buf));
+
+    let events = events.lock().unwrap();
+    assert_eq!(events.started, 4);
+    assert_eq!(events.stashed, 2);
+    assert_eq!(events.retried, 2);
+    assert_eq!(events.given_up, 2);
+    // given up jobs are still reported
+    assert_eq!(events.results, 2);
+}
+
+#[test]
+fn pool_flushed_stash_keeps_retry_count() {
+    use noop as mock;
+
+    #[derive(Debug)]
+    struct Unreachable;
+
+    #[async_trait::async_trait]
+    impl CRON for Unreachable {
+        type State = mock::State;
+        type Response = mock::Response;
+
+        async fn exec(_state: &mut Self::State) -> Result<JobCtrl<Self::Response>, Error> {
+            // network unreachable
+            Ok(JobCtrl::Error(JobErr::Errno(101)))
+        }
+    }
+
+    let rt = Runtime::new().unwrap();
+    let events = Arc::new(Mutex::new(Events::default()));
+
+    let worker: Worker<Unreachable, mock::Response, mock::State> = Worker::new(
+        Boundary::Unlimited,
+        std::time::Duration::from_secs(5)
+    );
+    let mut pool = Pool::new(worker);
+    pool.set_max_retries(Some(2));
+    pool.observe(Box::new(Recorder(events.clone())));
+
+    let results = rt.block_on(async move {
+        let mut buf = vec![mock::State];
+        let mut results = Vec::new();
+        // how the feeder drives the pool, the stash is flushed before every spawn
+        loop {
+            let idle = buf.is_empty() && pool.is_idle();
+            pool.flush_stash();
+            pool.spawn(&mut buf);
+            results.extend(pool.tick(&mut Vec::new()).await);
+
+            if idle && pool.is_idle() {
+                break
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        results
+    });
+
+    let events = events.lock().unwrap();
+    assert_eq!(events.started, 3);
+    assert_eq!(events.stashed, 2);
+    assert_eq!(events.retried, 2);
+    assert_eq!(events.given_up, 1);
+    assert_eq!(results.len(), 1);
+}
+
+#[test]
+fn pool_abandons_host_over_budget() {
+    use noop as mock;
+    use std::net::{IpAddr, SocketAddr};
+
+    #[derive(Debug)]
+    struct Tarpit;
+
+    #[async_trait::async_trait]
+    impl CRON for Tarpit {
+        type State = SocketAddr;
+        type Response = mock::Response;
+
+        async fn exec(_state: &mut Self::State) -> Result<JobCtrl<Self::Response>, Error> {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            Ok(JobCtrl::Return(NetState::Filtered, mock::Response))
+        }
+    }
+
+    let rt = Runtime::new().unwrap();
+    let events = Arc::new(Mutex::new(Events::default()));
+
+    // one job at a time
+    let worker: Worker<Tarpit, mock::Response, SocketAddr> = Worker::new(
+        Boundary::Limited(2),
+        std::time::Duration::from_secs(5)
+    );
+    let mut pool = Pool::new(worker);
+    pool.set_host_timeout(Some(Duration::from_millis(500)));
+    pool.observe(Box::new(Recorder(events.clone())));
+
+    let tarpit: IpAddr = "127.0.0.1".parse().unwrap();
+    let quick: IpAddr = "127.0.0.2".parse().unwrap();
+
+    let mut buf: Vec<SocketAddr> = (1..=5).map(|port| SocketAddr::new(tarpit, port)).collect();
+    buf.push(SocketAddr::new(quick, 1));
+
+    let results = rt.block_on(async move {
+        let mut results = Vec::new();
+        loop {
+            // same as `run_until_idle`, one last tick once idle
+            let idle = buf.len() == 0 && pool.is_idle();
+            results.extend(pool.tick(&mut buf).await);
+
+            if idle && buf.len() == 0 && pool.is_idle() {
+                break
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        (results, pool.take_timed_out())
+    });
+
+    let (results, timed_out) = results;
+    assert_eq!(timed_out, vec![tarpit]);
+    assert!(results.iter().filter(|(_, state)| state.ip() == tarpit).count() < 5);
+    assert_eq!(results.iter().filter(|(_, state)| state.ip() == quick).count(), 1);
+    assert_eq!(events.lock().unwrap().timed_out, 1);
+}
@@ -52,6 +52,12 @@ pub enum JobErr {
     IO(std::io::ErrorKind),
     Errno(i32),
     TaskFailed,
+    /// nothing was learnt about the target, and no other job can succeed either,
+    /// a broken pivot chain
+    Fatal(String),
+    /// nothing was learnt about the target, the job may go through if retried,
+    /// a pivot too busy to answer
+    Transient(String),
     Other
 }
 
@@ -73,8 +79,9 @@ where
 	R: Send + Sync + Clone,
     S: Send + Sync + Clone,
 {
-    tx: Arc<Mutex<evc::WriteHandle<EVec<(JobCtrl<R>, S)>>>>,
-    rx: evc::ReadHandle<EVec<(JobCtrl<R>, S)>>,
+    // each result is tagged with the number of times its job was retried
+    tx: Arc<Mutex<evc::WriteHandle<EVec<(JobCtrl<R>, S, u8)>>>>,
+    rx: evc::ReadHandle<EVec<(JobCtrl<R>, S, u8)>>,
 
     ttl: std::time::Duration, // time to live for each job
     
@@ -143,7 +150,22 @@ where
 
         if spawn_limit > 0 {
             for state in buf.drain(..spawn_limit) {
-                spawn_worker::<J, R, S>(self.tx.clone(), state, self.ttl)
+                spawn_worker::<J, R, S>(self.tx.clone(), state, 0, self.ttl)
+            }
+        }
+
+        spawn_limit
+    }
+
+    /// same as `spawn`, but each state carries the amount of times it was retried,
+    /// handed back along with its result by `take_finished`
+    pub(crate) fn spawn_retries(&mut self, buf: &mut Vec<(S, u8)>) -> usize
+    {
+        let spawn_limit = self.calc_new_spawns(buf.len());
+
+        if spawn_limit > 0 {
+            for (state, attempt) in buf.drain(..spawn_limit) {
+                spawn_worker::<J, R, S>(self.tx.clone(), state, attempt, self.ttl)
             }
         }
 
@@ -159,7 +181,7 @@ where
         buffer.extend(self.rx.read().0.clone());
         lock.write(Operation::Clear);
         lock.refresh();
-        buffer
+        untag(buffer)
     }
 
     /// wait until all work is done before attempting to flush `rx` and `tx`
@@ -183,33 +205,47 @@ where
     type Item = Vec<(JobCtrl<R>, S)>;
     
     fn poll_next(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        // whenever pre-emptive futures come out
+        // tokio::coop::proceed().await; 
+        Poll::Ready(self.take_finished().map(untag))
+    }
+}
+
+impl<J, R, S> Worker<J, R, S>
+where
+	J: CRON<Response = R, State = S>,
+	R: Send + Sync + Clone + 'static,
+    S: Send + Sync + Clone + 'static
+{
+    /// takes every finished job, along with the amount of times it was retried
+    pub(crate) fn take_finished(&self) -> Option<Vec<(JobCtrl<R>, S, u8)>> {
         let mut lock = self.tx.lock().unwrap();
         lock.refresh();
         let jobs = &self.rx.read().0;
-        
-        let poll;
 
-        if jobs.len() > 0 {
+        if !jobs.is_empty() {
             // this is safe because operations
             // aren't reflected until `lock.refresh()`
             // is executed
             lock.write(Operation::Clear);
-            poll = Poll::Ready(Some(jobs.clone()));
+            return Some(jobs.clone())
         }
-        else {
-            poll = Poll::Ready(None);
-        }
-        
-        // whenever pre-emptive futures come out
-        // tokio::coop::proceed().await; 
-        return poll;
+
+        None
     }
 }
 
+fn untag<R, S>(jobs: Vec<(JobCtrl<R>, S, u8)>) -> Vec<(JobCtrl<R>, S)> {
+    jobs.into_iter()
+        .map(|(ctrl, state, _attempt)| (ctrl, state))
+        .collect()
+}
+
 
 fn spawn_worker<J, R, S>(
-    vtx: Arc<Mutex<evc::WriteHandle<EVec<(JobCtrl<R>, S)>>>>,
+    vtx: Arc<Mutex<evc::WriteHandle<EVec<(JobCtrl<R>, S, u8)>>>>,
     mut state: S,
+    attempt: u8,
     ttl: std::time::Duration
 ) where 
     J: CRON<Response=R, State=S>,
@@ -228,6 +264,6 @@ fn spawn_worker<J, R, S>(
         };
 
         let mut lock = vtx.lock().unwrap();
-        lock.write(Operation::Push((sig, state)));
+        lock.write(Operation::Push((sig, state, attempt)));
     });
 }
@@ -0,0 +1,145 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::error::Error;
+
+/// Furthest we'll look ahead for a matching minute before giving up,
+/// (leap year + a day)
+const SEARCH_MINUTES: u64 = 367 * 24 * 60;
+
+/// A standard 5 field cron expression, evaluated in UTC
+/// `minute hour day-of-month month day-of-week`
+///
+/// Every field accepts `*`, `a`, `a-b`, and steps `*/n` or `a-b/n`, as comma separated lists.
+/// Day of week is 0-7, where both 0 and 7 are sunday.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronExpr {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+
+    // day of month/week starting with "*" ("*", "*/2"),
+    // when neither does, matching either one is enough, like cron
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl std::str::FromStr for CronExpr {
+    type Err = Error;
+
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = src.split_whitespace().collect();
+
+        if fields.len() != 5 {
+            return Err(Error::ParseError(format!("expected 5 fields in cron expression, got {}", fields.len())))
+        }
+
+        let mut weekdays = parse_field(fields[4], 0, 7)?;
+        // sunday is both 0 and 7
+        if weekdays & (1 << 7) != 0 {
+            weekdays |= 1;
+        }
+
+        Ok(Self {
+            minutes: parse_field(fields[0], 0, 59)?,
+            hours: parse_field(fields[1], 0, 23)?,
+            days: parse_field(fields[2], 1, 31)?,
+            months: parse_field(fields[3], 1, 12)?,
+            weekdays,
+            any_day: fields[2].starts_with('*'),
+            any_weekday: fields[4].starts_with('*'),
+        })
+    }
+}
+
+impl CronExpr {
+    /// The first time after `after` matching the expression,
+    /// `None` if nothing matches within a year (`0 0 31 2 *`)
+    pub fn next_after(&self, after: SystemTime) -> Option<SystemTime> {
+        let secs = after.duration_since(UNIX_EPOCH).ok()?.as_secs();
+        let first = secs / 60 + 1;
+
+        (first..first + SEARCH_MINUTES)
+            .find(|minute| self.matches(*minute))
+            .map(|minute| UNIX_EPOCH + Duration::from_secs(minute * 60))
+    }
+
+    /// `minute` being minutes since the unix epoch
+    fn matches(&self, minute: u64) -> bool {
+        let days = minute / (24 * 60);
+        let (_year, month, day) = civil_from_days(days);
+        // 1970-01-01 was a thursday
+        let weekday = (days + 4) % 7;
+
+        let (day, weekday) = (has(self.days, day), has(self.weekdays, weekday));
+        let day_matches = if self.any_day || self.any_weekday { day && weekday }
+            else { day || weekday };
+
+        has(self.minutes, minute % 60)
+            && has(self.hours, (minute / 60) % 24)
+            && has(self.months, month)
+            && day_matches
+    }
+}
+
+#[inline]
+fn has(set: u64, value: u64) -> bool {
+    set & (1 << value) != 0
+}
+
+/// parses a single field into a bitset of the values it allows
+fn parse_field(field: &str, min: u64, max: u64) -> Result<u64, Error> {
+    let mut set = 0;
+
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u64>()?),
+            None => (part, 1)
+        };
+
+        if step == 0 {
+            return Err(Error::ParseError(format!("step of 0 in cron field '{}'", field)))
+        }
+
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (start.parse::<u64>()?, end.parse::<u64>()?),
+                // "5/15" runs from 5 until the end of the range
+                None if step > 1 => (range.parse::<u64>()?, max),
+                None => {
+                    let value = range.parse::<u64>()?;
+                    (value, value)
+                }
+            }
+        };
+
+        if start < min || end > max || start > end {
+            return Err(Error::ParseError(format!("'{}' out of range {}-{}", part, min, max)))
+        }
+
+        for value in (start..=end).step_by(step as usize) {
+            set |= 1 << value;
+        }
+    }
+
+    Ok(set)
+}
+
+/// days since the unix epoch into (year, month, day)
+/// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
@@ -0,0 +1,145 @@
+// Runs the same scan over and over,
+// comparing each result set against the one before it
+use std::time::{Duration, SystemTime};
+
+use crate::error::Error;
+
+mod cron;
+mod snapshot;
+
+pub use cron::CronExpr;
+pub use snapshot::{Snapshot, Observation, Change};
+
+#[cfg(test)]
+#[cfg(feature="include-tests")]
+pub mod test;
+
+/// When a scan is ran
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Schedule {
+    /// fixed delay between the start of each run
+    Interval(Duration),
+    Cron(CronExpr),
+}
+
+impl std::str::FromStr for Schedule {
+    type Err = Error;
+
+    /// Intervals are a number with a unit, "90s", "15m", "6h", "1d",
+    /// anything else is read as a cron expression "0 */6 * * *"
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        let src = src.trim();
+        let unit = match src.chars().last() {
+            Some('s') => 1,
+            Some('m') => 60,
+            Some('h') => 60 * 60,
+            Some('d') => 24 * 60 * 60,
+            _ => return Ok(Schedule::Cron(src.parse()?))
+        };
+
+        match src[..src.len()-1].parse::<u64>() {
+            Ok(0) => Err(Error::ParseError("interval must be greater than 0".to_string())),
+            Ok(n) => Ok(Schedule::Interval(Duration::from_secs(n * unit))),
+            Err(_) => Ok(Schedule::Cron(src.parse()?))
+        }
+    }
+}
+
+impl Schedule {
+    /// Time until the next run.
+    /// `last_run` is when the previous run started, if there was one
+    pub fn until_next(&self, last_run: Option<SystemTime>, now: SystemTime) -> Option<Duration> {
+        match self {
+            Schedule::Interval(every) => Some(match last_run {
+                Some(last) => (last + *every).duration_since(now).unwrap_or_default(),
+                None => Duration::from_secs(0)
+            }),
+
+            Schedule::Cron(expr) => expr.next_after(now)
+                .map(|next| next.duration_since(now).unwrap_or_default())
+        }
+    }
+}
+
+/// Receives every change found between two runs
+pub trait ChangeSink: Send {
+    /// `run` counts from 0, the first run is only used as a baseline
+    fn on_change(&mut self, run: usize, change: &Change);
+}
+
+/// Keeps the last result set, and hands the differences of each new one to its sinks.
+///
+/// Callers drive the loop themselves
+/// ```ignore
+/// loop {
+///     scheduler.wait().await?;
+///     let snapshot = scan().await;
+///     scheduler.update(snapshot);
+/// }
+/// ```
+pub struct Scheduler {
+    schedule: Schedule,
+    sinks: Vec<Box<dyn ChangeSink>>,
+
+    previous: Option<Snapshot>,
+    last_run: Option<SystemTime>,
+    runs: usize,
+}
+
+impl Scheduler {
+    pub fn new(schedule: Schedule) -> Self {
+        Self {
+            schedule,
+            sinks: Vec::new(),
+            previous: None,
+            last_run: None,
+            runs: 0,
+        }
+    }
+
+    /// Registers `sink` to receive every change from here on
+    pub fn add_sink(&mut self, sink: Box<dyn ChangeSink>) {
+        self.sinks.push(sink);
+    }
+
+    #[inline]
+    pub fn runs(&self) -> usize {
+        self.runs
+    }
+
+    #[inline]
+    pub fn previous(&self) -> Option<&Snapshot> {
+        self.previous.as_ref()
+    }
+
+    /// Sleeps until the next run is due, and marks it as started.
+    /// Fails when the schedule never fires again
+    pub async fn wait(&mut self) -> Result<(), Error> {
+        let delay = self.schedule.until_next(self.last_run, SystemTime::now())
+            .ok_or(Error::ScheduleExhausted)?;
+
+        tokio::time::sleep(delay).await;
+        self.last_run = Some(SystemTime::now());
+        Ok(())
+    }
+
+    /// Stores the result set of the run that just finished,
+    /// returning what changed since the previous one.
+    /// Nothing is reported for the first run
+    pub fn update(&mut self, snapshot: Snapshot) -> Vec<Change> {
+        let changes = match &self.previous {
+            Some(previous) => previous.diff(&snapshot),
+            None => Vec::new()
+        };
+
+        for change in &changes {
+            for sink in self.sinks.iter_mut() {
+                sink.on_change(self.runs, change);
+            }
+        }
+
+        self.previous = Some(snapshot);
+        self.runs += 1;
+        changes
+    }
+}
@@ -0,0 +1,116 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+};
+
+use serde::{Serialize, Deserialize};
+
+use crate::model::State;
+
+/// What was seen on a single port during a run
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Observation {
+    pub state: State,
+    /// service/version, when the scan identifies it
+    pub service: Option<String>,
+}
+
+/// The result set of a single run
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub ports: HashMap<SocketAddr, Observation>,
+}
+
+impl Snapshot {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, addr: SocketAddr, state: State, service: Option<String>) {
+        self.ports.insert(addr, Observation { state, service });
+    }
+
+    #[inline]
+    pub fn is_open(&self, addr: &SocketAddr) -> bool {
+        self.ports.get(addr)
+            .map(|seen| seen.state == State::Open)
+            .unwrap_or(false)
+    }
+
+    /// Everything that changed going from `self` to `next`
+    pub fn diff(&self, next: &Snapshot) -> Vec<Change> {
+        let mut changes = Vec::new();
+
+        for (addr, seen) in &next.ports {
+            if seen.state != State::Open {
+                continue
+            }
+
+            match self.ports.get(addr) {
+                Some(before) if before.state == State::Open => {
+                    if before.service != seen.service {
+                        changes.push(Change::ServiceChanged {
+                            addr: *addr,
+                            before: before.service.clone(),
+                            after: seen.service.clone(),
+                        });
+                    }
+                }
+                _ => changes.push(Change::Opened {
+                    addr: *addr,
+                    service: seen.service.clone(),
+                })
+            }
+        }
+
+        for (addr, before) in &self.ports {
+            if before.state == State::Open && !next.is_open(addr) {
+                changes.push(Change::Closed {
+                    addr: *addr,
+                    // not being scanned at all reads as closed
+                    state: next.ports.get(addr).map(|seen| seen.state).unwrap_or(State::Closed),
+                });
+            }
+        }
+
+        changes.sort_by_key(|change| change.addr());
+        changes
+    }
+}
+
+/// A difference between two consecutive runs
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum Change {
+    /// port wasn't open during the previous run
+    Opened { addr: SocketAddr, service: Option<String> },
+    /// port was open during the previous run, `state` is what it is now
+    Closed { addr: SocketAddr, state: State },
+    /// port stayed open, but answers as something else
+    ServiceChanged { addr: SocketAddr, before: Option<String>, after: Option<String> },
+}
+
+impl Change {
+    pub fn addr(&self) -> SocketAddr {
+        match self {
+            Change::Opened { addr, .. }
+            | Change::Closed { addr, .. }
+            | Change::ServiceChanged { addr, .. } => *addr
+        }
+    }
+}
+
+impl std::fmt::Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn service(x: &Option<String>) -> &str {
+            x.as_deref().unwrap_or("unknown")
+        }
+
+        match self {
+            Change::Opened { addr, service: svc } => write!(f, "+ {}\topen\t{}", addr, service(svc)),
+            Change::Closed { addr, state } => write!(f, "- {}\t{}", addr, state),
+            Change::ServiceChanged { addr, before, after } =>
+                write!(f, "~ {}\t{} -> {}", addr, service(before), service(after)),
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use super::*;
+use crate::model::State;
+
+fn at(secs: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+fn addr(port: u16) -> SocketAddr {
+    SocketAddr::new("10.0.0.1".parse().unwrap(), port)
+}
+
+#[test]
+fn parse_schedule() {
+    assert_eq!("90s".parse::<Schedule>().unwrap(), Schedule::Interval(Duration::from_secs(90)));
+    assert_eq!("6h".parse::<Schedule>().unwrap(), Schedule::Interval(Duration::from_secs(6 * 3600)));
+    assert!(matches!("*/15 * * * *".parse::<Schedule>().unwrap(), Schedule::Cron(_)));
+
+    assert!("0s".parse::<Schedule>().is_err());
+    assert!("* * *".parse::<Schedule>().is_err());
+    assert!("61 * * * *".parse::<Schedule>().is_err());
+    assert!("*/0 * * * *".parse::<Schedule>().is_err());
+}
+
+#[test]
+fn cron_next_after() {
+    // 2021-03-14 15:09:26 UTC, a sunday
+    let now = at(1615734566);
+
+    let every_15: CronExpr = "*/15 * * * *".parse().unwrap();
+    // 15:15
+    assert_eq!(every_15.next_after(now), Some(at(1615734900)));
+
+    let daily: CronExpr = "30 2 * * *".parse().unwrap();
+    // 2021-03-15 02:30
+    assert_eq!(daily.next_after(now), Some(at(1615775400)));
+
+    let mondays: CronExpr = "0 9 * * 1".parse().unwrap();
+    // 2021-03-15 09:00
+    assert_eq!(mondays.next_after(now), Some(at(1615798800)));
+
+    let sundays: CronExpr = "0 18 * * 7".parse().unwrap();
+    // 2021-03-14 18:00, 7 is sunday too
+    assert_eq!(sundays.next_after(now), Some(at(1615744800)));
+
+    let first_of_month: CronExpr = "0 0 1 * *".parse().unwrap();
+    // 2021-04-01 00:00
+    assert_eq!(first_of_month.next_after(now), Some(at(1617235200)));
+
+    let never: CronExpr = "0 0 31 2 *".parse().unwrap();
+    assert_eq!(never.next_after(now), None);
+
+    // day of month and day of week both restricted, either one will do
+    let friday_or_13th: CronExpr = "0 0 13 * 5".parse().unwrap();
+    // 2021-03-19 00:00, a friday
+    assert_eq!(friday_or_13th.next_after(now), Some(at(1616112000)));
+
+    // "*/2" restricts the day of month, but like "*" it needs the day of week to match too
+    let odd_tuesdays: CronExpr = "0 0 */2 * 2".parse().unwrap();
+    // 2021-03-23 00:00, the 16th is a tuesday but even
+    assert_eq!(odd_tuesdays.next_after(now), Some(at(1616457600)));
+}
+
+#[test]
+fn interval_until_next() {
+    let every = Schedule::Interval(Duration::from_secs(60));
+
+    assert_eq!(every.until_next(None, at(1000)), Some(Duration::from_secs(0)));
+    assert_eq!(every.until_next(Some(at(1000)), at(1020)), Some(Duration::from_secs(40)));
+    // overran, go right away
+    assert_eq!(every.until_next(Some(at(1000)), at(1100)), Some(Duration::from_secs(0)));
+}
+
+#[test]
+fn snapshot_diff() {
+    let mut before = Snapshot::new();
+    before.record(addr(22), State::Open, Some("ssh OpenSSH 7.4".to_string()));
+    before.record(addr(80), State::Open, None);
+    before.record(addr(443), State::Closed, None);
+    before.record(addr(8080), State::Open, None);
+
+    let mut after = Snapshot::new();
+    after.record(addr(22), State::Open, Some("ssh OpenSSH 8.9".to_string()));
+    after.record(addr(80), State::Open, None);
+    after.record(addr(443), State::Open, None);
+    after.record(addr(8080), State::Filtered, None);
+
+    assert_eq!(before.diff(&after), vec![
+        Change::ServiceChanged {
+            addr: addr(22),
+            before: Some("ssh OpenSSH 7.4".to_string()),
+            after: Some("ssh OpenSSH 8.9".to_string())
+        },
+        Change::Opened { addr: addr(443), service: None },
+        Change::Closed { addr: addr(8080), state: State::Filtered },
+    ]);
+
+    // missing entirely counts as closed
+    assert_eq!(before.diff(&Snapshot::new()).len(), 3);
+    assert!(after.diff(&after).is_empty());
+}
+
+struct Collect(Arc<Mutex<Vec<(usize, Change)>>>);
+
+impl ChangeSink for Collect {
+    fn on_change(&mut self, run: usize, change: &Change) {
+        self.0.lock().unwrap().push((run, change.clone()));
+    }
+}
+
+#[test]
+fn scheduler_emits_changes_between_runs() {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let seen = Arc::new(Mutex::new(Vec::new()));
+
+    let mut scheduler = Scheduler::new(Schedule::Interval(Duration::from_millis(50)));
+    scheduler.add_sink(Box::new(Collect(seen.clone())));
+
+    let mut runs = vec![Snapshot::new(), Snapshot::new()];
+    runs[0].record(addr(80), State::Open, None);
+    runs[1].record(addr(80), State::Open, None);
+    runs[1].record(addr(81), State::Open, None);
+
+    let started = std::time::Instant::now();
+    rt.block_on(async {
+        for snapshot in runs {
+            scheduler.wait().await.unwrap();
+            scheduler.update(snapshot);
+        }
+    });
+
+    // the first run fires immediately, the second waits out the interval
+    assert!(started.elapsed() >= Duration::from_millis(50));
+    assert_eq!(scheduler.runs(), 2);
+    assert_eq!(*seen.lock().unwrap(), vec![
+        (1, Change::Opened { addr: addr(81), service: None })
+    ]);
+}
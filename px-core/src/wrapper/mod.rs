@@ -0,0 +1,155 @@
+// noticing we're only using connections we're going to attempt to make a more generic worker
+//
+// Protocol probes (socks5, http, ssh, ...) are written against `NetworkInterface`
+// instead of a concrete socket, so the same probe runs over tcp, udp, or tls.
+use std::{
+    net::SocketAddr,
+    time::Duration
+};
+
+use crate::{
+    model::State,
+    error::Error
+};
+
+mod tcp;
+mod udp;
+mod tls;
+mod starttls;
+pub mod pivot;
+
+pub use tcp::TcpInterface;
+pub use udp::UdpInterface;
+pub use tls::{TlsInterface, insecure_config};
+pub use starttls::{Tls, StartTls, StartTlsProtocol, Reply, CodedSession, Exchange, read_reply, read_coded_reply, coded_reply_done};
+
+#[cfg(test)]
+#[cfg(feature="include-tests")]
+pub mod test;
+
+/// Default time given to every read and write on an interface
+pub const DEFAULT_TTL: Duration = Duration::from_secs(5);
+
+/// A connected transport.
+/// Every read and write is bounded by the interface's timeout,
+/// and returns `std::io::ErrorKind::TimedOut` once it expires.
+#[async_trait::async_trait]
+pub trait NetworkInterface: Send {
+    /// Reads at most `amount` bytes, appending them to the tail of `buf`.
+    /// Returns the amount of bytes read, 0 when the peer closed the connection.
+    async fn read_iface(&mut self, buf: &mut Vec<u8>, amount: usize) -> Result<usize, std::io::Error>;
+    async fn write_iface(&mut self, buf: &[u8]) -> Result<usize, std::io::Error>;
+
+    fn peer(&self) -> SocketAddr;
+    fn state(&self) -> State;
+
+    fn set_timeout(&mut self, ttl: Duration);
+
+    /// Reads exactly `amount` bytes into the tail of `buf`,
+    /// fails with `UnexpectedEof` if the peer closes before then
+    async fn read_exact_iface(&mut self, buf: &mut Vec<u8>, amount: usize) -> Result<(), std::io::Error> {
+        let start = buf.len();
+
+        while buf.len() - start < amount {
+            let n = self.read_iface(buf, amount - (buf.len() - start)).await?;
+            if n == 0 {
+                return Err(std::io::ErrorKind::UnexpectedEof.into())
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads into `buf` until `delimiter` shows up, the peer closes,
+    /// or `max` bytes have been read.
+    /// Returns the amount of bytes read,
+    /// an empty `delimiter` is `InvalidInput` and nothing is read.
+    async fn read_until(&mut self, buf: &mut Vec<u8>, delimiter: &[u8], max: usize) -> Result<usize, std::io::Error> {
+        if delimiter.is_empty() {
+            return Err(std::io::ErrorKind::InvalidInput.into())
+        }
+
+        let start = buf.len();
+
+        while buf.len() - start < max {
+            let n = self.read_iface(buf, max - (buf.len() - start)).await?;
+            if n == 0 {
+                break
+            }
+
+            let searched = &buf[start..];
+            if searched.windows(delimiter.len()).any(|window| window == delimiter) {
+                break
+            }
+        }
+
+        Ok(buf.len() - start)
+    }
+}
+
+/// Identifies/extracts `T` out of a service.
+/// `T` being probe data, `Probe<Minecraft>`, `Probe<Ssh>`
+#[async_trait::async_trait]
+pub trait Probe<T> {
+    async fn probe_service<I: NetworkInterface>(&mut self, iface: &mut I) -> Option<T>;
+}
+
+/// Applies operations (commands, queries) over an established interface,
+/// like `CodedSession` sending FTP/SMTP commands and reading their replies.
+#[async_trait::async_trait]
+pub trait Interact {
+    type Operation: Send;
+    type Output;
+
+    async fn apply_operation<I: NetworkInterface>(&mut self, iface: &mut I, op: Self::Operation) -> Result<Self::Output, Error>;
+}
+
+/// Turns an interface into another interface,
+/// like wrapping tcp in tls, or negotiating STARTTLS first.
+#[async_trait::async_trait]
+pub trait UpgradeConnection<I: NetworkInterface> {
+    type Upgraded: NetworkInterface;
+
+    async fn negotiate_upgrade(&self, iface: I) -> Result<Self::Upgraded, Error>;
+}
+
+/// Glue over `Probe` and `Interact`, implemented for everything.
+#[async_trait::async_trait]
+pub trait Protocol: Send {
+    async fn probe<T, I>(&mut self, iface: &mut I) -> Option<T>
+    where
+        Self: Probe<T>,
+        I: NetworkInterface
+    {
+        self.probe_service(iface).await
+    }
+
+    async fn interact<I>(&mut self, iface: &mut I, op: <Self as Interact>::Operation) -> Result<<Self as Interact>::Output, Error>
+    where
+        Self: Interact,
+        I: NetworkInterface
+    {
+        self.apply_operation(iface, op).await
+    }
+
+    /// Upgrades `iface` using `upgrade`, before handing it back to be probed/interacted with
+    async fn upgrade<I, U>(&mut self, iface: I, upgrade: &U) -> Result<U::Upgraded, Error>
+    where
+        I: NetworkInterface + 'async_trait,
+        U: UpgradeConnection<I> + Sync
+    {
+        upgrade.negotiate_upgrade(iface).await
+    }
+}
+
+impl<P: Send> Protocol for P {}
+
+/// maps the io error of an interface into the state of the port
+pub fn io_state(err: &std::io::Error) -> State {
+    match err.kind() {
+        std::io::ErrorKind::ConnectionRefused
+        | std::io::ErrorKind::ConnectionReset
+        | std::io::ErrorKind::ConnectionAborted => State::Closed,
+        _ => State::Filtered
+    }
+}
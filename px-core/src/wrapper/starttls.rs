@@ -0,0 +1,498 @@
+use std::sync::Arc;
+
+use tokio_rustls::rustls::ClientConfig;
+
+use crate::error::Error;
+use super::{
+    NetworkInterface,
+    Interact,
+    Protocol,
+    UpgradeConnection,
+    TcpInterface,
+    TlsInterface,
+    insecure_config
+};
+
+/// Largest reply we'll buffer while negotiating
+const MAX_REPLY: usize = 16384;
+
+/// Wraps a connected tcp interface in tls
+#[derive(Clone)]
+pub struct Tls {
+    /// sent as SNI when set
+    pub server_name: Option<String>,
+    pub config: Arc<ClientConfig>,
+}
+
+impl Tls {
+    pub fn new(server_name: Option<String>) -> Self {
+        Self {
+            server_name,
+            config: Arc::new(insecure_config()),
+        }
+    }
+}
+
+impl Default for Tls {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+impl std::fmt::Debug for Tls {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Tls")
+            .field("server_name", &self.server_name)
+            .finish()
+    }
+}
+
+#[async_trait::async_trait]
+impl UpgradeConnection<TcpInterface> for Tls {
+    type Upgraded = TlsInterface;
+
+    async fn negotiate_upgrade(&self, iface: TcpInterface) -> Result<TlsInterface, Error> {
+        let peer = iface.peer();
+        let ttl = iface.ttl();
+
+        Ok(TlsInterface::handshake(
+            iface.into_inner(),
+            peer,
+            self.server_name.as_deref(),
+            self.config.clone(),
+            ttl
+        ).await?)
+    }
+}
+
+/// Plaintext protocols that can ask to switch over to tls mid-session
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StartTlsProtocol {
+    Smtp,
+    Imap,
+    Pop3,
+    Ftp,
+    Ldap,
+    Xmpp,
+}
+
+impl std::str::FromStr for StartTlsProtocol {
+    type Err = Error;
+
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        Ok(match src.to_lowercase().as_str() {
+            "smtp" => StartTlsProtocol::Smtp,
+            "imap" => StartTlsProtocol::Imap,
+            "pop3" => StartTlsProtocol::Pop3,
+            "ftp" => StartTlsProtocol::Ftp,
+            "ldap" => StartTlsProtocol::Ldap,
+            "xmpp" => StartTlsProtocol::Xmpp,
+            x => return Err(Error::ParseError(format!("unknown STARTTLS protocol '{}'", x)))
+        })
+    }
+}
+
+impl StartTlsProtocol {
+    /// Guess by well known port
+    pub fn from_port(port: u16) -> Option<Self> {
+        Some(match port {
+            25 | 587 | 2525 => StartTlsProtocol::Smtp,
+            143 => StartTlsProtocol::Imap,
+            110 => StartTlsProtocol::Pop3,
+            21 => StartTlsProtocol::Ftp,
+            389 => StartTlsProtocol::Ldap,
+            5222 | 5269 => StartTlsProtocol::Xmpp,
+            _ => return None
+        })
+    }
+}
+
+/// Negotiates STARTTLS in plaintext, and then completes a tls handshake
+#[derive(Debug, Clone)]
+pub struct StartTls {
+    pub protocol: StartTlsProtocol,
+    pub tls: Tls,
+}
+
+impl StartTls {
+    pub fn new(protocol: StartTlsProtocol) -> Self {
+        Self {
+            protocol,
+            tls: Tls::default(),
+        }
+    }
+
+    /// Runs the plaintext half of the upgrade
+    pub async fn negotiate<I: NetworkInterface>(&self, iface: &mut I) -> Result<(), Error> {
+        match self.protocol {
+            StartTlsProtocol::Smtp => {
+                let mut session = CodedSession::default();
+                expect_code(session.interact(iface, Exchange::Read).await?, 220)?;
+                expect_code(session.interact(iface, Exchange::command("EHLO scurry")).await?, 250)?;
+                expect_code(session.interact(iface, Exchange::command("STARTTLS")).await?, 220)
+            }
+
+            StartTlsProtocol::Ftp => {
+                let mut session = CodedSession::default();
+                expect_code(session.interact(iface, Exchange::Read).await?, 220)?;
+                expect_code(session.interact(iface, Exchange::command("AUTH TLS")).await?, 234)
+            }
+
+            StartTlsProtocol::Imap => {
+                expect(read_reply(iface, |line| line.starts_with("* ")).await?, "* OK")?;
+                iface.write_iface(b"a001 STARTTLS\r\n").await?;
+                expect(read_reply(iface, |line| line.starts_with("a001 ")).await?, "a001 OK")
+            }
+
+            StartTlsProtocol::Pop3 => {
+                expect(read_reply(iface, pop3_reply_done).await?, "+OK")?;
+                iface.write_iface(b"STLS\r\n").await?;
+                expect(read_reply(iface, pop3_reply_done).await?, "+OK")
+            }
+
+            StartTlsProtocol::Ldap => {
+                iface.write_iface(&ldap_starttls_request()).await?;
+                let buf = read_ldap_message(iface).await?;
+
+                if ldap_extended_success(&buf) { Ok(()) }
+                else { Err(Error::UpgradeRefused(format!("ldap replied {:02X?}", buf))) }
+            }
+
+            StartTlsProtocol::Xmpp => {
+                let domain = match &self.tls.server_name {
+                    Some(name) => name.clone(),
+                    None => iface.peer().ip().to_string()
+                };
+
+                iface.write_iface(format!(
+                    "<?xml version='1.0'?><stream:stream to='{}' xmlns='jabber:client' \
+                    xmlns:stream='http://etherx.jabber.org/streams' version='1.0'>",
+                    domain
+                ).as_bytes()).await?;
+
+                let mut buf = Vec::new();
+                read_until_any(iface, &mut buf, &[b"</stream:features>", b"</stream:stream>"]).await?;
+
+                let features = String::from_utf8_lossy(&buf).to_string();
+                if !features.contains("<starttls") {
+                    return Err(Error::UpgradeRefused(features))
+                }
+
+                iface.write_iface(b"<starttls xmlns='urn:ietf:params:xml:ns:xmpp-tls'/>").await?;
+
+                buf.clear();
+                read_until_any(iface, &mut buf, &[b"/>", b"</failure>"]).await?;
+
+                let reply = String::from_utf8_lossy(&buf).to_string();
+                if reply.contains("<proceed") { Ok(()) }
+                else { Err(Error::UpgradeRefused(reply)) }
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl UpgradeConnection<TcpInterface> for StartTls {
+    type Upgraded = TlsInterface;
+
+    async fn negotiate_upgrade(&self, mut iface: TcpInterface) -> Result<TlsInterface, Error> {
+        self.negotiate(&mut iface).await?;
+        self.tls.negotiate_upgrade(iface).await
+    }
+}
+
+/// Reads lines until `done` returns true for one of them,
+/// returning everything read.
+pub async fn read_reply<I, F>(iface: &mut I, done: F) -> Result<String, Error>
+where
+    I: NetworkInterface,
+    F: Fn(&str) -> bool + Send
+{
+    let mut buf = Vec::new();
+
+    loop {
+        if iface.read_until(&mut buf, b"\n", MAX_REPLY).await? == 0 {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into())
+        }
+
+        let text = String::from_utf8_lossy(&buf);
+        // only look at lines that have been terminated
+        let finished = text.split_terminator('\n')
+            .take(text.matches('\n').count())
+            .any(|line| done(line.trim_end()));
+
+        if finished {
+            return Ok(text.to_string())
+        }
+
+        if buf.len() > MAX_REPLY {
+            return Err(Error::ParseError("reply too large".to_string()))
+        }
+    }
+}
+
+/// The last line of a multi-line reply in SMTP/FTP has a space after the code ("250 OK"),
+/// every line before it uses a dash ("250-STARTTLS")
+pub fn coded_reply_done(line: &str) -> bool {
+    let bytes = line.as_bytes();
+    bytes.len() >= 3
+        && bytes[..3].iter().all(u8::is_ascii_digit)
+        && bytes.get(3).map(|b| *b == b' ').unwrap_or(true)
+}
+
+/// A reply of a protocol with coded replies, FTP (RFC 959 4.2), SMTP (RFC 5321 4.2),
+/// continuation lines included
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reply {
+    pub code: u16,
+    pub lines: Vec<String>,
+}
+
+impl Reply {
+    /// First line's text, without its code
+    pub fn text(&self) -> &str {
+        self.lines.first()
+            .and_then(|line| line.get(4..))
+            .unwrap_or_default()
+    }
+
+    /// Every line's text, without its code
+    pub fn texts(&self) -> impl Iterator<Item = &str> {
+        self.lines.iter().map(|line| line.get(4..).unwrap_or_default())
+    }
+
+    /// Every line's text, codes stripped from the lines that have them
+    pub fn message(&self) -> String {
+        let prefixes = [format!("{}-", self.code), format!("{} ", self.code)];
+        self.lines.iter()
+            .map(|line| prefixes.iter().find_map(|x| line.strip_prefix(x.as_str())).unwrap_or(line).trim())
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    pub fn positive(&self) -> bool {
+        (200..300).contains(&self.code)
+    }
+}
+
+/// Reads the next coded reply. "123-" starts a multi-line one,
+/// only ended by a line with the same code followed by a space, "123 ".
+/// `buf` holds what was read past the reply, it has to be handed to the next call
+pub async fn read_coded_reply<I: NetworkInterface>(iface: &mut I, buf: &mut Vec<u8>) -> Result<Reply, Error> {
+    const CHUNK: usize = 1024;
+
+    let mut code = None;
+    let mut lines = Vec::new();
+    let mut size = 0;
+
+    loop {
+        let end = match buf.iter().position(|b| *b == b'\n') {
+            Some(end) => end,
+            None => {
+                if size + buf.len() > MAX_REPLY {
+                    return Err(Error::ParseError("reply too large".to_string()))
+                }
+                if iface.read_iface(buf, CHUNK).await? == 0 {
+                    return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into())
+                }
+                continue
+            }
+        };
+
+        let line: Vec<u8> = buf.drain(..=end).collect();
+        size += line.len();
+        let line = String::from_utf8_lossy(&line).trim_end().to_string();
+
+        let this = line.get(..3)
+            .filter(|x| x.bytes().all(|b| b.is_ascii_digit()))
+            .and_then(|x| x.parse::<u16>().ok());
+
+        let first = match code {
+            Some(first) => first,
+            None => match this {
+                Some(this) if (100..600).contains(&this) => *code.insert(this),
+                _ => return Err(Error::ParseError(format!("not a coded reply: {:?}", line)))
+            }
+        };
+
+        let done = this == Some(first) && coded_reply_done(&line);
+        lines.push(line);
+
+        if done {
+            return Ok(Reply { code: first, lines })
+        }
+        if size > MAX_REPLY {
+            return Err(Error::ParseError("reply too large".to_string()))
+        }
+    }
+}
+
+/// What's done next in a `CodedSession`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Exchange {
+    /// reads the next reply without asking, a greeting
+    Read,
+    /// sends the command, "\r\n" is added, and reads its reply
+    Command(String),
+}
+
+impl Exchange {
+    pub fn command(command: &str) -> Self {
+        Exchange::Command(command.to_string())
+    }
+}
+
+/// A conversation with a server that answers in coded replies (FTP, SMTP),
+/// keeps whatever was sent ahead of being asked for, for the next exchange
+#[derive(Debug, Default)]
+pub struct CodedSession {
+    buf: Vec<u8>,
+}
+
+#[async_trait::async_trait]
+impl Interact for CodedSession {
+    type Operation = Exchange;
+    type Output = Reply;
+
+    async fn apply_operation<I: NetworkInterface>(&mut self, iface: &mut I, op: Exchange) -> Result<Reply, Error> {
+        if let Exchange::Command(command) = op {
+            iface.write_iface(format!("{}\r\n", command).as_bytes()).await?;
+        }
+        read_coded_reply(iface, &mut self.buf).await
+    }
+}
+
+fn expect_code(reply: Reply, code: u16) -> Result<(), Error> {
+    if reply.code == code { Ok(()) }
+    else { Err(Error::UpgradeRefused(reply.lines.join("\n"))) }
+}
+
+fn pop3_reply_done(line: &str) -> bool {
+    line.starts_with("+OK") || line.starts_with("-ERR")
+}
+
+/// Checks the status code of the final line of a reply
+fn expect(reply: String, prefix: &str) -> Result<(), Error> {
+    let last = reply.lines().last().unwrap_or("");
+
+    if last.starts_with(prefix) { Ok(()) }
+    else { Err(Error::UpgradeRefused(reply)) }
+}
+
+async fn read_until_any<I: NetworkInterface>(iface: &mut I, buf: &mut Vec<u8>, needles: &[&[u8]]) -> Result<(), Error> {
+    loop {
+        if iface.read_iface(buf, MAX_REPLY).await? == 0 {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into())
+        }
+
+        let found = needles.iter()
+            .any(|needle| buf.windows(needle.len()).any(|window| window == *needle));
+
+        if found {
+            return Ok(())
+        }
+
+        if buf.len() > MAX_REPLY {
+            return Err(Error::ParseError("reply too large".to_string()))
+        }
+    }
+}
+
+/// LDAPMessage { messageID 1, ExtendedRequest { requestName "1.3.6.1.4.1.1466.20037" } }
+fn ldap_starttls_request() -> Vec<u8> {
+    const OID: &[u8] = b"1.3.6.1.4.1.1466.20037";
+
+    let mut extended = vec![0x80, OID.len() as u8];
+    extended.extend_from_slice(OID);
+
+    let mut msg = vec![
+        0x02, 0x01, 0x01, // messageID
+        0x77, extended.len() as u8 // [APPLICATION 23] ExtendedRequest
+    ];
+    msg.extend(extended);
+
+    let mut packet = vec![0x30, msg.len() as u8];
+    packet.extend(msg);
+    packet
+}
+
+/// Reads a whole LDAPMessage, however many segments it comes in
+async fn read_ldap_message<I: NetworkInterface>(iface: &mut I) -> Result<Vec<u8>, Error> {
+    let mut buf = Vec::new();
+
+    loop {
+        if let Some((_tag, header, len)) = ber_header(&buf) {
+            let total = header.saturating_add(len);
+            if total > MAX_REPLY {
+                return Err(Error::ParseError("reply too large".to_string()))
+            }
+            if buf.len() >= total {
+                buf.truncate(total);
+                return Ok(buf)
+            }
+        }
+
+        if iface.read_iface(&mut buf, MAX_REPLY).await? == 0 {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into())
+        }
+    }
+}
+
+/// Tag, header size, and content length of the BER element `buf` starts with,
+/// `None` until the whole header was read.
+/// Indefinite lengths, and lengths over 4 bytes are `usize::MAX`
+fn ber_header(buf: &[u8]) -> Option<(u8, usize, usize)> {
+    let tag = *buf.first()?;
+    let first = *buf.get(1)?;
+
+    if first < 0x80 {
+        return Some((tag, 2, first as usize))
+    }
+
+    let size = (first & 0x7f) as usize;
+    if size == 0 || size > 4 {
+        return Some((tag, 2, usize::MAX))
+    }
+
+    let len = buf.get(2..2 + size)?
+        .iter()
+        .fold(0, |len, byte| (len << 8) | *byte as usize);
+    Some((tag, 2 + size, len))
+}
+
+/// Splits `buf` into the tag and content of its first element, and whatever follows it
+fn ber_element(buf: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (tag, header, len) = ber_header(buf)?;
+    let end = header.checked_add(len)?;
+    Some((tag, buf.get(header..end)?, &buf[end..]))
+}
+
+/// LDAPMessage { messageID 1, ExtendedResponse ([APPLICATION 24]) { resultCode success (0), ... } }
+fn ldap_extended_success(buf: &[u8]) -> bool {
+    let result_code = || {
+        let (tag, message, _) = ber_element(buf)?;
+        if tag != 0x30 {
+            return None
+        }
+
+        // the id of our request, 0 would be a notice of disconnection
+        let (tag, id, rest) = ber_element(message)?;
+        if tag != 0x02 || id != [0x01] {
+            return None
+        }
+
+        let (tag, response, _) = ber_element(rest)?;
+        if tag != 0x78 {
+            return None
+        }
+
+        // ENUMERATED
+        match ber_element(response)? {
+            (0x0A, code, _) => Some(code.to_vec()),
+            _ => None
+        }
+    };
+
+    result_code().as_deref() == Some(&[0x00])
+}
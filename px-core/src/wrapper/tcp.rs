@@ -0,0 +1,102 @@
+use std::{
+    net::SocketAddr,
+    time::Duration
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::timeout
+};
+
+use crate::model::State;
+use super::{NetworkInterface, DEFAULT_TTL, io_state, pivot};
+
+#[derive(Debug)]
+pub struct TcpInterface {
+    stream: TcpStream,
+    peer: SocketAddr,
+    state: State,
+    ttl: Duration,
+}
+
+impl TcpInterface {
+    /// Connects to `addr`, through the pivots if any are set (`pivot::route_through`).
+    /// The connection attempt is bounded by `ttl` as well.
+    pub async fn connect(addr: SocketAddr, ttl: Duration) -> Result<Self, std::io::Error> {
+        let stream = pivot::connect(addr, ttl).await?;
+        
+        Ok(Self::from_stream(stream, addr, ttl))
+    }
+
+    pub fn from_stream(stream: TcpStream, peer: SocketAddr, ttl: Duration) -> Self {
+        Self {
+            stream,
+            peer,
+            ttl,
+            state: State::Open,
+        }
+    }
+
+    pub fn into_inner(self) -> TcpStream {
+        self.stream
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+}
+
+impl From<TcpStream> for TcpInterface {
+    fn from(stream: TcpStream) -> Self {
+        let peer = stream.peer_addr()
+            .unwrap_or_else(|_| SocketAddr::from(([0, 0, 0, 0], 0)));
+        
+        Self::from_stream(stream, peer, DEFAULT_TTL)
+    }
+}
+
+#[async_trait::async_trait]
+impl NetworkInterface for TcpInterface {
+    async fn read_iface(&mut self, buf: &mut Vec<u8>, amount: usize) -> Result<usize, std::io::Error> {
+        let mut chunk = vec![0; amount];
+        
+        match timeout(self.ttl, self.stream.read(&mut chunk)).await {
+            Ok(Ok(n)) => {
+                if n == 0 && amount > 0 {
+                    self.state = State::Closed;
+                }
+                buf.extend_from_slice(&chunk[..n]);
+                Ok(n)
+            },
+            Ok(Err(e)) => {
+                self.state = io_state(&e);
+                Err(e)
+            },
+            Err(_) => Err(std::io::ErrorKind::TimedOut.into())
+        }
+    }
+
+    async fn write_iface(&mut self, buf: &[u8]) -> Result<usize, std::io::Error> {
+        match timeout(self.ttl, self.stream.write_all(buf)).await {
+            Ok(Ok(())) => Ok(buf.len()),
+            Ok(Err(e)) => {
+                self.state = io_state(&e);
+                Err(e)
+            },
+            Err(_) => Err(std::io::ErrorKind::TimedOut.into())
+        }
+    }
+
+    fn peer(&self) -> SocketAddr {
+        self.peer
+    }
+
+    fn state(&self) -> State {
+        self.state
+    }
+
+    fn set_timeout(&mut self, ttl: Duration) {
+        self.ttl = ttl;
+    }
+}
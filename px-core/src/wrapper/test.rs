@@ -0,0 +1,461 @@
+/*
+Local stand-ins for each transport,
+so probes can be ran against them without leaving loopback.
+*/
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    time::Duration
+};
+
+use tokio::{
+    runtime::Runtime,
+    net::{TcpListener, UdpSocket},
+    io::{AsyncReadExt, AsyncWriteExt},
+};
+
+use tokio_rustls::{
+    TlsAcceptor,
+    rustls::{ServerConfig, NoClientAuth, Certificate, PrivateKey},
+};
+
+use super::*;
+
+const TTL: Duration = Duration::from_secs(2);
+
+/// Writes "ping\n" and expects the same line back
+#[derive(Debug)]
+struct EchoProbe;
+
+#[async_trait::async_trait]
+impl Probe<String> for EchoProbe {
+    async fn probe_service<I: NetworkInterface>(&mut self, iface: &mut I) -> Option<String> {
+        iface.write_iface(b"ping\n").await.ok()?;
+
+        let mut buf = Vec::new();
+        iface.read_until(&mut buf, b"\n", 64).await.ok()?;
+
+        Some(String::from_utf8_lossy(&buf).trim().to_string())
+    }
+}
+
+pub async fn tcp_echo_server() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        loop {
+            let (mut con, _) = listener.accept().await.unwrap();
+            tokio::spawn(async move {
+                let mut buf = [0; 512];
+                while let Ok(n) = con.read(&mut buf).await {
+                    if n == 0 || con.write_all(&buf[..n]).await.is_err() {
+                        break
+                    }
+                }
+            });
+        }
+    });
+
+    addr
+}
+
+/// self signed certificate for "localhost", (certificate, private key) in DER
+pub fn self_signed() -> (Vec<u8>, Vec<u8>) {
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    (cert.serialize_der().unwrap(), cert.serialize_private_key_der())
+}
+
+pub fn tls_acceptor() -> TlsAcceptor {
+    let (cert, key) = self_signed();
+    let mut config = ServerConfig::new(NoClientAuth::new());
+    config.set_single_cert(vec![Certificate(cert)], PrivateKey(key)).unwrap();
+    TlsAcceptor::from(Arc::new(config))
+}
+
+pub async fn tls_echo_server() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let acceptor = tls_acceptor();
+
+    tokio::spawn(async move {
+        loop {
+            let (con, _) = listener.accept().await.unwrap();
+            let acceptor = acceptor.clone();
+
+            tokio::spawn(async move {
+                let mut con = match acceptor.accept(con).await {
+                    Ok(con) => con,
+                    Err(_) => return
+                };
+
+                let mut buf = [0; 512];
+                while let Ok(n) = con.read(&mut buf).await {
+                    if n == 0 || con.write_all(&buf[..n]).await.is_err() {
+                        break
+                    }
+                    let _ = con.flush().await;
+                }
+            });
+        }
+    });
+
+    addr
+}
+
+#[test]
+fn tcp_probe() {
+    let rt = Runtime::new().unwrap();
+
+    rt.block_on(async move {
+        let addr = tcp_echo_server().await;
+        let mut iface = TcpInterface::connect(addr, TTL).await.unwrap();
+
+        assert_eq!(iface.state(), State::Open);
+        assert_eq!(iface.peer(), addr);
+        assert_eq!(EchoProbe.probe(&mut iface).await, Some("ping".to_string()));
+    });
+}
+
+#[test]
+fn tcp_peer_closed() {
+    let rt = Runtime::new().unwrap();
+
+    rt.block_on(async move {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let (con, _) = listener.accept().await.unwrap();
+            drop(con);
+        });
+
+        let mut iface = TcpInterface::connect(addr, TTL).await.unwrap();
+        let mut buf = Vec::new();
+
+        assert_eq!(iface.read_iface(&mut buf, 16).await.unwrap(), 0);
+        assert_eq!(iface.state(), State::Closed);
+    });
+}
+
+#[test]
+fn read_until_empty_delimiter() {
+    let rt = Runtime::new().unwrap();
+
+    rt.block_on(async move {
+        let addr = tcp_echo_server().await;
+        let mut iface = TcpInterface::connect(addr, TTL).await.unwrap();
+        iface.write_iface(b"ping\n").await.unwrap();
+
+        let mut buf = Vec::new();
+        let err = iface.read_until(&mut buf, b"", 64).await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(buf.is_empty());
+    });
+}
+
+#[test]
+fn coded_replies() {
+    let rt = Runtime::new().unwrap();
+
+    rt.block_on(async move {
+        let addr = tcp_echo_server().await;
+        let mut iface = TcpInterface::connect(addr, TTL).await.unwrap();
+        // echoed back in one read
+        iface.write_iface(b"120 soon\r\n220-hello\r\n100 users\r\n220 ready\r\n").await.unwrap();
+
+        let mut session = CodedSession::default();
+        let reply = session.interact(&mut iface, Exchange::Read).await.unwrap();
+        assert_eq!(reply.code, 120);
+        assert_eq!(reply.lines, vec!["120 soon"]);
+
+        // only the first line's code ends the reply
+        let reply = session.interact(&mut iface, Exchange::Read).await.unwrap();
+        assert_eq!(reply.code, 220);
+        assert_eq!(reply.lines, vec!["220-hello", "100 users", "220 ready"]);
+        assert_eq!(reply.message(), "hello 100 users ready");
+
+        // echoed back as its own reply
+        let reply = session.interact(&mut iface, Exchange::command("250 ok")).await.unwrap();
+        assert!(reply.positive());
+        assert_eq!(reply.text(), "ok");
+    });
+}
+
+#[test]
+fn tcp_read_times_out() {
+    let rt = Runtime::new().unwrap();
+
+    rt.block_on(async move {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let mut iface = TcpInterface::connect(addr, TTL).await.unwrap();
+        iface.set_timeout(Duration::from_millis(100));
+
+        let mut buf = Vec::new();
+        let err = iface.read_iface(&mut buf, 16).await.unwrap_err();
+
+        assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+        drop(listener);
+    });
+}
+
+#[test]
+fn udp_probe() {
+    let rt = Runtime::new().unwrap();
+
+    rt.block_on(async move {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = server.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let mut buf = [0; 512];
+            let (n, peer) = server.recv_from(&mut buf).await.unwrap();
+            server.send_to(&buf[..n], peer).await.unwrap();
+        });
+
+        let mut iface = UdpInterface::connect(addr, TTL).await.unwrap();
+        assert_eq!(iface.state(), State::Filtered);
+        assert_eq!(EchoProbe.probe(&mut iface).await, Some("ping".to_string()));
+        assert_eq!(iface.state(), State::Open);
+    });
+}
+
+#[test]
+fn udp_port_unreachable() {
+    let rt = Runtime::new().unwrap();
+
+    rt.block_on(async move {
+        // bind and drop to find a port nobody listens on
+        let addr = UdpSocket::bind("127.0.0.1:0").await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let mut iface = UdpInterface::connect(addr, TTL).await.unwrap();
+        iface.write_iface(b"hello").await.unwrap();
+
+        let mut buf = Vec::new();
+        let err = iface.read_iface(&mut buf, 16).await.unwrap_err();
+
+        assert_eq!(err.kind(), std::io::ErrorKind::ConnectionRefused);
+        assert_eq!(iface.state(), State::Closed);
+    });
+}
+
+#[test]
+fn tls_probe() {
+    let rt = Runtime::new().unwrap();
+
+    rt.block_on(async move {
+        let addr = tls_echo_server().await;
+        let mut iface = TlsInterface::connect(addr, Some("localhost"), TTL).await.unwrap();
+
+        assert_eq!(EchoProbe.probe(&mut iface).await, Some("ping".to_string()));
+    });
+}
+
+#[test]
+fn tls_without_server_name() {
+    let rt = Runtime::new().unwrap();
+
+    rt.block_on(async move {
+        let addr = tls_echo_server().await;
+        let mut iface = TlsInterface::connect(addr, None, TTL).await.unwrap();
+
+        assert_eq!(EchoProbe.probe(&mut iface).await, Some("ping".to_string()));
+    });
+}
+
+/// Plays the plaintext half of a STARTTLS exchange:
+/// sends `greeting`, then answers each read with the next entry of `replies`,
+/// and then switches over to a tls echo server
+pub async fn starttls_server(greeting: &'static [u8], replies: Vec<&'static [u8]>) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let acceptor = tls_acceptor();
+
+    tokio::spawn(async move {
+        let (mut con, _) = listener.accept().await.unwrap();
+        con.write_all(greeting).await.unwrap();
+
+        let mut buf = [0; 512];
+        for reply in replies {
+            assert!(con.read(&mut buf).await.unwrap() > 0);
+            con.write_all(reply).await.unwrap();
+        }
+
+        let mut con = acceptor.accept(con).await.unwrap();
+        while let Ok(n) = con.read(&mut buf).await {
+            if n == 0 || con.write_all(&buf[..n]).await.is_err() {
+                break
+            }
+            let _ = con.flush().await;
+        }
+    });
+
+    addr
+}
+
+fn starttls_probe(protocol: StartTlsProtocol, greeting: &'static [u8], replies: Vec<&'static [u8]>) -> Option<String> {
+    let rt = Runtime::new().unwrap();
+
+    rt.block_on(async move {
+        let addr = starttls_server(greeting, replies).await;
+        let iface = TcpInterface::connect(addr, TTL).await.unwrap();
+        
+        let mut tls = EchoProbe.upgrade(iface, &StartTls::new(protocol)).await.unwrap();
+        EchoProbe.probe(&mut tls).await
+    })
+}
+
+#[test]
+fn tls_upgrade() {
+    let rt = Runtime::new().unwrap();
+
+    rt.block_on(async move {
+        let addr = tls_echo_server().await;
+        let iface = TcpInterface::connect(addr, TTL).await.unwrap();
+
+        let mut tls = EchoProbe.upgrade(iface, &Tls::new(Some("localhost".to_string()))).await.unwrap();
+        assert_eq!(EchoProbe.probe(&mut tls).await, Some("ping".to_string()));
+    });
+}
+
+#[test]
+fn starttls_smtp() {
+    let reply = starttls_probe(
+        StartTlsProtocol::Smtp,
+        b"220-localhost ESMTP\r\n220 ready\r\n",
+        vec![b"250-localhost\r\n250-SIZE 1000\r\n250 STARTTLS\r\n", b"220 go ahead\r\n"]
+    );
+    assert_eq!(reply, Some("ping".to_string()));
+}
+
+#[test]
+fn starttls_ftp() {
+    let reply = starttls_probe(
+        StartTlsProtocol::Ftp,
+        b"220 ftp ready\r\n",
+        vec![b"234 AUTH TLS ok\r\n"]
+    );
+    assert_eq!(reply, Some("ping".to_string()));
+}
+
+#[test]
+fn starttls_imap() {
+    let reply = starttls_probe(
+        StartTlsProtocol::Imap,
+        b"* OK IMAP4rev1 ready\r\n",
+        vec![b"a001 OK begin tls\r\n"]
+    );
+    assert_eq!(reply, Some("ping".to_string()));
+}
+
+#[test]
+fn starttls_pop3() {
+    let reply = starttls_probe(
+        StartTlsProtocol::Pop3,
+        b"+OK pop3 ready\r\n",
+        vec![b"+OK begin tls\r\n"]
+    );
+    assert_eq!(reply, Some("ping".to_string()));
+}
+
+#[test]
+fn starttls_ldap() {
+    let reply = starttls_probe(
+        StartTlsProtocol::Ldap,
+        b"",
+        vec![&[0x30, 0x0c, 0x02, 0x01, 0x01, 0x78, 0x07, 0x0a, 0x01, 0x00, 0x04, 0x00, 0x04, 0x00]]
+    );
+    assert_eq!(reply, Some("ping".to_string()));
+}
+
+/// Answers the StartTLS request with `chunks`, pausing in between
+fn ldap_negotiate(chunks: Vec<&'static [u8]>) -> Result<(), Error> {
+    let rt = Runtime::new().unwrap();
+
+    rt.block_on(async move {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let (mut con, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 512];
+            let _ = con.read(&mut buf).await;
+
+            for chunk in chunks {
+                con.write_all(chunk).await.unwrap();
+                con.flush().await.unwrap();
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        });
+
+        let mut iface = TcpInterface::connect(addr, TTL).await.unwrap();
+        StartTls::new(StartTlsProtocol::Ldap).negotiate(&mut iface).await
+    })
+}
+
+#[test]
+fn starttls_ldap_split_reply() {
+    assert!(ldap_negotiate(vec![
+        &[0x30, 0x0c, 0x02],
+        &[0x01, 0x01, 0x78, 0x07, 0x0a, 0x01, 0x00, 0x04, 0x00, 0x04, 0x00],
+    ]).is_ok());
+}
+
+#[test]
+fn starttls_ldap_refused() {
+    // protocolError (2), with the bytes of a success in its diagnosticMessage
+    let refused = ldap_negotiate(vec![
+        &[0x30, 0x0f, 0x02, 0x01, 0x01, 0x78, 0x0a, 0x0a, 0x01, 0x02, 0x04, 0x00, 0x04, 0x03, 0x0a, 0x01, 0x00],
+    ]);
+    assert!(matches!(refused, Err(Error::UpgradeRefused(_))));
+
+    // a notice of disconnection has a messageID of 0
+    let refused = ldap_negotiate(vec![
+        &[0x30, 0x0c, 0x02, 0x01, 0x00, 0x78, 0x07, 0x0a, 0x01, 0x00, 0x04, 0x00, 0x04, 0x00],
+    ]);
+    assert!(matches!(refused, Err(Error::UpgradeRefused(_))));
+}
+
+#[test]
+fn starttls_xmpp() {
+    let reply = starttls_probe(
+        StartTlsProtocol::Xmpp,
+        b"",
+        vec![
+            b"<?xml version='1.0'?><stream:stream from='localhost' xmlns='jabber:client' \
+            xmlns:stream='http://etherx.jabber.org/streams' version='1.0'>\
+            <stream:features><starttls xmlns='urn:ietf:params:xml:ns:xmpp-tls'><required/></starttls></stream:features>",
+            b"<proceed xmlns='urn:ietf:params:xml:ns:xmpp-tls'/>"
+        ]
+    );
+    assert_eq!(reply, Some("ping".to_string()));
+}
+
+#[test]
+fn starttls_refused() {
+    let rt = Runtime::new().unwrap();
+
+    rt.block_on(async move {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let (mut con, _) = listener.accept().await.unwrap();
+            con.write_all(b"+OK pop3 ready\r\n").await.unwrap();
+            let mut buf = [0; 64];
+            assert!(con.read(&mut buf).await.unwrap() > 0);
+            con.write_all(b"-ERR no tls here\r\n").await.unwrap();
+        });
+
+        let mut iface = TcpInterface::connect(addr, TTL).await.unwrap();
+        match StartTls::new(StartTlsProtocol::Pop3).negotiate(&mut iface).await {
+            Err(crate::error::Error::UpgradeRefused(reply)) => assert!(reply.contains("-ERR")),
+            x => panic!("expected refusal, got {:?}", x)
+        }
+    });
+}
@@ -0,0 +1,168 @@
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    time::Duration
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::timeout
+};
+
+use tokio_rustls::{
+    TlsConnector,
+    client::TlsStream,
+    webpki::DNSNameRef,
+    rustls::{
+        ClientConfig,
+        ServerCertVerifier,
+        ServerCertVerified,
+        RootCertStore,
+        Certificate,
+        TLSError,
+    }
+};
+
+use crate::model::State;
+use super::{NetworkInterface, io_state, pivot};
+
+/// We're scanning, not trusting.
+/// Every certificate is accepted so self-signed/expired services still get probed.
+struct AcceptAnyCert;
+
+impl ServerCertVerifier for AcceptAnyCert {
+    fn verify_server_cert(
+        &self,
+        _roots: &RootCertStore,
+        _presented_certs: &[Certificate],
+        _dns_name: DNSNameRef<'_>,
+        _ocsp_response: &[u8]
+    ) -> Result<ServerCertVerified, TLSError> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+/// Client configuration that doesn't verify the peer's certificate
+pub fn insecure_config() -> ClientConfig {
+    let mut config = ClientConfig::new();
+    config.dangerous()
+        .set_certificate_verifier(Arc::new(AcceptAnyCert));
+    config
+}
+
+pub struct TlsInterface {
+    stream: TlsStream<TcpStream>,
+    peer: SocketAddr,
+    state: State,
+    ttl: Duration,
+}
+
+impl std::fmt::Debug for TlsInterface {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TlsInterface")
+            .field("peer", &self.peer)
+            .field("state", &self.state)
+            .field("ttl", &self.ttl)
+            .finish()
+    }
+}
+
+impl TlsInterface {
+    /// Connects over tcp, and completes a tls handshake.
+    /// `server_name` is sent as SNI when given.
+    pub async fn connect(addr: SocketAddr, server_name: Option<&str>, ttl: Duration) -> Result<Self, std::io::Error> {
+        Self::connect_with(addr, server_name, Arc::new(insecure_config()), ttl).await
+    }
+
+    pub async fn connect_with(addr: SocketAddr, server_name: Option<&str>, config: Arc<ClientConfig>, ttl: Duration) -> Result<Self, std::io::Error> {
+        let stream = pivot::connect(addr, ttl).await?;
+
+        Self::handshake(stream, addr, server_name, config, ttl).await
+    }
+
+    /// Completes a tls handshake over an already connected stream
+    pub async fn handshake(stream: TcpStream, peer: SocketAddr, server_name: Option<&str>, config: Arc<ClientConfig>, ttl: Duration) -> Result<Self, std::io::Error> {
+        // webpki doesn't accept ip addresses as names,
+        // so when we don't have one, don't send SNI at all
+        let (config, name) = match server_name.and_then(|name| DNSNameRef::try_from_ascii_str(name).ok()) {
+            Some(name) => (config, name),
+            None => {
+                let mut config = (*config).clone();
+                config.enable_sni = false;
+                (Arc::new(config), DNSNameRef::try_from_ascii_str("invalid").unwrap())
+            }
+        };
+
+        let connector = TlsConnector::from(config);
+        let stream = timeout(ttl, connector.connect(name, stream)).await
+            .map_err(|_| std::io::Error::from(std::io::ErrorKind::TimedOut))??;
+
+        Ok(Self {
+            stream,
+            peer,
+            ttl,
+            state: State::Open,
+        })
+    }
+
+    /// Access to the tls session (certificates, negotiated version/cipher)
+    pub fn session(&self) -> &tokio_rustls::rustls::ClientSession {
+        self.stream.get_ref().1
+    }
+
+    pub fn into_inner(self) -> TlsStream<TcpStream> {
+        self.stream
+    }
+}
+
+#[async_trait::async_trait]
+impl NetworkInterface for TlsInterface {
+    async fn read_iface(&mut self, buf: &mut Vec<u8>, amount: usize) -> Result<usize, std::io::Error> {
+        let mut chunk = vec![0; amount];
+
+        match timeout(self.ttl, self.stream.read(&mut chunk)).await {
+            Ok(Ok(n)) => {
+                if n == 0 && amount > 0 {
+                    self.state = State::Closed;
+                }
+                buf.extend_from_slice(&chunk[..n]);
+                Ok(n)
+            },
+            Ok(Err(e)) => {
+                self.state = io_state(&e);
+                Err(e)
+            },
+            Err(_) => Err(std::io::ErrorKind::TimedOut.into())
+        }
+    }
+
+    async fn write_iface(&mut self, buf: &[u8]) -> Result<usize, std::io::Error> {
+        let stream = &mut self.stream;
+        let write = async move {
+            stream.write_all(buf).await?;
+            stream.flush().await
+        };
+
+        match timeout(self.ttl, write).await {
+            Ok(Ok(())) => Ok(buf.len()),
+            Ok(Err(e)) => {
+                self.state = io_state(&e);
+                Err(e)
+            },
+            Err(_) => Err(std::io::ErrorKind::TimedOut.into())
+        }
+    }
+
+    fn peer(&self) -> SocketAddr {
+        self.peer
+    }
+
+    fn state(&self) -> State {
+        self.state
+    }
+
+    fn set_timeout(&mut self, ttl: Duration) {
+        self.ttl = ttl;
+    }
+}
@@ -0,0 +1,95 @@
+use std::{
+    net::SocketAddr,
+    time::Duration
+};
+
+use tokio::{
+    net::UdpSocket,
+    time::timeout
+};
+
+use crate::model::State;
+use super::{NetworkInterface, io_state};
+
+/// Connected udp socket.
+/// Until the peer answers, the port is considered `Filtered`
+#[derive(Debug)]
+pub struct UdpInterface {
+    socket: UdpSocket,
+    peer: SocketAddr,
+    state: State,
+    ttl: Duration,
+}
+
+impl UdpInterface {
+    pub async fn connect(addr: SocketAddr, ttl: Duration) -> Result<Self, std::io::Error> {
+        let bind_addr: SocketAddr = match addr {
+            SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+            SocketAddr::V6(_) => ([0u16; 8], 0).into(),
+        };
+
+        let socket = UdpSocket::bind(bind_addr).await?;
+        socket.connect(addr).await?;
+        
+        Ok(Self::from_socket(socket, addr, ttl))
+    }
+
+    /// `socket` must already be connected to `peer`
+    pub fn from_socket(socket: UdpSocket, peer: SocketAddr, ttl: Duration) -> Self {
+        Self {
+            socket,
+            peer,
+            ttl,
+            state: State::Filtered,
+        }
+    }
+
+    pub fn into_inner(self) -> UdpSocket {
+        self.socket
+    }
+}
+
+#[async_trait::async_trait]
+impl NetworkInterface for UdpInterface {
+    /// Receives a single datagram, truncated to `amount` bytes
+    async fn read_iface(&mut self, buf: &mut Vec<u8>, amount: usize) -> Result<usize, std::io::Error> {
+        let mut chunk = vec![0; amount];
+
+        match timeout(self.ttl, self.socket.recv(&mut chunk)).await {
+            Ok(Ok(n)) => {
+                self.state = State::Open;
+                buf.extend_from_slice(&chunk[..n]);
+                Ok(n)
+            },
+            // icmp port unreachable shows up as ECONNREFUSED
+            Ok(Err(e)) => {
+                self.state = io_state(&e);
+                Err(e)
+            },
+            Err(_) => Err(std::io::ErrorKind::TimedOut.into())
+        }
+    }
+
+    async fn write_iface(&mut self, buf: &[u8]) -> Result<usize, std::io::Error> {
+        match timeout(self.ttl, self.socket.send(buf)).await {
+            Ok(Ok(n)) => Ok(n),
+            Ok(Err(e)) => {
+                self.state = io_state(&e);
+                Err(e)
+            },
+            Err(_) => Err(std::io::ErrorKind::TimedOut.into())
+        }
+    }
+
+    fn peer(&self) -> SocketAddr {
+        self.peer
+    }
+
+    fn state(&self) -> State {
+        self.state
+    }
+
+    fn set_timeout(&mut self, ttl: Duration) {
+        self.ttl = ttl;
+    }
+}
@@ -1,6 +1,8 @@
 mod error;
 pub mod service_probe;
 
+pub use error::Error;
+
 
 #[cfg(test)]
 mod tests {
@@ -36,10 +36,67 @@ pub fn construct_payload(source: &str, output: &mut Vec<u8>) -> Result<(), Error
     Ok(())
 }
 
+/// Decodes the payload of a `Probe` line, `q|GET / HTTP/1.0\r\n\r\n|`,
+/// into the bytes sent on the wire.
+/// Understands the escapes nmap does: `\\ \0 \a \b \f \n \r \t \v \xHH`
+pub fn probe_payload(source: &str) -> Result<Vec<u8>, Error> {
+    let mut chars = source.trim().chars();
+
+    let delimiter = match (chars.next(), chars.next()) {
+        (Some('q'), Some(delimiter)) => delimiter,
+        _ => return Err(Error::ParseError(format!("expected q|...| payload, got '{}'", source)))
+    };
+
+    let mut output = Vec::new();
+    let mut utf8 = [0; 4];
+
+    while let Some(c) = chars.next() {
+        let byte = match c {
+            c if c == delimiter => return Ok(output),
+            '\\' => match chars.next() {
+                Some('0') => 0,
+                Some('a') => 0x07,
+                Some('b') => 0x08,
+                Some('f') => 0x0c,
+                Some('n') => b'\n',
+                Some('r') => b'\r',
+                Some('t') => b'\t',
+                Some('v') => 0x0b,
+                Some('x') => {
+                    let hex: String = chars.by_ref().take(2).collect();
+                    u8::from_str_radix(&hex, 16)
+                        .map_err(|_| Error::ParseError(format!("bad \\x escape in '{}'", source)))?
+                }
+                Some(c) => {
+                    output.extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+                    continue
+                }
+                None => break
+            },
+            c => {
+                output.extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+                continue
+            }
+        };
+
+        output.push(byte);
+    }
+
+    Err(Error::ParseError(format!("unterminated payload '{}'", source)))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn parse_probe_payload() {
+        assert_eq!(probe_payload(r"q||").unwrap(), b"");
+        assert_eq!(probe_payload(r"q|GET / HTTP/1.0\r\n\r\n|").unwrap(), b"GET / HTTP/1.0\r\n\r\n");
+        assert_eq!(probe_payload(r"q|\0\x1E\0\x06version\x04|").unwrap(), b"\0\x1e\0\x06version\x04");
+        assert!(probe_payload(r"q|unterminated").is_err());
+    }
+
     #[test]
     fn parse_byte_string() {
         let mut buffer = Vec::with_capacity(64);
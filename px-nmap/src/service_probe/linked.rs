@@ -1,287 +1,524 @@
-
-use regex::bytes::RegexSet;
+use regex::bytes::{Regex, RegexBuilder, RegexSet, RegexSetBuilder, Match};
 use px_common::netport::PortInput;
 
 use super::{
-    parser::model::{ProbeExpr, Protocol},
-    parser::MatchLineExpr,
+    parser::model::{ProbeExpr, Protocol, DataField},
+    parser::{MatchLineExpr, Flags, FileError, Meta, parse},
+    parser::model::Directive,
 };
 use crate::error::Error;
 
 use std::{
     cmp::Ordering,
-    collections::HashMap
+    collections::HashMap,
+    time::Duration,
 };
-use super::hex::construct_payload;
-use regex::Regex;
-
-
-// #[derive(Debug)]
-// pub struct LinkGuard<'a>(&'a Link);
-
-// #[derive(Debug)]
-// struct Link {
-//     proto: Protocol,// TCP/UDP
-//     payload: Vec<u8>,
-//     name: String,
-    
-//     ports: Vec<PortInput>,
-//     exclude: Vec<PortInput>,
-//     tls_ports: Vec<PortInput>,
-
-//     //so here we have a flat map where we'll do a quick match on, 
-//     //where we get a collection of indexes matched, we'll take those 
-//     fallback: String,
-//     lookup_set: AlignedSet,
-// }
-
-// impl Link {
-//     fn matches<'a>(&'a self, input_buf: &[u8], out_buf: &mut Vec<&'a MatchLineExpr>) {
-//         self.lookup_set.match_response(input_buf, out_buf)
-//     }
-// }
-
-// #[derive(Debug)]
-// pub struct ChainedProbes {
-//     inner: Vec<Link>,
-//     name_map: HashMap<String, usize>
-// }
+use super::hex::probe_payload;
+
+/// `totalwaitms` of probes that don't declare it
+pub const DEFAULT_WAIT: Duration = Duration::from_secs(5);
+/// `tcpwrappedms` of probes that don't declare it
+pub const DEFAULT_WRAPPED_WAIT: Duration = Duration::from_secs(2);
+/// nmap's default `--version-intensity`
+pub const DEFAULT_INTENSITY: u8 = 7;
+
+/// patterns compile into a lot of states,
+/// nmap's NULL probe alone carries thousands of them
+const SIZE_LIMIT: usize = 256 * (1 << 20);
+
+lazy_static::lazy_static! {
+    /// groups that didn't participate in a match are substituted as empty
+    static ref EMPTY: Regex = Regex::new("").unwrap();
+}
+
+/// A match line the regex crate couldn't compile (backreferences, lookarounds),
+/// it's left out, and never matches
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedPattern {
+    pub probe: String,
+    /// service the line would've identified
+    pub service: String,
+    pub pattern: String,
+    pub reason: String,
+}
+
+/// What a match line identified, with its substitutions made
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ServiceMatch {
+    pub name: String,
+    pub product: Option<String>,
+    pub version: Option<String>,
+    pub info: Option<String>,
+    pub hostname: Option<String>,
+    pub operating_system: Option<String>,
+    pub device_type: Option<String>,
+    pub cpe: Vec<String>,
+    /// came from a `softmatch`, the service is known, but not its product/version
+    pub soft: bool,
+}
+
+impl ServiceMatch {
+    /// Name nmap gives services that close the connection before sending anything,
+    /// usually tcp wrappers rejecting the client
+    pub const TCPWRAPPED: &'static str = "tcpwrapped";
+
+    pub fn tcpwrapped() -> Self {
+        Self {
+            name: Self::TCPWRAPPED.to_string(),
+            ..Self::default()
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Link {
+    pub proto: Protocol,// TCP/UDP
+    pub payload: Vec<u8>,
+    pub name: String,
+    pub rarity: u8,
+
+    ports: Vec<PortInput>,
+    tls_ports: Vec<PortInput>,
+    total_wait: Duration,
+    wrapped_wait: Duration,
+
+    // indexes into `ChainedProbes::inner`, in the order they're declared
+    fallback: Vec<usize>,
+    //so here we have a flat map where we'll do a quick match on,
+    //where we get a collection of indexes matched, we'll take those
+    lookup_set: AlignedSet,
+}
+
+impl Link {
+    /// How long to wait on a response, `totalwaitms`
+    pub fn wait(&self) -> Duration {
+        self.total_wait
+    }
+
+    /// A server closing the connection without a word within this long is tcpwrapped,
+    /// `tcpwrappedms`
+    pub fn wrapped_wait(&self) -> Duration {
+        self.wrapped_wait
+    }
+
+    /// the probe is registered to `port` with the `ports` directive
+    pub fn registered(&self, port: u16) -> bool {
+        self.ports.iter().any(|x| x.contains(port))
+    }
+
+    /// the probe is registered to `port` with the `sslports` directive
+    pub fn registered_tls(&self, port: u16) -> bool {
+        self.tls_ports.iter().any(|x| x.contains(port))
+    }
+
+    /// the probe has match lines for `service`,
+    /// used to skip probes that can't improve a softmatch
+    pub fn identifies(&self, service: &str) -> bool {
+        self.lookup_set.map.iter().any(|x| x.name == service)
+    }
+}
 
 // immutable collection of probe & trigger combinations
 // once created, its contents shouldn't be modified
 // [x] rarity order + load order
-// [x] all enteries with fallbacks do exist, or go to Null 
-// impl ChainedProbes {
-//     #[inline]
-//     fn inner_new(x: ProbeExpr) -> Result<Link, Error> {
-//         let mut this = Link {
-//             proto: x.proto,
-//             // interpret bytes TODO
-//             payload: {
-//                 let mut buffer = Vec::new();
-//                 construct_payload(&x.payload, &mut buffer)?;
-//                 buffer
-//             },
-//             name: x.name,
-//             ports: x.ports,
-//             exclude: x.exclude,
-//             tls_ports: x.tls_ports,
-//             fallback: x.fallback,
-//             lookup_set: AlignedSet::new(x.matches).unwrap()
-//         };
-        
-//         this.tls_ports.shrink_to_fit();
-//         this.ports.shrink_to_fit();
-//         this.exclude.shrink_to_fit();
-//         this.name.shrink_to_fit();
-//         this.payload.shrink_to_fit();
-//         this.fallback.shrink_to_fit();
-    
-//         Ok(this)
-//     }
-    
-//     #[inline]
-//     fn deduplicate_probes(mut last_state: (Option<String>, Vec<ProbeExpr>), probe: ProbeExpr) -> (Option<String>, Vec<ProbeExpr>) {
-//         if let Some(ref last_name) = last_state.0 {
-//             if probe.name == *last_name {
-//                 //TODO(ADAM)
-//                 //eprintln!("duplicate probe name found: {}", &probe.name);
-//             }
-//             last_state.0 = Some(probe.name.clone());
-//             last_state.1.push(probe);
-
-//         }
-//         else {
-//             // assume its the first iteration
-//             last_state.0 = Some(probe.name);
-//         }
-//         last_state
-//     }
-
-//     pub fn new(mut buf: Vec<ProbeExpr>) -> Result<Self, Error> {
-//         // sort by name
-//         buf.sort_by(|a, b| a.name.partial_cmp(&b.name).unwrap());
-        
-//         // look for duplicates, and de-duplicate
-//         let mut dedup = buf.drain(..)
-//             .fold(
-//                 (None::<String>, Vec::new()), Self::deduplicate_probes
-//             ).1;
-//         drop(buf);
-
-//         // okay, now we need to do a fallback link check.
-//         // to ensure they actually go to something
-
-//         // copy all the names into this buffer
-//         let name_buf: Vec<String> = dedup
-//             .iter()
-//             .map(|probe| probe.name.clone())
-//             .collect();
-        
-//         // use the buffer to see if anything shows up that doesn't exist
-//         let mut linked_probes: Vec<_> = dedup.drain(..).map(|mut probe| {
-//             if let Some(fallback) = &mut probe.fallback {
-
-//                 // map to no fallback if it doesn't exist
-//                 if !name_buf.contains(&fallback.to_string()) {
-//                     probe.fallback = None;
-//                 }
-//             }
-//             probe
-//         }).collect();
-//         drop(name_buf);
-
-//         // now that we're de-duped and all unknown fallbacks are set to None,
-//         // we will set them to NULL (the probe), but before we can do that
-//         // we have to find NULL (again, the probe.)
-
-//         // sort buffer by rarity, and then by load order
-//         linked_probes.sort_by(|a, b| {
-//             // so this little condition should
-//             // put NULL as index 0
-//             if a.name == "NULL" {
-//                 return Ordering::Greater
-//             }
-
-//             else if b.name == "NULL" {
-//                 return Ordering::Less
-//             }
-
-//             // in all other cases, we'll sort on rarity, and then if equal, then load order
-//             let cmp = a.rarity.partial_cmp(&b.rarity).unwrap();
-//             match cmp {
-//                 Ordering::Equal => return a.load_ord.partial_cmp(&b.load_ord).unwrap(),
-//                 _ => return cmp
-//             }
-//         });
-        
-//         // cut off after intensity is met
-//         // let mut probes: Vec<_> = linked.drain(..)
-//         //     .take_while(|probe| probe.rarity <= max_intensity)
-//         //     .collect();
-        
-//         let mut name_map = HashMap::new();
-//         for (i, mut probe) in linked_probes.iter_mut().enumerate() {
-//             //reset None to NULL probe
-//             if let None = probe.fallback {
-//                 probe.fallback = Some("NULL".to_string());
-//             }
-//             name_map.insert(probe.name.clone(), i);
-//                 //.unwrap_none()
-            
-//         }
-        
-//         let mut links = Vec::with_capacity(linked_probes.len());
-//         for item in linked_probes {
-//             links.push(Self::inner_new(item)?)
-//         }
-
-//         // as far as we're concerned, 
-//         // this is now a flat list of probes that are ordered
-//         // first from rarity, then load order
-//         Ok(Self {
-//             inner: links,
-//             name_map
-//         })
-//     }
-
-//     pub fn null(&self) -> LinkGuard {
-//         LinkGuard(self.inner.get(0).unwrap())
-//     }
-
-// }
+// [x] all enteries with fallbacks do exist, or go to Null
+#[derive(Debug)]
+pub struct ChainedProbes {
+    inner: Vec<Link>,
+    name_map: HashMap<(String, bool), usize>,
+    exclude: Vec<PortInput>,
+}
+
+impl ChainedProbes {
+    #[inline]
+    fn inner_new(x: ProbeExpr) -> Result<Link, Error> {
+        let mut this = Link {
+            proto: x.proto,
+            payload: probe_payload(&x.payload)?,
+            name: x.name,
+            rarity: x.rarity,
+            ports: x.ports,
+            tls_ports: x.tls_ports,
+            total_wait: match x.wait_total_ms.into_inner() {
+                wait if wait.as_millis() == 0 => DEFAULT_WAIT,
+                wait => wait
+            },
+            wrapped_wait: match x.wait_wrapped_ms.into_inner() {
+                wait if wait.as_millis() == 0 => DEFAULT_WRAPPED_WAIT,
+                wait => wait
+            },
+            fallback: Vec::new(),
+            lookup_set: AlignedSet::new(x.matches)?
+        };
+
+        this.tls_ports.shrink_to_fit();
+        this.ports.shrink_to_fit();
+        this.name.shrink_to_fit();
+        this.payload.shrink_to_fit();
+
+        Ok(this)
+    }
+
+    pub async fn from_file(path: &str) -> Result<Self, FileError> {
+        let mut buf = Vec::new();
+        parse(path, &mut buf).await?;
+        Self::new(buf).map_err(|e| FileError::new(Meta::new(path), e))
+    }
+
+    pub fn new(mut buf: Vec<ProbeExpr>) -> Result<Self, Error> {
+        // `Exclude` shows up before the first probe,
+        // which leaves it on a nameless entry
+        let exclude = buf.iter_mut()
+            .flat_map(|probe| probe.exclude.drain(..))
+            .collect();
+        buf.retain(|probe| !probe.name.is_empty());
+
+        for (i, probe) in buf.iter_mut().enumerate() {
+            probe.load_ord = i;
+        }
+
+        // sort buffer by rarity, and then by load order
+        buf.sort_by(|a, b| {
+            // so this little condition should
+            // put NULL as index 0
+            if a.name == "NULL" {
+                return Ordering::Less
+            }
+
+            else if b.name == "NULL" {
+                return Ordering::Greater
+            }
+
+            // in all other cases, we'll sort on rarity, and then if equal, then load order
+            a.rarity.cmp(&b.rarity).then(a.load_ord.cmp(&b.load_ord))
+        });
+
+        let fallbacks: Vec<Vec<String>> = buf.iter()
+            .map(|probe| probe.fallback.clone())
+            .collect();
+
+        let mut name_map = HashMap::new();
+        let mut inner = Vec::with_capacity(buf.len());
+
+        for (i, probe) in buf.into_iter().enumerate() {
+            let link = Self::inner_new(probe)?;
+
+            // tcp and udp probes share names ("RPCCheck")
+            if name_map.insert((link.name.clone(), is_tcp(&link.proto)), i).is_some() {
+                return Err(Error::ParseError(format!("duplicate probe name found: {}", link.name)))
+            }
+            inner.push(link);
+        }
+
+        if inner.first().map(|x| x.name.as_str()) != Some("NULL") {
+            return Err(Error::ParseError("no NULL probe declared".to_string()))
+        }
+
+        // fallbacks pointing at probes that don't exist are dropped,
+        // the NULL probe is tried last for every tcp probe anyways
+        for (i, names) in fallbacks.into_iter().enumerate() {
+            let tcp = is_tcp(&inner[i].proto);
+            inner[i].fallback = names.iter()
+                .filter_map(|name| name_map.get(&(name.clone(), tcp)).copied())
+                .filter(|fallback| *fallback != i)
+                .collect();
+        }
+
+        // as far as we're concerned,
+        // this is now a flat list of probes that are ordered
+        // first from rarity, then load order
+        Ok(Self {
+            inner,
+            name_map,
+            exclude,
+        })
+    }
+
+    pub fn null(&self) -> &Link {
+        &self.inner[0]
+    }
+
+    pub fn get(&self, name: &str, proto: Protocol) -> Option<&Link> {
+        self.name_map.get(&(name.to_string(), is_tcp(&proto)))
+            .map(|i| &self.inner[*i])
+    }
+
+    /// Ports nmap doesn't probe (`Exclude`), printers print whatever they're sent
+    pub fn is_excluded(&self, port: u16) -> bool {
+        self.exclude.iter().any(|x| x.contains(port))
+    }
+
+    /// Probes to send to `port` after the NULL probe, in the order to send them.
+    /// Probes registered to the port come first, and are used regardless of their rarity,
+    /// the rest follow in order of rarity, up to `intensity`
+    pub fn select(&self, proto: Protocol, port: u16, intensity: u8) -> Vec<&Link> {
+        let candidates = self.inner[1..].iter()
+            .filter(|link| is_tcp(&link.proto) == is_tcp(&proto));
+
+        let (mut registered, rest): (Vec<&Link>, Vec<&Link>) = candidates
+            .partition(|link| link.registered(port));
+
+        registered.extend(rest.into_iter().filter(|link| link.rarity <= intensity));
+        registered
+    }
+
+    /// Matches `response` against the match lines of `link`, then its fallbacks,
+    /// and for tcp probes, the NULL probe.
+    ///
+    /// The first hard match is returned,
+    /// otherwise the first softmatch.
+    /// Once a softmatch is known (`service`), only matches for that service are considered
+    pub fn identify(&self, link: &Link, response: &[u8], service: Option<&str>) -> Option<ServiceMatch> {
+        let mut chain = vec![link];
+        chain.extend(link.fallback.iter().map(|i| &self.inner[*i]));
+
+        if is_tcp(&link.proto) && link.name != "NULL" {
+            chain.push(self.null());
+        }
+
+        let mut soft = None;
+        for link in chain {
+            match link.lookup_set.match_response(response, service) {
+                Some(found) if !found.soft => return Some(found),
+                Some(found) if soft.is_none() => soft = Some(found),
+                _ => {}
+            }
+        }
+
+        soft
+    }
+
+    /// Every link, NULL first, then rarity order
+    pub fn iter(&self) -> impl Iterator<Item=&Link> {
+        self.inner.iter()
+    }
+
+    /// Match lines that couldn't be compiled, and are never tried
+    pub fn skipped(&self) -> Vec<SkippedPattern> {
+        self.inner.iter()
+            .flat_map(|link| link.lookup_set.skipped.iter().map(move |(service, pattern, reason)| SkippedPattern {
+                probe: link.name.clone(),
+                service: service.clone(),
+                pattern: pattern.clone(),
+                reason: reason.clone(),
+            }))
+            .collect()
+    }
+}
+
+#[inline]
+fn is_tcp(proto: &Protocol) -> bool {
+    matches!(proto, Protocol::TCP)
+}
 
 
 // This data structure is used for matching regex patterns expressed inside `nmap-service-probes`.
-// It allows us to store regex patterns inside a set, and their respective partner as 
-// 
+// It allows us to store regex patterns inside a set, and their respective partner as
+//
 // this grouping is aligned so that indexes in `self.patterns` also correlate to `self.map`
 // where information about the response's data capture
-// #[derive(Debug)]
-// pub struct AlignedSet {
-//     patterns: RegexSet,
-//     map: Vec<MatchLineExpr>
-// }
-
-// impl AlignedSet {
-//     pub fn match_response<'a>(&'a self, input_buf: &[u8], out_buf: &mut Vec<&'a MatchLineExpr>) {
-//         self.patterns
-//             .matches(input_buf)
-//             .into_iter()
-//             .for_each(|i| {
-//                 out_buf.push(self.map.get(i).unwrap())
-//             });
-//     }
-
-//     // new(cpe: CPE, name: String, directive: Directive)
-//     pub fn new(patterns: Vec<MatchLineExpr>) -> Result<AlignedSet, regex::Error> {
-//         // align two buffers so that RegexSet's index correlates with
-//         // 
-//         // -- self.patterns
-//         // -- self.cpe_lookup
-            
-//         let mut regex_buf = Vec::new();
-//         let mut mapping = Vec::new();
-        
-//         for item in patterns {
-//             regex_buf.push(item.pattern.schematic.clone());
-//             mapping.push(item);
-//         }
-
-//         regex_buf.shrink_to_fit(); 
-//         mapping.shrink_to_fit();
-        
-//         Ok(AlignedSet {
-//             patterns: RegexSet::new(regex_buf)?,
-//             map: mapping
-//         })
-//     }
-
-//     fn iter<'s>(&'s self) -> AlignedSetIter<'s> {
-//         AlignedSetIter {
-//             idx: 0,
-//             set: self
-//         }
-//     }
-// }
-
-// struct AlignedSetIter<'set> {
-//     idx: usize,
-//     set: &'set AlignedSet
-// }
-
-// impl<'s> Iterator for AlignedSetIter<'s> {
-//     type Item=(&'s str, &'s MatchLineExpr);
-
-//     fn next(&mut self) -> Option<Self::Item> {
-//         match self.set.map.iter().nth(self.idx) {
-//             Some(match_expr) => {
-//                 let pattern = self.set
-//                     .patterns
-//                     .patterns()[self.idx]
-//                     .as_str();
-                
-//                 self.idx += 1;
-//                 Some((pattern, match_expr))
-//             }
-//             None => None
-//         }
-//     }
-// }
-
-
-// #[cfg(test)]
-// mod test {
-//     use super::*;
-//     use super::super::parser::parse;
-
-//     #[tokio::test]
-//     async fn align_set_behaves() {
-//         let mut buf = Vec::new();
-//         parse("/usr/share/nmap/nmap-service-probes", &mut buf).await.unwrap();
-//         let probes = ChainedProbes::new(buf).unwrap();
-        
-//         //eprintln!("{:#?}", probes);
-//         //assert!(false)
-//     }
-// }
+//
+// Patterns are perl flavored, the ones the regex crate can't express
+// (backreferences, lookarounds) are left out of the set.
+#[derive(Debug)]
+pub struct AlignedSet {
+    patterns: RegexSet,
+    compiled: Vec<Regex>,
+    map: Vec<MatchLineExpr>,
+    // service, pattern, and why it didn't compile
+    skipped: Vec<(String, String, String)>,
+}
+
+impl AlignedSet {
+    pub fn match_response(&self, input_buf: &[u8], service: Option<&str>) -> Option<ServiceMatch> {
+        let mut soft = None;
+
+        for i in self.patterns.matches(input_buf).into_iter() {
+            let expr = &self.map[i];
+            if service.map(|name| name != expr.name).unwrap_or(false) {
+                continue
+            }
+
+            let captures = match self.compiled[i].captures(input_buf) {
+                Some(captures) => captures,
+                None => continue
+            };
+
+            let groups: Vec<Match> = captures.iter()
+                .skip(1)
+                .map(|group| group.unwrap_or_else(|| EMPTY.find(b"").unwrap()))
+                .collect();
+
+            let found = service_match(expr, &groups);
+            if !found.soft {
+                return Some(found)
+            }
+            else if soft.is_none() {
+                soft = Some(found)
+            }
+        }
+
+        soft
+    }
+
+    // new(cpe: CPE, name: String, directive: Directive)
+    pub fn new(patterns: Vec<MatchLineExpr>) -> Result<AlignedSet, Error> {
+        // align two buffers so that RegexSet's index correlates with
+        //
+        // -- self.patterns
+        // -- self.map
+        let mut regex_buf = Vec::new();
+        let mut compiled = Vec::new();
+        let mut mapping = Vec::new();
+        let mut skipped = Vec::new();
+
+        for item in patterns {
+            let pattern = translate(&item);
+
+            match builder(&pattern).build() {
+                Ok(regex) => {
+                    regex_buf.push(pattern);
+                    compiled.push(regex);
+                    mapping.push(item);
+                }
+                Err(e) => skipped.push((item.name, pattern, e.to_string())),
+            }
+        }
+
+        regex_buf.shrink_to_fit();
+        mapping.shrink_to_fit();
+
+        let patterns = RegexSetBuilder::new(regex_buf)
+            .unicode(false)
+            .size_limit(SIZE_LIMIT)
+            .dfa_size_limit(SIZE_LIMIT)
+            .build()
+            .map_err(|e| Error::ParseError(e.to_string()))?;
+
+        Ok(AlignedSet {
+            patterns,
+            compiled,
+            map: mapping,
+            skipped,
+        })
+    }
+}
+
+fn builder(pattern: &str) -> RegexBuilder {
+    let mut builder = RegexBuilder::new(pattern);
+    builder.unicode(false).size_limit(SIZE_LIMIT);
+    builder
+}
+
+/// nmap's regex flavor into the regex crate's.
+/// Flags become inline flags, and `\0` is spelled out,
+/// the regex crate would otherwise read it as the start of a backreference
+fn translate(expr: &MatchLineExpr) -> String {
+    let mut pattern = String::with_capacity(expr.pattern.schematic.len() + 8);
+
+    // m/.../s lets `.` match newlines, m/.../i ignores case
+    let flags: String = expr.pattern.flags.iter()
+        .filter_map(|flag| match flag {
+            Flags::CaseSensitive => Some('s'),
+            Flags::IgnoreWhiteSpace => Some('i'),
+            Flags::UNIT => None
+        })
+        .collect();
+
+    if !flags.is_empty() {
+        pattern.push_str(&format!("(?{})", flags));
+    }
+
+    let mut chars = expr.pattern.schematic.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('0') => pattern.push_str("\\x00"),
+                Some(escaped) => {
+                    pattern.push('\\');
+                    pattern.push(escaped);
+                }
+                None => pattern.push('\\')
+            },
+            c => pattern.push(c)
+        }
+    }
+
+    pattern
+}
+
+fn service_match(expr: &MatchLineExpr, groups: &[Match]) -> ServiceMatch {
+    // a field referencing a group that doesn't exist is left out, not the whole match
+    let field = |field: &Option<DataField>| field.as_ref()
+        .and_then(|field| field.interpret(groups).ok())
+        .filter(|value| !value.is_empty());
+
+    let info = &expr.service_info;
+
+    ServiceMatch {
+        name: expr.name.clone(),
+        product: field(&info.product_name),
+        version: field(&info.version),
+        info: field(&info.info),
+        hostname: field(&info.hostname),
+        operating_system: field(&info.operating_system),
+        device_type: field(&info.device_type),
+        cpe: expr.cpe.iter()
+            .filter_map(|cpe| cpe.render(groups).ok())
+            .collect(),
+        soft: expr.directive == Directive::SoftMatch,
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn database() -> String {
+        format!("{}/../share/nmap/nmap-service-probes", env!("CARGO_MANIFEST_DIR"))
+    }
+
+    #[tokio::test]
+    async fn chained_probes_order() {
+        let probes = ChainedProbes::from_file(&database()).await.unwrap();
+
+        assert_eq!(probes.null().name, "NULL");
+        assert!(probes.is_excluded(9100));
+        assert_eq!(probes.null().wrapped_wait(), Duration::from_millis(3000));
+
+        let selected = probes.select(Protocol::TCP, 80, 1);
+        assert_eq!(selected[0].name, "GetRequest");
+        assert!(selected.iter().all(|link| matches!(link.proto, Protocol::TCP)));
+
+        // registered probes go first no matter their rarity
+        let http_options = probes.get("HTTPOptions", Protocol::TCP).unwrap();
+        assert!(http_options.rarity > 1);
+        assert!(selected.iter().any(|link| link.name == "HTTPOptions"));
+        assert!(!probes.select(Protocol::TCP, 1, 1).iter().any(|link| link.name == "HTTPOptions"));
+
+        // perl reads a lone `{` as a literal, the regex crate doesn't
+        let skipped = probes.skipped();
+        assert!(skipped.iter().any(|x| x.probe == "NULL" && x.service == "calibre-json"));
+        assert!(skipped.iter().all(|x| !x.reason.is_empty()));
+    }
+
+    #[tokio::test]
+    async fn identify_responses() {
+        let probes = ChainedProbes::from_file(&database()).await.unwrap();
+
+        let ssh = probes.identify(probes.null(), b"SSH-2.0-OpenSSH_8.9p1 Ubuntu-3ubuntu0.1\r\n", None).unwrap();
+        assert_eq!(ssh.name, "ssh");
+        assert_eq!(ssh.product.as_deref(), Some("OpenSSH"));
+        assert_eq!(ssh.version.as_deref(), Some("8.9p1 Ubuntu 3ubuntu0.1"));
+        assert!(ssh.cpe.iter().any(|cpe| cpe.starts_with("cpe:/a:openbsd:openssh")));
+
+        let get = probes.get("GetRequest", Protocol::TCP).unwrap();
+        let http = probes.identify(get, b"HTTP/1.0 200 OK\r\nServer: nginx/1.18.0\r\n\r\n", None).unwrap();
+        assert_eq!(http.name, "http");
+        assert_eq!(http.product.as_deref(), Some("nginx"));
+        assert_eq!(http.version.as_deref(), Some("1.18.0"));
+
+        assert_eq!(probes.identify(get, b"\x7f\xde\xad\xbe\xef nothing to see here \x00\x01\x02", None), None);
+    }
+}
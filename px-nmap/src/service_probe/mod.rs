@@ -2,4 +2,4 @@ mod linked;
 pub mod parser;
 pub mod hex;
 
-//pub use linked::ChainedProbes;
+pub use linked::{ChainedProbes, Link, ServiceMatch, SkippedPattern, DEFAULT_INTENSITY, DEFAULT_WAIT, DEFAULT_WRAPPED_WAIT};
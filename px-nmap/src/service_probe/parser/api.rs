@@ -75,12 +75,13 @@ fn probe_declare_expr(line: &str, lex: &mut Lexer<Token>, expr: &mut ProbeExpr)
         _ => return Err(Error::ExpectedToken(Token::Word))
     };
 
-    expr.name = match lex.next() {
-        Some(Token::Word) => String::from(lex.slice()),
-        _ => return Err(Error::ExpectedToken(Token::Word))
-    };
-    
-    expr.payload = (&line[lex.span().end+1..]).trim().to_string();
+    // names aren't limited to `Token::Word` ("DNS-SD", "Quake1_server_info")
+    let rest = line[lex.span().end..].trim_start();
+    let name_end = rest.find(char::is_whitespace)
+        .ok_or(Error::ExpectedToken(Token::Word))?;
+
+    expr.name = rest[..name_end].to_string();
+    expr.payload = rest[name_end..].trim().to_string();
 
     if expr.payload.len() > 0 {
         Ok(())
@@ -92,8 +93,9 @@ fn probe_declare_expr(line: &str, lex: &mut Lexer<Token>, expr: &mut ProbeExpr)
 }
 
 pub async fn parse(path: &str, buf: &mut Vec<ProbeExpr>) -> Result<(), FileError> {
-    let fd = File::open(path).await.unwrap();
     let mut bookkeeping = Meta::new(path);
+    let fd = File::open(path).await
+        .map_err(|e| FileError::new(bookkeeping.clone(), e.into()))?;
     
     let mut fd = BufReader::new(fd);
     let mut line = String::new();
@@ -189,7 +191,7 @@ fn parse_line(line: &str, expr: &mut ProbeExpr, lex: &mut Lexer<Token>, meta: &m
             Token::Ports => {
                 while let Some(token) = lex.next() {
                     match token {
-                        Token::Num | Token::Rng => { expr.tls_ports.push(PortInput::from_str(lex.slice())?); }
+                        Token::Num | Token::Rng => { expr.ports.push(PortInput::from_str(lex.slice())?); }
                         Token::Error => {}
                         _ => return Err(Error::ExpectedToken(Token::Rng))   
                     }
@@ -205,13 +207,18 @@ fn parse_line(line: &str, expr: &mut ProbeExpr, lex: &mut Lexer<Token>, meta: &m
                 }
             }
 
-            Token::Fallback => match lex.next() {
-                Some(Token::Word) => expr.fallback.extend(
-                    lex.slice()
-                        .split(",")
-                        .map(|s| s.to_string())
-                    ),
-                 _ => return Err(Error::ExpectedToken(Token::Num))
+            // "fallback GetRequest,GenericLines", commas are skipped by the lexer
+            Token::Fallback => {
+                while let Some(token) = lex.next() {
+                    match token {
+                        Token::Word => expr.fallback.push(lex.slice().to_string()),
+                        _ => return Err(Error::ExpectedToken(Token::Word))
+                    }
+                }
+
+                if expr.fallback.is_empty() {
+                    return Err(Error::ExpectedToken(Token::Word))
+                }
             },
 
             token => return Err(Error::ParseError(format!(
@@ -232,19 +232,22 @@ impl HelperFunction {
     }
 
     fn run(&self, matches: &[Match<'_>]) -> Result<String, Error> {
+        // the probe file may reference a group the pattern doesn't have
+        let select = |idx: usize| matches.get(idx).ok_or_else(|| Error::ParseError(format!(
+            "match selected not found (${})", idx + 1
+        )));
+
         let string = match self {
             HelperFunction::Print(idx) => 
                 String::from_utf8_lossy(
-                    matches.get(*idx)
-                        .unwrap()
-                        .as_bytes()
+                    select(*idx)?.as_bytes()
                 )
                     .replace("�", "")
                     .chars()
                     .filter(|c| (*c as u8) > 31 && 128 > (*c as u8)).collect(),
             
             HelperFunction::Substitute(idx, original, replacement) =>
-                String::from_utf8_lossy(matches.get(*idx).unwrap().as_bytes()).replace(original, replacement),
+                String::from_utf8_lossy(select(*idx)?.as_bytes()).replace(original, replacement),
             
              HelperFunction::UnpackInt(index, endianness) => {
                 let serializer = bincode::DefaultOptions::new()
@@ -252,10 +255,10 @@ impl HelperFunction {
                 
                 let num: u64 = match endianness {
                     EndianSymbol::Big => { 
-                        serializer.with_big_endian().deserialize(dbg!(matches.get(*index).unwrap().as_bytes()))?
+                        serializer.with_big_endian().deserialize(select(*index)?.as_bytes())?
                     },
                     EndianSymbol::Little => {
-                        serializer.with_little_endian().deserialize(dbg!(matches.get(*index).unwrap().as_bytes()))?
+                        serializer.with_little_endian().deserialize(select(*index)?.as_bytes())?
                     }
                 };
 
@@ -296,9 +299,6 @@ impl DataField {
             let span = lexer.span();
             // places head from self.schematic into ret
             ret.push_str(&self.schematic[last..span.start]);
-            dbg!(&ret);
-            dbg!(&token);
-            dbg!(&lexer.slice());
             
             last = span.end;
 
@@ -409,6 +409,12 @@ impl CPExpr {
         self.0.interpret(matches)?.parse()
     }
 
+    /// The cpe with its substitutions made, as written by nmap ("cpe:/a:openbsd:openssh:8.9")
+    pub fn render(&self, matches: &[Match]) -> Result<String, Error> {
+        let cpe = self.0.interpret(matches)?;
+        Ok(format!("cpe:/{}", cpe.trim_end_matches("/a").trim_end_matches('/')))
+    }
+
     pub fn into_inner(self) -> String {
         self.0.into_inner()
     }
@@ -518,4 +524,15 @@ mod tests {
         let constructed = field.interpret(&matches[..]).unwrap();
         assert_eq!(constructed, "hello 0")
     }
+
+    #[test]
+    fn intrepret_datafield_missing_group() {
+        let pattern = regex::bytes::Regex::new(r"(.*)").unwrap();
+        let matches: Vec<Match> = pattern.find_iter(b"adam").collect();
+
+        for field in &["hello $P(2)", "hello $SUBST(2,\"_\",\".\")", "hello $I(2,\">\")"] {
+            let err = DataField::new(field).interpret(&matches[..]);
+            assert!(matches!(err, Err(Error::ParseError(reason)) if reason.contains("not found")));
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,191 @@
+use std::{
+	collections::{HashMap, BTreeSet},
+	net::{IpAddr, SocketAddr},
+	path::Path,
+	fs::File,
+	io::BufReader,
+};
+
+use serde::Serialize;
+use px_core::model::State as NetState;
+
+use crate::cli::{
+	error::Error,
+	output::{HostReport, PortReport, ServiceInfo},
+};
+
+/// Result set as written by `--format json`
+pub type Results = HashMap<IpAddr, HostReport>;
+
+pub fn load_results<P: AsRef<Path>>(path: P) -> Result<Results, Error> {
+	let reader = BufReader::new(File::open(path)?);
+	Ok(serde_json::from_reader(reader)?)
+}
+
+/// Differences going from one result set to another
+#[derive(Debug, Default, Serialize)]
+pub struct ResultsDiff {
+	pub added_hosts: Vec<IpAddr>,
+	pub removed_hosts: Vec<IpAddr>,
+	/// every port that appeared, disappeared, changed state, or changed service.
+	/// Ports of added/removed hosts are included
+	pub ports: Vec<PortDiff>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PortDiff {
+	pub addr: SocketAddr,
+	pub before: Option<NetState>,
+	pub after: Option<NetState>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub service_before: Option<ServiceInfo>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub service_after: Option<ServiceInfo>,
+}
+
+impl ResultsDiff {
+	pub fn new(old: &Results, new: &Results) -> Self {
+		let mut diff = Self::default();
+
+		let hosts: BTreeSet<&IpAddr> = old.keys().chain(new.keys()).collect();
+
+		for host in hosts {
+			let before = old.get(host);
+			let after = new.get(host);
+
+			match (before, after) {
+				(None, Some(_)) => diff.added_hosts.push(*host),
+				(Some(_), None) => diff.removed_hosts.push(*host),
+				_ => {}
+			}
+
+			diff.ports.extend(port_diffs(*host, before, after));
+		}
+
+		diff
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.added_hosts.is_empty() && self.removed_hosts.is_empty() && self.ports.is_empty()
+	}
+}
+
+fn port_diffs(host: IpAddr, before: Option<&HostReport>, after: Option<&HostReport>) -> Vec<PortDiff> {
+	fn index(report: Option<&HostReport>) -> HashMap<u16, &PortReport> {
+		report
+			.map(|report| report.ports.iter().map(|port| (port.port, port)).collect())
+			.unwrap_or_default()
+	}
+
+	let (before, after) = (index(before), index(after));
+	let ports: BTreeSet<&u16> = before.keys().chain(after.keys()).collect();
+
+	ports.into_iter()
+		.filter_map(|port| {
+			let (old, new) = (before.get(port), after.get(port));
+
+			let diff = PortDiff {
+				addr: SocketAddr::new(host, *port),
+				before: old.map(|x| x.state),
+				after: new.map(|x| x.state),
+				service_before: old.and_then(|x| x.service.clone()),
+				service_after: new.and_then(|x| x.service.clone()),
+			};
+
+			if diff.before != diff.after || diff.service_before != diff.service_after {
+				Some(diff)
+			}
+			else {
+				None
+			}
+		})
+		.collect()
+}
+
+impl std::fmt::Display for PortDiff {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		fn side(state: &Option<NetState>, service: &Option<ServiceInfo>) -> String {
+			match (state, service) {
+				(None, _) => "-".to_string(),
+				(Some(state), None) => state.to_string(),
+				(Some(state), Some(service)) => format!("{} ({})", state, service),
+			}
+		}
+
+		write!(
+			f, "{}\t{} -> {}",
+			self.addr,
+			side(&self.before, &self.service_before),
+			side(&self.after, &self.service_after)
+		)
+	}
+}
+
+impl std::fmt::Display for ResultsDiff {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		for host in &self.added_hosts {
+			writeln!(f, "+ {}", host)?;
+		}
+
+		for host in &self.removed_hosts {
+			writeln!(f, "- {}", host)?;
+		}
+
+		for port in &self.ports {
+			writeln!(f, "~ {}", port)?;
+		}
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn host(ports: Vec<PortReport>) -> HostReport {
+		HostReport { ports, timed_out: false }
+	}
+
+	#[test]
+	fn diff_results() {
+		let kept: IpAddr = "10.0.0.1".parse().unwrap();
+		let gone: IpAddr = "10.0.0.2".parse().unwrap();
+		let new: IpAddr = "10.0.0.3".parse().unwrap();
+
+		let ssh = |version: &str| ServiceInfo {
+			name: "ssh".to_string(),
+			product: Some("OpenSSH".to_string()),
+			version: Some(version.to_string()),
+			cpe: vec![],
+		};
+
+		let mut old_ssh = PortReport::new(22, NetState::Open);
+		old_ssh.service = Some(ssh("7.4"));
+		let mut new_ssh = PortReport::new(22, NetState::Open);
+		new_ssh.service = Some(ssh("8.9"));
+
+		let mut old = Results::new();
+		old.insert(kept, host(vec![old_ssh, PortReport::new(80, NetState::Open), PortReport::new(443, NetState::Closed)]));
+		old.insert(gone, host(vec![PortReport::new(80, NetState::Open)]));
+
+		let mut new_results = Results::new();
+		new_results.insert(kept, host(vec![new_ssh, PortReport::new(80, NetState::Open), PortReport::new(443, NetState::Open)]));
+		new_results.insert(new, host(vec![]));
+
+		let diff = ResultsDiff::new(&old, &new_results);
+
+		assert_eq!(diff.added_hosts, vec![new]);
+		assert_eq!(diff.removed_hosts, vec![gone]);
+		assert_eq!(diff.ports.len(), 3);
+
+		assert_eq!(diff.ports[0].addr, SocketAddr::new(kept, 22));
+		assert_eq!(diff.ports[0].service_after, Some(ssh("8.9")));
+		assert_eq!(diff.ports[1].addr, SocketAddr::new(kept, 443));
+		assert_eq!((diff.ports[1].before, diff.ports[1].after), (Some(NetState::Closed), Some(NetState::Open)));
+		assert_eq!(diff.ports[2].addr, SocketAddr::new(gone, 80));
+		assert_eq!(diff.ports[2].after, None);
+
+		assert!(ResultsDiff::new(&old, &old).is_empty());
+	}
+}
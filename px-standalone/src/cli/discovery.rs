@@ -0,0 +1,46 @@
+use std::{
+	net::{IpAddr, SocketAddr},
+	time::Duration,
+	fmt::Debug,
+	marker::Unpin
+};
+
+use px_core::pool::{CRON, JobCtrl, Destination};
+
+use crate::cli::{
+	menu::run_pool,
+	error::Error,
+	output::CastAs,
+	input::{combine::Feeder, parser::AddressInput},
+};
+
+/// Ports "pinged" during host discovery when `--ping-ports` isn't given
+pub const DEFAULT_PING_PORTS: [u16; 5] = [80, 443, 22, 445, 3389];
+
+/// Runs `H` against every job `pinger` produces, and returns every host that answered at least once,
+/// once each and in address order, whatever order they answered in.
+/// Any `JobCtrl::Return` counts as an answer, it's up to the handler to decide what proves a host is alive.
+pub async fn discover_hosts<'a, H, R, S>(pinger: &mut Feeder<'a>, timeout: Duration) -> Result<Vec<AddressInput>, Error>
+where
+	H: CRON<Response = R, State = S> + Unpin,
+	R: Send + Sync + Clone + Debug + 'static,
+	S: Send + Sync + Clone + Debug + Destination + 'static + From<SocketAddr> + CastAs<SocketAddr>,
+{
+	let mut alive: Vec<IpAddr> = Vec::new();
+
+	run_pool::<H, R, S, _, _>(pinger, timeout, None, S::from, |jobs_done, _timed_out| {
+		for (sig, state) in jobs_done {
+			if let JobCtrl::Return(_netstate, _resp) = sig {
+				alive.push(state.cast().ip());
+			}
+		}
+	}).await?;
+
+	// every port of a host may have answered
+	alive.sort_unstable();
+	alive.dedup();
+
+	Ok(alive.into_iter()
+		.map(AddressInput::Singleton)
+		.collect())
+}
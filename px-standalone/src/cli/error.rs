@@ -1,4 +1,5 @@
 use px_core::error::Error as LibError;
+use px_nmap::service_probe::parser::FileError;
 
 #[derive(Debug)]
 enum OutputError {
@@ -19,6 +20,23 @@ pub enum Error {
     CliError(String),
     CliInputError(InputError),
     CliOutputError(OutputError),
+    /// the service probe database couldn't be loaded
+    ProbeError(FileError),
+    /// a job failed in a way every other job would (a broken pivot chain),
+    /// the scan was stopped
+    ScanAborted(String),
+}
+
+impl From<LibError> for Error {
+    fn from(x: LibError) -> Self {
+        Self::CoreError(x)
+    }
+}
+
+impl From<FileError> for Error {
+    fn from(x: FileError) -> Self {
+        Self::ProbeError(x)
+    }
 }
 
 impl From<std::io::Error> for Error {
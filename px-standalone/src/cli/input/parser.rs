@@ -65,9 +65,20 @@ impl std::str::FromStr for ScanMethod {
             "open" => ScanMethod::Complete { wait_flag: true },
             "connect" => ScanMethod::Complete { wait_flag: false },
             "socks" => ScanMethod::Socks,
+            "http-proxy" => ScanMethod::HttpProxy,
+            "udp" => ScanMethod::Udp,
+            "banner" => ScanMethod::Banner,
+            "http" => ScanMethod::Http,
+            "tls" => ScanMethod::Tls,
+            "ssh" => ScanMethod::Ssh,
+            "dns" => ScanMethod::Dns,
+            "smb" => ScanMethod::Smb,
+            "datastore" => ScanMethod::Datastore,
+            "ftp" => ScanMethod::Ftp,
+            "smtp" => ScanMethod::Smtp,
 
-            "vscan" | "version-scan" => unimplemented!(),
-            "syn" => unimplemented!(),
+            "vscan" | "version-scan" => ScanMethod::VScan,
+            "syn" => ScanMethod::Syn,
             _ => return Err(Error::CliError("unrecognized scan method".to_string()))
         };
 
@@ -79,7 +90,8 @@ impl std::str::FromStr for ScanMethod {
 pub enum ScanMethod {
     /// complete a connection to the target,
     /// and if the wait flag (`wait_flag`) is set to true, 
-    /// it will wait until the peer closes the connection
+    /// it will wait until the peer closes the connection,
+    /// or the timeout runs out, noting whether the peer sent anything
     /// 
     /// if set to false, it will close the connection 
     /// immediately after the connection completes
@@ -94,6 +106,122 @@ pub enum ScanMethod {
     VScan,
 
     Socks,
+
+    /// absolute-URI GET and CONNECT through the target, towards the canary
+    HttpProxy,
+
+    /// udp payloads/empty datagrams,
+    /// ICMP port unreachable means closed, silence open|filtered
+    Udp,
+
+    /// connect and capture whatever the server sends,
+    /// optionally after sending it a payload
+    Banner,
+
+    /// GET / over http or https, status, headers, title,
+    /// and whether HTTP/2 is offered
+    Http,
+
+    /// certificate chain, negotiated version/cipher,
+    /// and which tls versions are accepted
+    Tls,
+
+    /// identification, offered algorithms and the host key
+    Ssh,
+
+    /// udp then tcp, CHAOS version.bind/hostname.bind,
+    /// and whether the server resolves outside names
+    Dns,
+
+    /// SMB2/SMB1 dialects, signing, and the names/OS build
+    /// in the server's NTLM challenge
+    Smb,
+
+    /// redis, memcached, mongodb, elasticsearch, postgresql and mysql,
+    /// their version and whether they let anyone in without credentials
+    Datastore,
+
+    /// greeting, FEAT/SYST, anonymous login,
+    /// and whether AUTH TLS is offered
+    Ftp,
+
+    /// greeting, EHLO's extensions (STARTTLS, AUTH, SIZE),
+    /// and with `--smtp-relay` whether it relays for outside domains
+    Smtp,
+}
+
+/// Bytes given on the command line, understands
+/// `\r`, `\n`, `\t`, `\0`, `\\` and `\xNN` escapes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Payload(pub Vec<u8>);
+
+impl std::str::FromStr for Payload {
+    type Err = Error;
+
+    fn from_str(src: &str) -> Result<Payload, Self::Err> {
+        unescape(src).map(Payload)
+    }
+}
+
+fn unescape(src: &str) -> Result<Vec<u8>, Error> {
+    let mut payload = Vec::with_capacity(src.len());
+    let mut bytes = src.bytes();
+
+    while let Some(b) = bytes.next() {
+        if b != b'\\' {
+            payload.push(b);
+            continue
+        }
+
+        let escaped = match bytes.next() {
+            Some(b'r') => b'\r',
+            Some(b'n') => b'\n',
+            Some(b't') => b'\t',
+            Some(b'0') => 0,
+            Some(b'\\') => b'\\',
+            Some(b'x') => {
+                let digits = [bytes.next(), bytes.next()];
+                let hex = match digits {
+                    [Some(hi), Some(lo)] => String::from_utf8(vec![hi, lo]).ok(),
+                    _ => None
+                };
+
+                hex.and_then(|hex| u8::from_str_radix(&hex, 16).ok())
+                    .ok_or_else(|| Error::CliError("\\x takes two hex digits".to_string()))?
+            }
+            _ => return Err(Error::CliError(format!("unknown escape in payload {:?}", src)))
+        };
+
+        payload.push(escaped);
+    }
+
+    Ok(payload)
+}
+
+/// How hosts are checked for life before they're port scanned
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DiscoveryMethod {
+    /// TCP connect to each of the ping ports
+    Tcp,
+    /// ICMP echo over unprivileged ping sockets
+    Echo,
+    /// ICMP timestamp (IPv4 only), requires raw sockets
+    Timestamp,
+}
+
+impl std::str::FromStr for DiscoveryMethod {
+    type Err = Error;
+
+    fn from_str(src: &str) -> Result<DiscoveryMethod, Self::Err> {
+        let opt = match src {
+            "tcp" => DiscoveryMethod::Tcp,
+            "icmp" | "echo" => DiscoveryMethod::Echo,
+            "timestamp" => DiscoveryMethod::Timestamp,
+            _ => return Err(Error::CliError("unrecognized discovery method".to_string()))
+        };
+
+        Ok(opt)
+    }
 }
 
 #[derive(Debug, Eq, PartialEq, Hash, Copy, Clone, Serialize)]
@@ -1,34 +1,37 @@
-use crate::cli::{input::combine, output::OutputType, };
+use crate::cli::{input::combine, output::{OutputType, Report}, error::Error};
 
 use std::{
-	net::SocketAddr,
+	net::{SocketAddr, IpAddr},
 	time::Duration,
 	fmt::Debug,
 	marker::Unpin
 };
 
 use px_core::{
-	pool::{Worker, Pool, CRON},
+	pool::{Worker, Pool, CRON, JobCtrl, JobErr, Destination},
 	util::{Boundary, get_max_fd},
 };
 
 const TICK_NS: u64 = 500;
+/// Times a job is stashed and tried again (a blocked nic, a busy pivot) before it's given up on
+const MAX_RETRIES: u8 = 3;
 
-pub async fn fire_from_feeder<'a, J, R, S>(pool: &mut Pool<J, R, S>, queued: &mut Vec<S>, feed: &mut combine::Feeder<'a>) -> usize
+pub async fn fire_from_feeder<'a, J, R, S, T>(pool: &mut Pool<J, R, S>, queued: &mut Vec<S>, feed: &mut combine::Feeder<'a>, into_state: &T) -> usize
 where
     J: CRON<Response = R, State = S> + Unpin,
     R: Send + Sync + Clone + Debug + 'static,
-    S: Send + Sync + Clone + Debug + From<std::net::SocketAddr> + 'static
+    S: Send + Sync + Clone + Debug + Destination + 'static,
+    T: Fn(SocketAddr) -> S,
 {
 
     let mut sock_buf = Vec::with_capacity(4001);
     feed.generate_chunk(&mut sock_buf, 4000);
     
-    queued.extend(sock_buf.drain(..).map(|x| x.into()));
+    queued.extend(sock_buf.drain(..).map(into_state));
     let alloc_amt = pool.calc_new_spawns(queued.len());
     
     if alloc_amt > 0 {
-        let release_amt = pool.flush_stash(queued);
+        let release_amt = pool.flush_stash();
         let feed_amt;
 	
 		if release_amt >= alloc_amt {
@@ -41,7 +44,7 @@ where
         
         if feed_amt > 0 && !feed.is_done() {
             feed.generate_chunk(&mut sock_buf, feed_amt);
-            queued.extend(sock_buf.drain(..).map(|x| x.into()));
+            queued.extend(sock_buf.drain(..).map(into_state));
         }
         
         return pool.spawn(queued)
@@ -50,11 +53,40 @@ where
 }
 
 
-pub async fn run_handle<'a, H, R, S>(generator: &mut combine::Feeder<'a>, results: &mut OutputType, timeout: Duration)
+pub async fn run_handle<'a, H, R, S>(generator: &mut combine::Feeder<'a>, results: &mut OutputType, timeout: Duration, host_timeout: Option<Duration>) -> Result<(), Error>
+where
+	H: CRON<Response = R, State = S> + std::marker::Unpin,
+	R: Send + Sync + Clone + std::fmt::Debug + Report + 'static,
+    S: Send + Sync + Clone + std::fmt::Debug + Destination + 'static + From<SocketAddr> + crate::cli::output::CastAs<SocketAddr>,
+{
+	run_handle_as::<H, R, S, _>(generator, results, timeout, host_timeout, S::from).await
+}
+
+/// Same as `run_handle`, for states that can't be built from an address alone,
+/// every address `generator` produces is turned into a state by `into_state`
+pub async fn run_handle_as<'a, H, R, S, T>(generator: &mut combine::Feeder<'a>, results: &mut OutputType, timeout: Duration, host_timeout: Option<Duration>, into_state: T) -> Result<(), Error>
+where
+	H: CRON<Response = R, State = S> + std::marker::Unpin,
+	R: Send + Sync + Clone + std::fmt::Debug + Report + 'static,
+    S: Send + Sync + Clone + std::fmt::Debug + Destination + 'static + crate::cli::output::CastAs<SocketAddr>,
+	T: Fn(SocketAddr) -> S,
+{
+	run_pool::<H, R, S, _, _>(generator, timeout, host_timeout, into_state, |jobs_done, timed_out| {
+		results.handle(jobs_done);
+		results.handle_timeout(timed_out);
+	}).await
+}
+
+/// Drives a pool over everything `generator` produces,
+/// handing every batch of finished jobs, and hosts that ran out of time to `on_results`.
+/// Stops at the first `JobErr::Fatal`, without handing over its batch
+pub async fn run_pool<'a, H, R, S, T, F>(generator: &mut combine::Feeder<'a>, timeout: Duration, host_timeout: Option<Duration>, into_state: T, mut on_results: F) -> Result<(), Error>
 where
 	H: CRON<Response = R, State = S> + std::marker::Unpin,
 	R: Send + Sync + Clone + std::fmt::Debug + 'static,
-    S: Send + Sync + Clone + std::fmt::Debug + 'static + From<SocketAddr> + crate::cli::output::CastAs<SocketAddr>,
+    S: Send + Sync + Clone + std::fmt::Debug + Destination + 'static,
+	T: Fn(SocketAddr) -> S,
+	F: FnMut(&Vec<(JobCtrl<R>, S)>, &[IpAddr]),
 {
 	let mut buffer = Vec::new();
 
@@ -68,17 +100,21 @@ where
 			Worker::new(limit, timeout)
 		)
 	};
+	pool.set_host_timeout(host_timeout);
+	pool.set_max_retries(Some(MAX_RETRIES));
 
 	loop {
 		if !generator.is_done() {
-			fire_from_feeder(&mut pool, &mut buffer, generator).await;
+			fire_from_feeder(&mut pool, &mut buffer, generator, &into_state).await;
 		}
 		
 		let jobs_done = pool.tick(&mut buffer).await;		
+		fatal(&jobs_done)?;
 		
-		results.handle(&jobs_done);
+		on_results(&jobs_done, &pool.take_timed_out());
 		
-		if buffer.len() == 0 && generator.is_done() && pool.job_count() == 1 {
+		// stashed jobs are waited on too
+		if buffer.len() == 0 && generator.is_done() && pool.is_idle() {
 			break
 		}
 		// 
@@ -86,5 +122,17 @@ where
 		tokio::time::sleep(Duration::from_nanos(TICK_NS)).await;
 	}
 
-	results.handle(&pool.flush_channel());
-}
\ No newline at end of file
+	let jobs_done = pool.flush_channel();
+	fatal(&jobs_done)?;
+	on_results(&jobs_done, &pool.take_timed_out());
+	Ok(())
+}
+
+fn fatal<R, S>(jobs_done: &[(JobCtrl<R>, S)]) -> Result<(), Error> {
+	for (sig, _state) in jobs_done {
+		if let JobCtrl::Error(JobErr::Fatal(reason)) = sig {
+			return Err(Error::ScanAborted(reason.clone()))
+		}
+	}
+	Ok(())
+}
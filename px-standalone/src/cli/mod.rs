@@ -2,4 +2,7 @@ pub mod opt;
 pub mod menu;
 pub mod error;
 pub mod input;
-pub mod output;
\ No newline at end of file
+pub mod output;
+pub mod discovery;
+pub mod monitor;
+pub mod diff;
@@ -0,0 +1,27 @@
+use px_core::schedule::{Change, ChangeSink};
+
+use crate::cli::input::parser::Format;
+
+/// Prints every change between two monitoring runs to stdout
+pub struct ChangePrinter {
+	format: Format,
+}
+
+impl ChangePrinter {
+	pub fn new(format: Format) -> Self {
+		Self { format }
+	}
+}
+
+impl ChangeSink for ChangePrinter {
+	fn on_change(&mut self, run: usize, change: &Change) {
+		match self.format {
+			// one json object per line, so it can be tailed
+			Format::Json => println!(
+				"{}",
+				serde_json::json!({ "run": run, "change": change })
+			),
+			Format::Stdout | Format::Stream => println!("[{}] {}", run, change),
+		}
+	}
+}
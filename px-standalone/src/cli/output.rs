@@ -7,15 +7,127 @@ use std::{
 use px_core::{
 	model::{State as NetState},
 	pool::JobCtrl,
+	schedule::Snapshot,
 };
 
 use crate::cli::input::parser;
-use serde::Serialize;
+use serde::{Serialize, Deserialize};
 
 #[derive(Debug, Serialize)]
 pub enum OutputType {
 	Stream,
-	Map(HashMap<IpAddr, Vec<(u16, NetState)>>)
+	Map(HashMap<IpAddr, HostReport>)
+}
+
+/// Everything gathered about a single host
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct HostReport {
+	pub ports: Vec<PortReport>,
+	/// the host ran out of its time budget (`--host-timeout`),
+	/// `ports` only holds what was gathered before then
+	#[serde(default)]
+	pub timed_out: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PortReport {
+	pub port: u16,
+	pub state: NetState,
+	/// filled in by handlers that identify what's listening
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub service: Option<ServiceInfo>,
+	/// anything else the handler had to say (banners, proxy auth, ...)
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub details: Option<String>,
+	/// the handler's response in full, for handlers that gather more than fits in `details`
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub data: Option<serde_json::Value>,
+}
+
+impl PortReport {
+	pub fn new(port: u16, state: NetState) -> Self {
+		Self { port, state, service: None, details: None, data: None }
+	}
+
+	pub fn from_response<R: Report>(port: u16, state: NetState, response: &R) -> Self {
+		Self {
+			port,
+			state,
+			service: response.service(),
+			details: response.details(),
+			data: response.data(),
+		}
+	}
+}
+
+/// What a handler's response adds to its port in the output
+pub trait Report {
+	/// the service identified on the port
+	fn service(&self) -> Option<ServiceInfo> { None }
+
+	/// free form, printed next to the port
+	fn details(&self) -> Option<String> { None }
+
+	/// structured, only kept in serialized output
+	fn data(&self) -> Option<serde_json::Value> { None }
+}
+
+/// connect scans only tell if the port is open
+impl Report for SocketAddr {}
+
+/// handlers answer `None` for open ports that don't speak their protocol
+impl<T: Report> Report for Option<T> {
+	fn service(&self) -> Option<ServiceInfo> {
+		self.as_ref()?.service()
+	}
+
+	fn details(&self) -> Option<String> {
+		self.as_ref()?.details()
+	}
+
+	fn data(&self) -> Option<serde_json::Value> {
+		self.as_ref()?.data()
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServiceInfo {
+	pub name: String,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub product: Option<String>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub version: Option<String>,
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub cpe: Vec<String>,
+}
+
+impl std::fmt::Display for ServiceInfo {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}", self.name)?;
+		if let Some(product) = &self.product {
+			write!(f, " {}", product)?;
+		}
+		if let Some(version) = &self.version {
+			write!(f, " {}", version)?;
+		}
+		for cpe in &self.cpe {
+			write!(f, " {}", cpe)?;
+		}
+		Ok(())
+	}
+}
+
+impl std::fmt::Display for PortReport {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}\t{}", self.port, self.state)?;
+		if let Some(service) = &self.service {
+			write!(f, "\t{}", service)?;
+		}
+		if let Some(details) = &self.details {
+			write!(f, "\t{}", details)?;
+		}
+		Ok(())
+	}
 }
 
 
@@ -47,7 +159,7 @@ impl<T> CastAs<T> for T {
 impl OutputType {
 	pub fn handle<R, S>(&mut self, buf: &Vec<(JobCtrl<R>, S)>)
 	where
-		R: Debug,
+		R: Debug + Report,
 		S: CastAs<SocketAddr> + Debug
 	{
 		match self {
@@ -55,7 +167,7 @@ impl OutputType {
 				for (sig, state) in buf {
 					let sock = state.cast();
 					match sig {
-						JobCtrl::Return(netstate, _resp) => println!("{}\t{}\t{}", sock.ip(), sock.port(), netstate),	
+						JobCtrl::Return(netstate, resp) => println!("{}\t{}", sock.ip(), PortReport::from_response(sock.port(), *netstate, resp)),	
 						JobCtrl::Error(err) => eprintln!("unable to run [{}] {:?}:[{:?}]", sock, err, sig)
 					}
 				}
@@ -66,20 +178,16 @@ impl OutputType {
 					let sock = state.cast();
 					
 					let service = match sig {
-						JobCtrl::Return(netstate, _resp) => 
-							(sock.port(), *netstate),
+						JobCtrl::Return(netstate, resp) => 
+							PortReport::from_response(sock.port(), *netstate, resp),
 						JobCtrl::Error(_err) =>
-							(sock.port(),  NetState::Closed),
+							PortReport::new(sock.port(), NetState::Closed),
 					};
 
-					match map.get_mut(&sock.ip()) {
-						Some(buf) => { buf.push(service); },
-						None => { 
-							let mut buf = Vec::new();
-							buf.push(service);
-							map.insert(sock.ip(), buf);
-						}
-					}
+					map.entry(sock.ip())
+						.or_insert_with(HostReport::default)
+						.ports
+						.push(service);
 				}
 			}
 
@@ -87,4 +195,41 @@ impl OutputType {
 			
 		}
 	}
+
+	pub fn handle_timeout(&mut self, hosts: &[IpAddr]) {
+		match self {
+			OutputType::Stream => {
+				for host in hosts {
+					println!("{}\ttimed out", host);
+				}
+			},
+
+			OutputType::Map(map) => {
+				for host in hosts {
+					map.entry(*host)
+						.or_insert_with(HostReport::default)
+						.timed_out = true;
+				}
+			}
+		}
+	}
+
+	/// Result set gathered so far, `Stream` doesn't keep anything
+	pub fn snapshot(&self) -> Snapshot {
+		let mut snapshot = Snapshot::new();
+
+		if let OutputType::Map(map) = self {
+			for (ip, report) in map {
+				for port in &report.ports {
+					snapshot.record(
+						SocketAddr::new(*ip, port.port),
+						port.state,
+						port.service.as_ref().map(ToString::to_string)
+					);
+				}
+			}
+		}
+
+		snapshot
+	}
 }
@@ -0,0 +1,258 @@
+use px_core::{
+    pool::{JobCtrl, CRON, JobErr},
+    error::Error,
+    model::State,
+    wrapper::{NetworkInterface, TcpInterface},
+};
+
+use std::{
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use super::{handle_io_error, Job};
+use crate::cli::output::Report;
+
+/// Default time given to the server to speak first
+pub const DEFAULT_WAIT: Duration = Duration::from_secs(3);
+/// Most we'll keep from a single port
+pub const MAX_BANNER: usize = 4096;
+
+/// Once something arrived, how long we'll wait on the rest of it
+const GRACE: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone)]
+pub struct BannerConfig {
+    /// time given to complete the connection
+    pub connect: Duration,
+    /// time given to the server to speak first,
+    /// and to answer `payload`
+    pub wait: Duration,
+    /// sent after the greeting (or the lack of one)
+    pub payload: Option<Vec<u8>>,
+    pub max: usize,
+}
+
+impl Default for BannerConfig {
+    fn default() -> Self {
+        Self {
+            connect: DEFAULT_WAIT,
+            wait: DEFAULT_WAIT,
+            payload: None,
+            max: MAX_BANNER,
+        }
+    }
+}
+
+impl BannerConfig {
+    /// Longest a grab can take, connecting, then every read with its grace
+    pub fn budget(&self) -> Duration {
+        let reads = if self.payload.is_some() { 2 } else { 1 };
+        self.connect + (self.wait + GRACE) * reads
+    }
+}
+
+/// Whatever the port said
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Banner {
+    pub bytes: Vec<u8>,
+    pub hex: String,
+    pub printable: String,
+}
+
+impl From<Vec<u8>> for Banner {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self {
+            hex: hex(&bytes),
+            printable: printable(&bytes),
+            bytes,
+        }
+    }
+}
+
+impl Banner {
+    /// Text, give or take line breaks and tabs
+    pub fn is_text(&self) -> bool {
+        self.bytes.iter().all(|b| matches!(b, b'\r' | b'\n' | b'\t' | 0x20..=0x7e))
+    }
+}
+
+impl Report for Banner {
+    /// binary banners are shown as hex
+    fn details(&self) -> Option<String> {
+        if self.bytes.is_empty() { None }
+        else if self.is_text() { Some(self.printable.clone()) }
+        else { Some(self.hex.clone()) }
+    }
+}
+
+/// Connects, and captures what the server sends on its own,
+/// and what it answers to the configured payload.
+/// Servers that stay silent are still open, with an empty banner.
+#[derive(Debug)]
+pub struct BannerGrab;
+
+#[async_trait::async_trait]
+impl CRON for BannerGrab {
+    type State = Job<BannerConfig>;
+    type Response = Banner;
+
+    async fn exec(state: &mut Job<BannerConfig>) -> Result<JobCtrl<Self::Response>, Error> {
+        match grab(state.addr, &state.config).await {
+            Ok(banner) => Ok(JobCtrl::Return(State::Open, banner)),
+
+            Err(Error::IO(err)) => Ok(JobCtrl::Error(handle_io_error(err))),
+            Err(e) => {
+                eprintln!("unmatched error {:#?} [not io error]", e);
+                Ok(JobCtrl::Error(JobErr::Other))
+            }
+        }
+    }
+}
+
+pub async fn grab(addr: SocketAddr, config: &BannerConfig) -> Result<Banner, Error> {
+    let mut iface = TcpInterface::connect(addr, config.connect).await?;
+    let mut buf = Vec::new();
+
+    read_available(&mut iface, &mut buf, config).await?;
+
+    if let Some(payload) = &config.payload {
+        // the peer hanging up after its greeting doesn't make the greeting any less useful
+        if iface.write_iface(payload).await.is_ok() {
+            read_available(&mut iface, &mut buf, config).await?;
+        }
+    }
+
+    Ok(Banner::from(buf))
+}
+
+/// Waits up to `config.wait` for the first bytes,
+/// and keeps reading while more shows up within `GRACE`, `config.wait + GRACE` at most
+async fn read_available<I: NetworkInterface>(iface: &mut I, buf: &mut Vec<u8>, config: &BannerConfig) -> Result<(), Error> {
+    let deadline = Instant::now() + config.wait + GRACE;
+    iface.set_timeout(config.wait);
+
+    while buf.len() < config.max {
+        match iface.read_iface(buf, config.max - buf.len()).await {
+            Ok(0) => break,
+            Ok(_) => match deadline.checked_duration_since(Instant::now()) {
+                Some(left) => iface.set_timeout(left.min(GRACE)),
+                None => break
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::TimedOut => break,
+            // reset after speaking, keep what we've got
+            Err(_) if !buf.is_empty() => break,
+            Err(e) => return Err(e.into())
+        }
+    }
+
+    Ok(())
+}
+
+/// "53 53 48 2d" ...
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Printable ascii is kept, line breaks and everything else is escaped
+pub fn printable(bytes: &[u8]) -> String {
+    bytes.iter()
+        .map(|b| match b {
+            b'\r' => "\\r".to_string(),
+            b'\n' => "\\n".to_string(),
+            b'\t' => "\\t".to_string(),
+            b'\\' => "\\\\".to_string(),
+            0x20..=0x7e => (*b as char).to_string(),
+            _ => format!("\\x{:02x}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::{
+        runtime::Runtime,
+        net::TcpListener,
+        io::{AsyncReadExt, AsyncWriteExt},
+    };
+
+    fn config(payload: Option<&[u8]>) -> BannerConfig {
+        BannerConfig {
+            connect: Duration::from_secs(2),
+            wait: Duration::from_millis(300),
+            payload: payload.map(|x| x.to_vec()),
+            max: MAX_BANNER,
+        }
+    }
+
+    #[test]
+    fn forms() {
+        let banner = Banner::from(b"SSH-2.0\r\n\x00".to_vec());
+        assert_eq!(banner.printable, "SSH-2.0\\r\\n\\x00");
+        assert_eq!(banner.hex, "53 53 48 2d 32 2e 30 0d 0a 00");
+        assert_eq!(banner.details().unwrap(), banner.hex);
+
+        let banner = Banner::from(b"220 ready\r\n".to_vec());
+        assert_eq!(banner.details().unwrap(), "220 ready\\r\\n");
+    }
+
+    #[test]
+    fn server_speaks_first() {
+        let rt = Runtime::new().unwrap();
+
+        rt.block_on(async move {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+
+            tokio::spawn(async move {
+                let (mut con, _) = listener.accept().await.unwrap();
+                con.write_all(b"SSH-2.0-OpenSSH_8.9\r\n").await.unwrap();
+                let mut buf = [0; 16];
+                let _ = con.read(&mut buf).await;
+            });
+
+            let banner = grab(addr, &config(None)).await.unwrap();
+            assert_eq!(banner.bytes, b"SSH-2.0-OpenSSH_8.9\r\n");
+        });
+    }
+
+    #[test]
+    fn payload_is_answered() {
+        let rt = Runtime::new().unwrap();
+
+        rt.block_on(async move {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+
+            tokio::spawn(async move {
+                let (mut con, _) = listener.accept().await.unwrap();
+                let mut buf = [0; 64];
+                let n = con.read(&mut buf).await.unwrap();
+                assert_eq!(&buf[..n], b"HEAD / HTTP/1.0\r\n\r\n");
+                con.write_all(b"HTTP/1.0 200 OK\r\n\r\n").await.unwrap();
+            });
+
+            let banner = grab(addr, &config(Some(b"HEAD / HTTP/1.0\r\n\r\n"))).await.unwrap();
+            assert_eq!(banner.bytes, b"HTTP/1.0 200 OK\r\n\r\n");
+        });
+    }
+
+    #[test]
+    fn silent_server_is_empty() {
+        let rt = Runtime::new().unwrap();
+
+        rt.block_on(async move {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+
+            let banner = grab(addr, &config(None)).await.unwrap();
+            assert!(banner.bytes.is_empty());
+            assert_eq!(banner.details(), None);
+            drop(listener);
+        });
+    }
+}
@@ -0,0 +1,92 @@
+use px_core::wrapper::NetworkInterface;
+
+/// Most read at once while looking for the end of something
+const CHUNK: usize = 1024;
+
+/// A connection, along with what was read from it and not consumed yet.
+/// Handlers frame their protocol's messages on top of it
+pub struct Buffered<I> {
+    pub iface: I,
+    /// read, and not consumed yet
+    pub buf: Vec<u8>,
+}
+
+impl<I: NetworkInterface> Buffered<I> {
+    pub fn new(iface: I) -> Self {
+        Self { iface, buf: Vec::new() }
+    }
+
+    /// Reads until `buf` holds at least `amount` bytes
+    pub async fn fill(&mut self, amount: usize) -> Option<()> {
+        while self.buf.len() < amount {
+            let room = amount - self.buf.len();
+            match self.iface.read_iface(&mut self.buf, room).await {
+                Ok(0) | Err(_) => return None,
+                Ok(_) => {}
+            }
+        }
+        Some(())
+    }
+
+    /// A single read of whatever arrived, up to `CHUNK` bytes and what keeps `buf` under `max`.
+    /// Returns the amount read
+    pub async fn more(&mut self, max: usize) -> Option<usize> {
+        let room = max.saturating_sub(self.buf.len()).min(CHUNK);
+        match self.iface.read_iface(&mut self.buf, room).await {
+            Ok(0) | Err(_) => None,
+            Ok(n) => Some(n)
+        }
+    }
+
+    pub fn take(&mut self, amount: usize) -> Vec<u8> {
+        self.buf.drain(..amount).collect()
+    }
+
+    pub async fn write(&mut self, bytes: &[u8]) -> Option<()> {
+        self.iface.write_iface(bytes).await.ok()?;
+        Some(())
+    }
+
+    /// The next line, without its "\r\n".
+    /// Gives up once `max` bytes were read without one
+    pub async fn line(&mut self, max: usize) -> Option<String> {
+        let mut scanned = 0;
+        loop {
+            if let Some(at) = self.buf[scanned..].windows(2).position(|x| x == b"\r\n") {
+                let end = scanned + at;
+                let line = self.take(end + 2);
+                return Some(String::from_utf8_lossy(&line[..end]).to_string())
+            }
+            if self.buf.len() >= max {
+                return None
+            }
+            // the "\r" may be the last byte scanned
+            scanned = self.buf.len().saturating_sub(1);
+            self.more(max).await?;
+        }
+    }
+}
+
+pub fn le_u16(buf: &[u8], at: usize) -> Option<u16> {
+    let mut bytes = [0; 2];
+    bytes.copy_from_slice(buf.get(at..at + 2)?);
+    Some(u16::from_le_bytes(bytes))
+}
+
+pub fn le_u32(buf: &[u8], at: usize) -> Option<u32> {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(buf.get(at..at + 4)?);
+    Some(u32::from_le_bytes(bytes))
+}
+
+pub fn le_i32(buf: &[u8], at: usize) -> Option<i32> {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(buf.get(at..at + 4)?);
+    Some(i32::from_le_bytes(bytes))
+}
+
+pub fn le_u64(buf: &[u8], at: usize) -> Option<u64> {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(buf.get(at..at + 8)?);
+    Some(u64::from_le_bytes(bytes))
+}
@@ -0,0 +1,95 @@
+use tokio::{
+    net::TcpListener,
+    io::{AsyncReadExt, AsyncWriteExt},
+    task::JoinHandle,
+    time::timeout,
+};
+
+use std::{
+    net::SocketAddr,
+    time::Duration,
+};
+use super::rand_u64;
+
+/// How long a connection gets to send a request before it's treated as a raw tunnel
+const GRACE: Duration = Duration::from_millis(250);
+
+/// A listener of ours that proxies are asked to connect to.
+///
+/// Everything connecting to it is sent `token`,
+/// reading the token back through a proxy proves the proxy relays traffic,
+/// instead of only claiming it does.
+/// HTTP requests (forwarded by HTTP proxies) get the token as the body of a response,
+/// raw connections get it once they've stayed quiet for `GRACE`.
+#[derive(Debug)]
+pub struct Canary {
+    addr: SocketAddr,
+    token: Vec<u8>,
+    task: JoinHandle<()>,
+}
+
+impl Canary {
+    /// Must be called inside of a runtime.
+    /// `addr` has to be reachable from the proxies being tested
+    pub async fn bind(addr: SocketAddr) -> Result<Self, std::io::Error> {
+        let listener = TcpListener::bind(addr).await?;
+        let addr = listener.local_addr()?;
+        let token = format!("px-canary-{:016x}{:016x}\n", rand_u64(), rand_u64()).into_bytes();
+
+        let task = tokio::spawn(serve(listener, token.clone()));
+
+        Ok(Self { addr, token, task })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn token(&self) -> &[u8] {
+        &self.token
+    }
+}
+
+impl Drop for Canary {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn serve(listener: TcpListener, token: Vec<u8>) {
+    loop {
+        let mut con = match listener.accept().await {
+            Ok((con, _)) => con,
+            Err(_) => continue
+        };
+
+        let token = token.clone();
+        tokio::spawn(async move {
+            let mut buf = [0; 1024];
+            let request = match timeout(GRACE, con.read(&mut buf)).await {
+                Ok(Ok(n)) => &buf[..n],
+                _ => &[][..]
+            };
+
+            let reply = if is_http(request) {
+                let mut reply = format!(
+                    "HTTP/1.0 200 OK\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    token.len()
+                ).into_bytes();
+                reply.extend_from_slice(&token);
+                reply
+            }
+            else {
+                token
+            };
+
+            let _ = con.write_all(&reply).await;
+            let _ = con.shutdown().await;
+        });
+    }
+}
+
+fn is_http(request: &[u8]) -> bool {
+    const METHODS: [&[u8]; 4] = [b"GET ", b"HEAD ", b"POST ", b"OPTIONS "];
+    METHODS.iter().any(|method| request.starts_with(method))
+}
@@ -0,0 +1,963 @@
+use px_core::{
+    pool::{JobCtrl, CRON, JobErr},
+    error::Error,
+    model::State,
+    wrapper::{NetworkInterface, TcpInterface, TlsInterface, insecure_config},
+};
+use tokio_rustls::rustls::ClientConfig;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    time::Duration,
+};
+use super::{
+    Job, http,
+    buffered::{Buffered, le_u16, le_i32},
+};
+use crate::cli::output::{Report, ServiceInfo};
+
+/// Most we'll read of a single response
+const MAX_RESPONSE: usize = 64 * 1024;
+/// Most lines of `stats` we'll read
+const MAX_STATS: usize = 256;
+
+/// Errors redis turns unauthenticated clients away with
+const REDIS_DENIALS: [&str; 4] = ["-NOAUTH", "-NOPERM", "-DENIED", "-ERR operation not permitted"];
+
+const MONGO_OP_MSG: i32 = 2013;
+
+/// Protocol 3.0
+const POSTGRES_PROTOCOL: u32 = 196_608;
+/// Asks for tls in place of a protocol version
+const POSTGRES_SSL_REQUEST: u32 = 80_877_103;
+
+const MYSQL_LONG_PASSWORD: u32 = 0x0000_0001;
+const MYSQL_PROTOCOL_41: u32 = 0x0000_0200;
+const MYSQL_SECURE_CONNECTION: u32 = 0x0000_8000;
+const MYSQL_PLUGIN_AUTH: u32 = 0x0008_0000;
+const MYSQL_COM_QUIT: u8 = 0x01;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Datastore {
+    Redis,
+    Memcached,
+    MongoDb,
+    Elasticsearch,
+    PostgreSql,
+    MySql,
+}
+
+impl Datastore {
+    /// In the order they're tried on ports none of them are registered to,
+    /// MySQL speaks first, so it goes first
+    pub const ALL: [Datastore; 6] = [
+        Datastore::MySql,
+        Datastore::Redis,
+        Datastore::Memcached,
+        Datastore::MongoDb,
+        Datastore::PostgreSql,
+        Datastore::Elasticsearch,
+    ];
+
+    /// Listens on `port` by default
+    pub fn registered(self, port: u16) -> bool {
+        match self {
+            Datastore::Redis => port == 6379,
+            Datastore::Memcached => port == 11211,
+            Datastore::MongoDb => (27017..=27019).contains(&port),
+            Datastore::Elasticsearch => port == 9200,
+            Datastore::PostgreSql => port == 5432,
+            Datastore::MySql => port == 3306,
+        }
+    }
+
+    /// (service, product), the service named like nmap-services does
+    fn names(self) -> (&'static str, &'static str) {
+        match self {
+            Datastore::Redis => ("redis", "Redis"),
+            Datastore::Memcached => ("memcache", "Memcached"),
+            Datastore::MongoDb => ("mongodb", "MongoDB"),
+            Datastore::Elasticsearch => ("elasticsearch", "Elasticsearch"),
+            Datastore::PostgreSql => ("postgresql", "PostgreSQL"),
+            Datastore::MySql => ("mysql", "MySQL"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Exposure {
+    pub service: Datastore,
+    pub version: Option<String>,
+    /// unauthenticated clients were turned away,
+    /// unknown for datastores we didn't try logging into
+    pub auth_required: Option<bool>,
+}
+
+impl Exposure {
+    fn new(service: Datastore, version: Option<String>, auth_required: bool) -> Self {
+        Self { service, version, auth_required: Some(auth_required) }
+    }
+
+    fn unchecked(service: Datastore, version: Option<String>) -> Self {
+        Self { service, version, auth_required: None }
+    }
+}
+
+impl Report for Exposure {
+    fn service(&self) -> Option<ServiceInfo> {
+        let (name, product) = self.service.names();
+        Some(ServiceInfo {
+            name: name.to_string(),
+            product: Some(product.to_string()),
+            version: self.version.clone(),
+            cpe: Vec::new(),
+        })
+    }
+
+    fn details(&self) -> Option<String> {
+        match self.auth_required {
+            Some(true) => Some("auth required".to_string()),
+            Some(false) => Some("no auth required".to_string()),
+            None => None,
+        }
+    }
+
+    fn data(&self) -> Option<serde_json::Value> {
+        serde_json::to_value(self).ok()
+    }
+}
+
+pub struct DatastoreConfig {
+    pub timeout: Duration,
+    /// Elasticsearch 8 only serves https
+    pub tls: Arc<ClientConfig>,
+    /// log into PostgreSQL as "postgres", and MySQL as "root" with an empty password
+    pub login: bool,
+}
+
+impl DatastoreConfig {
+    pub fn new(timeout: Duration, login: bool) -> Self {
+        Self {
+            timeout,
+            tls: Arc::new(insecure_config()),
+            login,
+        }
+    }
+}
+
+impl std::fmt::Debug for DatastoreConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DatastoreConfig")
+            .field("timeout", &self.timeout)
+            .field("login", &self.login)
+            .finish()
+    }
+}
+
+/// Asks datastores about themselves the way any client could before logging in:
+/// redis `INFO`, memcached `stats`, mongodb `hello`/`buildInfo`/`listDatabases`,
+/// Elasticsearch's root endpoint, PostgreSQL's answer to an SSLRequest, and MySQL's greeting.
+/// Nothing is written, and no credentials are sent.
+///
+/// With `DatastoreConfig::login` PostgreSQL is started up as "postgres",
+/// and MySQL is logged into as "root" with an empty password,
+/// to tell whether either lets anyone in. Those are the only logins ever attempted.
+///
+/// The datastore registered to the port is tried first, then the others, each on a fresh connection.
+/// Ports none of them answer on are open, without a response.
+#[derive(Debug)]
+pub struct DatastoreScanner;
+
+#[async_trait::async_trait]
+impl CRON for DatastoreScanner {
+    type State = Job<DatastoreConfig>;
+    type Response = Option<Exposure>;
+
+    async fn exec(state: &mut Job<DatastoreConfig>) -> Result<JobCtrl<Self::Response>, Error> {
+        match inspect(state.addr, &state.config).await {
+            Ok(found) => Ok(JobCtrl::Return(State::Open, found)),
+
+            Err(Error::IO(err)) => Ok(JobCtrl::Error(super::handle_io_error(err))),
+            Err(e) => {
+                eprintln!("unmatched error {:#?} [not io error]", e);
+                Ok(JobCtrl::Error(JobErr::Other))
+            }
+        }
+    }
+}
+
+/// Errors when the port can't be connected to for the first datastore tried (the port's registered one),
+/// the others are skipped when their connection can't be made
+pub async fn inspect(addr: SocketAddr, config: &DatastoreConfig) -> Result<Option<Exposure>, Error> {
+    let (mut order, rest): (Vec<Datastore>, Vec<Datastore>) = Datastore::ALL.iter()
+        .copied()
+        .partition(|x| x.registered(addr.port()));
+    order.extend(rest);
+
+    let mut first = Some(TcpInterface::connect(addr, config.timeout).await?);
+    for datastore in order {
+        let iface = match first.take() {
+            Some(iface) => iface,
+            None => match TcpInterface::connect(addr, config.timeout).await {
+                Ok(iface) => iface,
+                Err(_) => continue
+            }
+        };
+
+        let mut con = Buffered::new(iface);
+        let found = match datastore {
+            Datastore::Redis => redis(&mut con).await,
+            Datastore::Memcached => memcached(&mut con).await,
+            Datastore::MongoDb => mongodb(&mut con).await,
+            Datastore::Elasticsearch => elasticsearch(con.iface, config).await,
+            Datastore::PostgreSql => postgresql(&mut con, config.login).await,
+            Datastore::MySql => mysql(&mut con, config.login).await,
+        };
+
+        if found.is_some() {
+            return Ok(found)
+        }
+    }
+
+    Ok(None)
+}
+
+async fn redis<I: NetworkInterface>(con: &mut Buffered<I>) -> Option<Exposure> {
+    con.write(b"INFO server\r\n").await?;
+    let line = con.line(MAX_RESPONSE).await?;
+
+    if REDIS_DENIALS.iter().any(|x| line.starts_with(x)) {
+        return Some(Exposure::new(Datastore::Redis, None, true))
+    }
+
+    let length: usize = line.strip_prefix('$')?.parse().ok()?;
+    if length > MAX_RESPONSE {
+        return None
+    }
+    con.fill(length).await?;
+
+    let info = String::from_utf8_lossy(&con.take(length)).to_string();
+    let version = info.lines()
+        .find_map(|x| x.strip_prefix("redis_version:"))
+        .map(|x| x.trim().to_string());
+
+    if version.is_none() && !info.contains("# Server") {
+        return None
+    }
+    Some(Exposure::new(Datastore::Redis, version, false))
+}
+
+async fn memcached<I: NetworkInterface>(con: &mut Buffered<I>) -> Option<Exposure> {
+    con.write(b"stats\r\n").await?;
+    let mut line = con.line(MAX_RESPONSE).await?;
+
+    // ascii authentication (-Y) is on
+    if line.starts_with("CLIENT_ERROR unauthenticated") {
+        return Some(Exposure::new(Datastore::Memcached, None, true))
+    }
+    if !line.starts_with("STAT ") {
+        return None
+    }
+
+    let mut version = None;
+    for _ in 0..MAX_STATS {
+        if let Some(found) = line.strip_prefix("STAT version ") {
+            version = Some(found.trim().to_string());
+            break
+        }
+
+        line = match con.line(MAX_RESPONSE).await {
+            Some(line) if line != "END" => line,
+            _ => break
+        };
+    }
+
+    Some(Exposure::new(Datastore::Memcached, version, false))
+}
+
+/// OP_MSG commands, MongoDB 3.6 and later.
+/// `hello` and `buildInfo` answer anyone, `listDatabases` needs a user once authorization is on
+async fn mongodb<I: NetworkInterface>(con: &mut Buffered<I>) -> Option<Exposure> {
+    let mut hello = mongo_command(con, 1, "hello").await?;
+    // `hello` came with 4.4.2, `isMaster` is what came before it
+    if !is_ok(&hello) {
+        hello = mongo_command(con, 2, "isMaster").await?;
+    }
+    if !is_ok(&hello) || !hello.contains_key("maxWireVersion") {
+        return None
+    }
+
+    let version = mongo_command(con, 3, "buildInfo").await
+        .filter(is_ok)
+        .and_then(|reply| Some(reply.get("version")?.as_str()?.to_string()));
+
+    let auth_required = match mongo_command(con, 4, "listDatabases").await {
+        Some(reply) => !is_ok(&reply),
+        None => true
+    };
+
+    Some(Exposure::new(Datastore::MongoDb, version, auth_required))
+}
+
+/// Plaintext first, then over tls
+async fn elasticsearch(con: TcpInterface, config: &DatastoreConfig) -> Option<Exposure> {
+    let addr = con.peer();
+    if let Some(found) = root_endpoint(con, addr).await {
+        return Some(found)
+    }
+
+    let con = TcpInterface::connect(addr, config.timeout).await.ok()?;
+    let iface = TlsInterface::handshake(con.into_inner(), addr, None, config.tls.clone(), config.timeout).await.ok()?;
+    root_endpoint(iface, addr).await
+}
+
+async fn root_endpoint<I: NetworkInterface>(mut iface: I, addr: SocketAddr) -> Option<Exposure> {
+    let request = format!(
+        "GET / HTTP/1.1\r\nHost: {}\r\nAccept: application/json\r\nConnection: close\r\n\r\n",
+        http::host(addr)
+    );
+    iface.write_iface(request.as_bytes()).await.ok()?;
+
+    let (status, _, headers, mut body) = http::read_head(&mut iface).await?;
+    let length = headers.iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.parse::<usize>().ok())
+        .unwrap_or(MAX_RESPONSE)
+        .min(MAX_RESPONSE);
+
+    while body.len() < length {
+        let room = length - body.len();
+        match iface.read_iface(&mut body, room).await {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+    }
+
+    if status == 401 || status == 403 {
+        // x-pack security's challenge, or its error body
+        let challenged = headers.iter()
+            .any(|(key, value)| key.eq_ignore_ascii_case("www-authenticate") && value.contains("realm=\"security\""));
+
+        if challenged || String::from_utf8_lossy(&body).contains("security_exception") {
+            return Some(Exposure::new(Datastore::Elasticsearch, None, true))
+        }
+        return None
+    }
+
+    let root: Value = serde_json::from_slice(&body).ok()?;
+    root.get("cluster_name")?;
+    let version = root.get("version")?.get("number")?.as_str()?;
+
+    Some(Exposure::new(Datastore::Elasticsearch, Some(version.to_string()), false))
+}
+
+/// Without `login` an SSLRequest is sent, any server answers it with a single 'S' or 'N'.
+/// Otherwise starts up as "postgres", only `trust` authentication lets that through.
+/// The server's version is only told once authenticated
+async fn postgresql<I: NetworkInterface>(con: &mut Buffered<I>, login: bool) -> Option<Exposure> {
+    if !login {
+        let mut request = 8u32.to_be_bytes().to_vec();
+        request.extend_from_slice(&POSTGRES_SSL_REQUEST.to_be_bytes());
+        con.write(&request).await?;
+
+        con.fill(1).await?;
+        // a reply longer than the answer isn't PostgreSQL
+        return match con.take(1)[..] {
+            [b'S'] | [b'N'] if con.buf.is_empty() => Some(Exposure::unchecked(Datastore::PostgreSql, None)),
+            _ => None
+        }
+    }
+
+    let mut startup = POSTGRES_PROTOCOL.to_be_bytes().to_vec();
+    for parameter in ["user", "postgres", "database", "postgres"].iter() {
+        startup.extend_from_slice(parameter.as_bytes());
+        startup.push(0);
+    }
+    startup.push(0);
+
+    let mut message = ((startup.len() + 4) as u32).to_be_bytes().to_vec();
+    message.extend_from_slice(&startup);
+    con.write(&message).await?;
+
+    let mut found = Exposure::new(Datastore::PostgreSql, None, true);
+    let (kind, body) = postgres_message(con).await?;
+    match kind {
+        // AuthenticationOk
+        b'R' if body.get(..4) == Some(&[0, 0, 0, 0]) => found.auth_required = Some(false),
+        // a password, or some other method
+        b'R' => return Some(found),
+        // turned away before authenticating, pg_hba.conf has no entry for us
+        b'E' if body.first() == Some(&b'S') => return Some(found),
+        _ => return None
+    }
+
+    // parameters are reported up to ReadyForQuery
+    for _ in 0..MAX_STATS {
+        let (kind, body) = match postgres_message(con).await {
+            Some(message) => message,
+            None => break
+        };
+
+        match kind {
+            b'S' => {
+                let mut parameter = body.split(|x| *x == 0);
+                if parameter.next() == Some(b"server_version") {
+                    found.version = parameter.next().map(|x| String::from_utf8_lossy(x).to_string());
+                }
+            },
+            b'Z' | b'E' => break,
+            _ => {}
+        }
+    }
+
+    // Terminate
+    let _ = con.write(&[b'X', 0, 0, 0, 4]).await;
+    Some(found)
+}
+
+/// Reads the greeting, then with `login` logs in as "root" with an empty password.
+/// Completing the handshake keeps the server from counting us against `max_connect_errors`,
+/// hanging up after the greeting does count
+async fn mysql<I: NetworkInterface>(con: &mut Buffered<I>, login: bool) -> Option<Exposure> {
+    let (sequence, greeting) = mysql_packet(con).await?;
+    let mut found = Exposure::new(Datastore::MySql, None, true);
+
+    match greeting.first()? {
+        // turned away before the greeting: host not allowed, too many connections
+        0xff if greeting.len() > 3 => return Some(found),
+        10 => {}
+        _ => return None
+    }
+
+    let end = 1 + greeting[1..].iter().position(|x| *x == 0)?;
+    found.version = Some(String::from_utf8_lossy(&greeting[1..end]).to_string());
+    if !login {
+        found.auth_required = None;
+        return Some(found)
+    }
+
+    // after the version: connection id, the scramble's first 8 bytes and a filler
+    let lower = le_u16(&greeting, end + 14)? as u32;
+    let upper = le_u16(&greeting, end + 19).unwrap_or(0) as u32;
+    let server = lower | upper << 16;
+    if server & MYSQL_PROTOCOL_41 == 0 {
+        return Some(found)
+    }
+
+    let capabilities = MYSQL_LONG_PASSWORD | MYSQL_PROTOCOL_41 | MYSQL_SECURE_CONNECTION | (server & MYSQL_PLUGIN_AUTH);
+    let mut response = capabilities.to_le_bytes().to_vec();
+    // max packet size, utf8, filler
+    response.extend_from_slice(&0x0100_0000u32.to_le_bytes());
+    response.push(33);
+    response.extend_from_slice(&[0; 23]);
+    // user, and an empty auth response
+    response.extend_from_slice(b"root\0");
+    response.push(0);
+    if capabilities & MYSQL_PLUGIN_AUTH != 0 {
+        response.extend_from_slice(b"mysql_native_password\0");
+    }
+
+    if mysql_send(con, sequence.wrapping_add(1), &response).await.is_none() {
+        return Some(found)
+    }
+
+    for _ in 0..3 {
+        let (sequence, packet) = match mysql_packet(con).await {
+            Some(packet) => packet,
+            None => break
+        };
+
+        match packet.first() {
+            Some(0x00) => {
+                found.auth_required = Some(false);
+                let _ = mysql_send(con, 0, &[MYSQL_COM_QUIT]).await;
+                break
+            },
+            // switching to the account's plugin, the password's still empty
+            Some(0xfe) => if mysql_send(con, sequence.wrapping_add(1), &[]).await.is_none() {
+                break
+            },
+            // caching_sha2_password's fast auth result, the verdict follows
+            Some(0x01) => {},
+            _ => break
+        }
+    }
+
+    Some(found)
+}
+
+/// Runs `command` against mongodb's admin database, the reply's top level fields
+async fn mongo_command<I: NetworkInterface>(con: &mut Buffered<I>, request_id: i32, command: &str) -> Option<Map<String, Value>> {
+    con.write(&op_msg(request_id, command)).await?;
+
+    con.fill(4).await?;
+    let length = le_length(&con.buf, 0)?;
+    // header, flags and a section kind
+    if length < 21 || length > MAX_RESPONSE {
+        return None
+    }
+
+    con.fill(length).await?;
+    let reply = con.take(length);
+    if le_i32(&reply, 12)? != MONGO_OP_MSG || reply[20] != 0 {
+        return None
+    }
+    document(&reply[21..])
+}
+
+/// A PostgreSQL backend message's type and body
+async fn postgres_message<I: NetworkInterface>(con: &mut Buffered<I>) -> Option<(u8, Vec<u8>)> {
+    con.fill(5).await?;
+    let length = u32::from_be_bytes([con.buf[1], con.buf[2], con.buf[3], con.buf[4]]) as usize;
+    if length < 4 || length > MAX_RESPONSE {
+        return None
+    }
+
+    con.fill(1 + length).await?;
+    let message = con.take(1 + length);
+    Some((message[0], message[5..].to_vec()))
+}
+
+/// A MySQL packet's sequence id and payload
+async fn mysql_packet<I: NetworkInterface>(con: &mut Buffered<I>) -> Option<(u8, Vec<u8>)> {
+    con.fill(4).await?;
+    let length = u32::from_le_bytes([con.buf[0], con.buf[1], con.buf[2], 0]) as usize;
+    if length > MAX_RESPONSE {
+        return None
+    }
+
+    con.fill(4 + length).await?;
+    let packet = con.take(4 + length);
+    Some((packet[3], packet[4..].to_vec()))
+}
+
+async fn mysql_send<I: NetworkInterface>(con: &mut Buffered<I>, sequence: u8, payload: &[u8]) -> Option<()> {
+    let mut packet = (payload.len() as u32).to_le_bytes().to_vec();
+    packet[3] = sequence;
+    packet.extend_from_slice(payload);
+    con.write(&packet).await
+}
+
+/// `{ <command>: 1, $db: "admin" }` in an OP_MSG
+fn op_msg(request_id: i32, command: &str) -> Vec<u8> {
+    let mut fields = vec![0x10];
+    fields.extend_from_slice(command.as_bytes());
+    fields.push(0);
+    fields.extend_from_slice(&1i32.to_le_bytes());
+    fields.push(0x02);
+    fields.extend_from_slice(b"$db\0");
+    fields.extend_from_slice(&6i32.to_le_bytes());
+    fields.extend_from_slice(b"admin\0");
+    fields.push(0);
+
+    let mut document = ((fields.len() + 4) as i32).to_le_bytes().to_vec();
+    document.extend_from_slice(&fields);
+
+    let mut message = ((16 + 4 + 1 + document.len()) as i32).to_le_bytes().to_vec();
+    message.extend_from_slice(&request_id.to_le_bytes());
+    // response to, opcode, flags, a body section
+    message.extend_from_slice(&0i32.to_le_bytes());
+    message.extend_from_slice(&MONGO_OP_MSG.to_le_bytes());
+    message.extend_from_slice(&0u32.to_le_bytes());
+    message.push(0);
+    message.extend_from_slice(&document);
+    message
+}
+
+/// The top level of a BSON document (bsonspec.org).
+/// Numbers, strings and booleans are kept, everything else is null
+fn document(bytes: &[u8]) -> Option<Map<String, Value>> {
+    let length = le_length(bytes, 0)?;
+    let mut rest = bytes.get(4..length.checked_sub(1)?)?;
+    let mut fields = Map::new();
+
+    while let Some((kind, tail)) = rest.split_first() {
+        let end = tail.iter().position(|x| *x == 0)?;
+        let name = String::from_utf8_lossy(&tail[..end]).to_string();
+        let value = &tail[end + 1..];
+
+        let (parsed, size) = match *kind {
+            0x01 => {
+                let mut double = [0; 8];
+                double.copy_from_slice(value.get(..8)?);
+                (Value::from(f64::from_le_bytes(double)), 8)
+            },
+            // string, javascript, symbol
+            0x02 | 0x0d | 0x0e => {
+                let size = le_length(value, 0)?;
+                let string = value.get(4..4 + size.checked_sub(1)?)?;
+                (Value::from(String::from_utf8_lossy(string).to_string()), 4 + size)
+            },
+            // document, array, javascript with scope
+            0x03 | 0x04 | 0x0f => (Value::Null, le_length(value, 0)?),
+            0x05 => (Value::Null, 5 + le_length(value, 0)?),
+            // undefined, null, min key, max key
+            0x06 | 0x0a | 0xff | 0x7f => (Value::Null, 0),
+            0x07 => (Value::Null, 12),
+            0x08 => (Value::from(*value.first()? != 0), 1),
+            // datetime, timestamp
+            0x09 | 0x11 => (Value::Null, 8),
+            0x0b => {
+                let pattern = value.iter().position(|x| *x == 0)?;
+                let options = value[pattern + 1..].iter().position(|x| *x == 0)?;
+                (Value::Null, pattern + options + 2)
+            },
+            0x0c => (Value::Null, 4 + le_length(value, 0)? + 12),
+            0x10 => (Value::from(le_i32(value, 0)?), 4),
+            0x12 => {
+                let mut long = [0; 8];
+                long.copy_from_slice(value.get(..8)?);
+                (Value::from(i64::from_le_bytes(long)), 8)
+            },
+            0x13 => (Value::Null, 16),
+            _ => return None
+        };
+
+        fields.insert(name, parsed);
+        rest = value.get(size..)?;
+    }
+
+    Some(fields)
+}
+
+/// `ok` is a double, but can be anything number-like
+fn is_ok(reply: &Map<String, Value>) -> bool {
+    match reply.get("ok") {
+        Some(Value::Number(ok)) => ok.as_f64() == Some(1.0),
+        Some(Value::Bool(ok)) => *ok,
+        _ => false
+    }
+}
+
+/// BSON's lengths are signed, negative ones are malformed
+fn le_length(buf: &[u8], at: usize) -> Option<usize> {
+    match le_i32(buf, at)? {
+        length if length < 0 => None,
+        length => Some(length as usize)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::future::Future;
+    use tokio::{
+        runtime::Runtime,
+        net::{TcpListener, TcpStream},
+        io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    };
+
+    fn config() -> DatastoreConfig {
+        DatastoreConfig::new(Duration::from_millis(300), false)
+    }
+
+    fn login() -> DatastoreConfig {
+        DatastoreConfig::new(Duration::from_millis(300), true)
+    }
+
+    /// Hands every connection to `serve`
+    async fn stand_in<F, R>(serve: F) -> SocketAddr
+    where
+        F: Fn(TcpStream) -> R + Send + Sync + 'static,
+        R: Future<Output = ()> + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            loop {
+                let (con, _) = listener.accept().await.unwrap();
+                tokio::spawn(serve(con));
+            }
+        });
+
+        addr
+    }
+
+    async fn redis_server(con: TcpStream, auth: bool) {
+        let mut con = BufReader::new(con);
+        let mut line = String::new();
+        if con.read_line(&mut line).await.unwrap_or(0) == 0 {
+            return
+        }
+
+        let reply = match (line.as_str(), auth) {
+            ("INFO server\r\n", true) => "-NOAUTH Authentication required.\r\n".to_string(),
+            ("INFO server\r\n", false) => {
+                let info = "# Server\r\nredis_version:7.0.11\r\nredis_mode:standalone\r\n";
+                format!("${}\r\n{}\r\n", info.len(), info)
+            },
+            _ => "-ERR unknown command\r\n".to_string(),
+        };
+        let _ = con.write_all(reply.as_bytes()).await;
+    }
+
+    /// `{ <name>: <value>.. }`, doubles, strings and bools, with an empty document thrown in
+    fn bson(fields: &[(&str, Value)]) -> Vec<u8> {
+        let mut body = vec![0x03];
+        body.extend_from_slice(b"topologyVersion\0");
+        body.extend_from_slice(&[5, 0, 0, 0, 0]);
+
+        for (name, value) in fields {
+            let kind = match value {
+                Value::Number(_) => 0x01,
+                Value::String(_) => 0x02,
+                _ => 0x08,
+            };
+            body.push(kind);
+            body.extend_from_slice(name.as_bytes());
+            body.push(0);
+
+            match value {
+                Value::Number(x) => body.extend_from_slice(&x.as_f64().unwrap().to_le_bytes()),
+                Value::String(x) => {
+                    body.extend_from_slice(&(x.len() as i32 + 1).to_le_bytes());
+                    body.extend_from_slice(x.as_bytes());
+                    body.push(0);
+                },
+                x => body.push(x.as_bool().unwrap() as u8),
+            }
+        }
+        body.push(0);
+
+        let mut document = ((body.len() + 4) as i32).to_le_bytes().to_vec();
+        document.extend_from_slice(&body);
+        document
+    }
+
+    async fn mongo_server(mut con: TcpStream, auth: bool) {
+        loop {
+            let mut length = [0; 4];
+            if con.read_exact(&mut length).await.is_err() {
+                return
+            }
+            let length = i32::from_le_bytes(length) as usize;
+            if length > MAX_RESPONSE {
+                return
+            }
+            let mut message = vec![0; length - 4];
+            if con.read_exact(&mut message).await.is_err() {
+                return
+            }
+
+            // the rest of the header, flags, a section kind, then the document,
+            // the command's its first field
+            let start = 12 + 4 + 1 + 4 + 1;
+            let end = start + message[start..].iter().position(|x| *x == 0).unwrap();
+            let reply = match &message[start..end] {
+                b"hello" => bson(&[("isWritablePrimary", true.into()), ("maxWireVersion", 17.0.into()), ("ok", 1.0.into())]),
+                b"buildInfo" => bson(&[("version", "6.0.5".into()), ("ok", 1.0.into())]),
+                b"listDatabases" if auth => bson(&[
+                    ("ok", 0.0.into()),
+                    ("errmsg", "command listDatabases requires authentication".into()),
+                    ("codeName", "Unauthorized".into()),
+                ]),
+                b"listDatabases" => bson(&[("totalSize", 8192.0.into()), ("ok", 1.0.into())]),
+                _ => bson(&[("ok", 0.0.into()), ("errmsg", "no such command".into())]),
+            };
+
+            let mut response = ((16 + 5 + reply.len()) as i32).to_le_bytes().to_vec();
+            response.extend_from_slice(&[0; 8]);
+            response.extend_from_slice(&MONGO_OP_MSG.to_le_bytes());
+            response.extend_from_slice(&[0; 5]);
+            response.extend_from_slice(&reply);
+            con.write_all(&response).await.unwrap();
+        }
+    }
+
+    async fn postgres_server(mut con: TcpStream, trust: bool) {
+        let mut length = [0; 4];
+        if con.read_exact(&mut length).await.is_err() {
+            return
+        }
+        let mut startup = vec![0; u32::from_be_bytes(length) as usize - 4];
+        if con.read_exact(&mut startup).await.is_err() {
+            return
+        }
+        if startup == POSTGRES_SSL_REQUEST.to_be_bytes() {
+            con.write_all(b"N").await.unwrap();
+            let _ = con.read(&mut [0; 16]).await;
+            return
+        }
+        if !startup.starts_with(&POSTGRES_PROTOCOL.to_be_bytes()) {
+            return
+        }
+
+        let message = |kind: u8, body: &[u8]| {
+            let mut message = vec![kind];
+            message.extend_from_slice(&(body.len() as u32 + 4).to_be_bytes());
+            message.extend_from_slice(body);
+            message
+        };
+
+        let reply = match trust {
+            true => [
+                message(b'R', &[0, 0, 0, 0]),
+                message(b'S', b"server_version\x0015.3\0"),
+                message(b'Z', b"I"),
+            ].concat(),
+            // md5, with a salt
+            false => message(b'R', &[0, 0, 0, 5, 1, 2, 3, 4]),
+        };
+        con.write_all(&reply).await.unwrap();
+        let _ = con.read(&mut [0; 16]).await;
+    }
+
+    async fn mysql_server(mut con: TcpStream, allowed: bool) {
+        let mut greeting = b"\x0a8.0.33\0\x01\0\0\0abcdefgh\0".to_vec();
+        greeting.extend_from_slice(&0x8201u16.to_le_bytes());
+        greeting.extend_from_slice(&[33, 2, 0]);
+        greeting.extend_from_slice(&0x0008u16.to_le_bytes());
+        greeting.push(21);
+        greeting.extend_from_slice(&[0; 10]);
+        greeting.extend_from_slice(b"ijklmnopqrst\0caching_sha2_password\0");
+
+        let mut packet = (greeting.len() as u32).to_le_bytes().to_vec();
+        packet[3] = 0;
+        packet.extend_from_slice(&greeting);
+        con.write_all(&packet).await.unwrap();
+
+        let mut header = [0; 4];
+        if con.read_exact(&mut header).await.is_err() {
+            return
+        }
+        let mut response = vec![0; u32::from_le_bytes([header[0], header[1], header[2], 0]) as usize];
+        con.read_exact(&mut response).await.unwrap();
+        assert_eq!(&response[32..37], b"root\0");
+
+        let reply: &[u8] = match allowed {
+            true => b"\x07\0\0\x02\0\0\0\x02\0\0\0",
+            false => b"\x0b\0\0\x02\xff\x15\x04#28000",
+        };
+        con.write_all(reply).await.unwrap();
+        let _ = con.read(&mut [0; 16]).await;
+    }
+
+    #[test]
+    fn redis() {
+        Runtime::new().unwrap().block_on(async {
+            let open = stand_in(|con| redis_server(con, false)).await;
+            let found = inspect(open, &config()).await.unwrap().unwrap();
+            assert_eq!(found, Exposure::new(Datastore::Redis, Some("7.0.11".to_string()), false));
+            assert_eq!(found.details().unwrap(), "no auth required");
+
+            let auth = stand_in(|con| redis_server(con, true)).await;
+            let found = inspect(auth, &config()).await.unwrap().unwrap();
+            assert_eq!(found, Exposure::new(Datastore::Redis, None, true));
+        });
+    }
+
+    #[test]
+    fn memcached() {
+        Runtime::new().unwrap().block_on(async {
+            let addr = stand_in(|con| async move {
+                let mut con = BufReader::new(con);
+                let mut line = String::new();
+                while con.read_line(&mut line).await.unwrap_or(0) > 0 {
+                    let reply = match line.as_str() {
+                        "stats\r\n" => "STAT pid 1\r\nSTAT uptime 20\r\nSTAT version 1.6.21\r\nEND\r\n",
+                        _ => "ERROR\r\n",
+                    };
+                    con.write_all(reply.as_bytes()).await.unwrap();
+                    line.clear();
+                }
+            }).await;
+
+            let found = inspect(addr, &config()).await.unwrap().unwrap();
+            assert_eq!(found, Exposure::new(Datastore::Memcached, Some("1.6.21".to_string()), false));
+            assert_eq!(found.service().unwrap().name, "memcache");
+        });
+    }
+
+    #[test]
+    fn mongodb() {
+        Runtime::new().unwrap().block_on(async {
+            let open = stand_in(|con| mongo_server(con, false)).await;
+            let found = inspect(open, &config()).await.unwrap().unwrap();
+            assert_eq!(found, Exposure::new(Datastore::MongoDb, Some("6.0.5".to_string()), false));
+
+            let auth = stand_in(|con| mongo_server(con, true)).await;
+            let found = inspect(auth, &config()).await.unwrap().unwrap();
+            assert_eq!(found, Exposure::new(Datastore::MongoDb, Some("6.0.5".to_string()), true));
+        });
+    }
+
+    #[test]
+    fn elasticsearch() {
+        Runtime::new().unwrap().block_on(async {
+            let serve = |secured: bool| move |con: TcpStream| async move {
+                let mut con = BufReader::new(con);
+                let mut line = String::new();
+                if con.read_line(&mut line).await.unwrap_or(0) == 0 || line != "GET / HTTP/1.1\r\n" {
+                    return
+                }
+                while line != "\r\n" {
+                    line.clear();
+                    if con.read_line(&mut line).await.unwrap_or(0) == 0 {
+                        return
+                    }
+                }
+
+                let response = match secured {
+                    true => {
+                        let body = r#"{"error":{"type":"security_exception"},"status":401}"#;
+                        format!("HTTP/1.1 401 Unauthorized\r\nWWW-Authenticate: Basic realm=\"security\" charset=\"UTF-8\"\r\nContent-Length: {}\r\n\r\n{}", body.len(), body)
+                    },
+                    false => {
+                        let body = r#"{"name":"node-1","cluster_name":"docker-cluster","version":{"number":"7.17.9"},"tagline":"You Know, for Search"}"#;
+                        format!("HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}", body.len(), body)
+                    },
+                };
+                let _ = con.write_all(response.as_bytes()).await;
+            };
+
+            let open = stand_in(serve(false)).await;
+            let found = inspect(open, &config()).await.unwrap().unwrap();
+            assert_eq!(found, Exposure::new(Datastore::Elasticsearch, Some("7.17.9".to_string()), false));
+
+            let secured = stand_in(serve(true)).await;
+            let found = inspect(secured, &config()).await.unwrap().unwrap();
+            assert_eq!(found, Exposure::new(Datastore::Elasticsearch, None, true));
+        });
+    }
+
+    #[test]
+    fn postgresql() {
+        Runtime::new().unwrap().block_on(async {
+            let trust = stand_in(|con| postgres_server(con, true)).await;
+            let found = inspect(trust, &login()).await.unwrap().unwrap();
+            assert_eq!(found, Exposure::new(Datastore::PostgreSql, Some("15.3".to_string()), false));
+
+            let md5 = stand_in(|con| postgres_server(con, false)).await;
+            let found = inspect(md5, &login()).await.unwrap().unwrap();
+            assert_eq!(found, Exposure::new(Datastore::PostgreSql, None, true));
+
+            // never started up without login
+            let found = inspect(trust, &config()).await.unwrap().unwrap();
+            assert_eq!(found, Exposure::unchecked(Datastore::PostgreSql, None));
+            assert_eq!(found.details(), None);
+        });
+    }
+
+    #[test]
+    fn mysql() {
+        Runtime::new().unwrap().block_on(async {
+            let empty_password = stand_in(|con| mysql_server(con, true)).await;
+            let found = inspect(empty_password, &login()).await.unwrap().unwrap();
+            assert_eq!(found, Exposure::new(Datastore::MySql, Some("8.0.33".to_string()), false));
+
+            // the greeting alone without login
+            let found = inspect(empty_password, &config()).await.unwrap().unwrap();
+            assert_eq!(found, Exposure::unchecked(Datastore::MySql, Some("8.0.33".to_string())));
+
+            let denied = stand_in(|con| mysql_server(con, false)).await;
+            let found = inspect(denied, &login()).await.unwrap().unwrap();
+            assert_eq!(found, Exposure::new(Datastore::MySql, Some("8.0.33".to_string()), true));
+            assert_eq!(found.service().unwrap().product.as_deref(), Some("MySQL"));
+        });
+    }
+}
@@ -0,0 +1,490 @@
+use px_core::{
+    pool::{JobCtrl, CRON, JobErr},
+    error::Error,
+    model::State,
+    wrapper::{NetworkInterface, TcpInterface, UdpInterface, pivot},
+};
+use serde::Serialize;
+
+use std::{
+    net::SocketAddr,
+    time::Duration,
+};
+use super::{Job, udp::ATTEMPTS};
+use crate::cli::output::{Report, ServiceInfo};
+
+/// Outside name asked for to test recursion, unless told otherwise
+pub const DEFAULT_NAME: &str = "example.com";
+
+const MAX_MESSAGE: usize = 65535;
+
+const TYPE_A: u16 = 1;
+const TYPE_TXT: u16 = 16;
+const CLASS_IN: u16 = 1;
+const CLASS_CHAOS: u16 = 3;
+
+/// flags
+const QR: u16 = 0x8000;
+const TC: u16 = 0x0200;
+const RD: u16 = 0x0100;
+const RA: u16 = 0x0080;
+
+#[derive(Debug)]
+pub struct DnsConfig {
+    /// given to every datagram/connection
+    pub timeout: Duration,
+    /// resolved through the server, shouldn't be in any of its zones
+    pub name: String,
+}
+
+impl DnsConfig {
+    /// Upper bound on a probe, three queries each sent over udp `ATTEMPTS` times,
+    /// then asked again over tcp, connecting and exchanging under a timeout each
+    pub fn budget(&self) -> Duration {
+        (self.timeout * ATTEMPTS as u32 + self.timeout * 2) * 3
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    Udp,
+    Tcp,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DnsReport {
+    /// what the server answered over first
+    pub transport: Transport,
+    /// TXT CHAOS "version.bind"
+    pub version: Option<String>,
+    /// TXT CHAOS "hostname.bind"
+    pub hostname: Option<String>,
+    /// the server set RA in its answer
+    pub recursion_available: bool,
+    /// the server resolved the outside name for us, an open resolver
+    pub recursive: bool,
+}
+
+impl Report for DnsReport {
+    fn service(&self) -> Option<ServiceInfo> {
+        Some(ServiceInfo {
+            name: "domain".to_string(),
+            product: None,
+            version: None,
+            cpe: Vec::new(),
+        })
+    }
+
+    fn details(&self) -> Option<String> {
+        let mut details = vec![format!("{:?}", self.transport).to_lowercase()];
+
+        if let Some(version) = &self.version {
+            details.push(format!("version.bind: {}", version));
+        }
+        if let Some(hostname) = &self.hostname {
+            details.push(format!("hostname.bind: {}", hostname));
+        }
+        if self.recursive {
+            details.push("open resolver".to_string());
+        }
+        else if self.recursion_available {
+            details.push("recursion available".to_string());
+        }
+
+        Some(details.join("; "))
+    }
+
+    fn data(&self) -> Option<serde_json::Value> {
+        serde_json::to_value(self).ok()
+    }
+}
+
+/// Checks if a port speaks DNS, first over udp then over tcp,
+/// then asks for `version.bind`/`hostname.bind` and resolves an outside name with recursion desired.
+///
+/// Over udp, truncated answers are asked again over tcp.
+/// Through pivots (`--pivot`), only tcp is used.
+/// A port answering neither is open|filtered (udp silent) or open (tcp connected), without a report.
+#[derive(Debug)]
+pub struct DnsScanner;
+
+#[async_trait::async_trait]
+impl CRON for DnsScanner {
+    type State = Job<DnsConfig>;
+    type Response = Option<DnsReport>;
+
+    async fn exec(state: &mut Job<DnsConfig>) -> Result<JobCtrl<Self::Response>, Error> {
+        match probe(state.addr, &state.config).await {
+            Ok((netstate, found)) => Ok(JobCtrl::Return(netstate, found)),
+
+            Err(Error::IO(err)) => Ok(JobCtrl::Error(super::handle_io_error(err))),
+            Err(e) => {
+                eprintln!("unmatched error {:#?} [not io error]", e);
+                Ok(JobCtrl::Error(JobErr::Other))
+            }
+        }
+    }
+}
+
+/// Errors are returned when neither udp or tcp say anything about the port,
+/// a refused tcp connection after an unanswered datagram means closed
+pub async fn probe(addr: SocketAddr, config: &DnsConfig) -> Result<(State, Option<DnsReport>), Error> {
+    let version = Query::new("version.bind", TYPE_TXT, CLASS_CHAOS, false);
+
+    let mut udp = UdpInterface::connect(addr, config.timeout).await?;
+    // udp can't go through the pivots, and shouldn't leave from here either
+    let udp_answer = match pivot::route() {
+        Some(_) => Err(std::io::ErrorKind::TimedOut.into()),
+        None => query_udp(&mut udp, &version).await
+    };
+
+    let (transport, answer) = match udp_answer {
+        Ok(answer) => (Transport::Udp, answer),
+        Err(udp_err) => match TcpInterface::connect(addr, config.timeout).await {
+            Ok(tcp) => match exchange_tcp(tcp, &version, config.timeout).await {
+                Ok(answer) => (Transport::Tcp, answer),
+                // connected, but it isn't dns
+                Err(_) => return Ok((State::Open, None))
+            },
+            Err(tcp_err) if udp_err.kind() == std::io::ErrorKind::TimedOut => {
+                if let Some(net_state) = pivot::reply_state(&tcp_err) {
+                    return Ok((net_state, None))
+                }
+                return match tcp_err.kind() {
+                    std::io::ErrorKind::ConnectionRefused => Ok((State::Closed, None)),
+                    _ if pivot::is_fault(&tcp_err) || pivot::is_transient(&tcp_err) => Err(tcp_err.into()),
+                    _ => Ok((State::OpenFiltered, None)),
+                }
+            },
+            Err(tcp_err) => return Err(tcp_err.into())
+        }
+    };
+
+    let mut found = DnsReport {
+        transport,
+        version: answer.txt(),
+        hostname: None,
+        recursion_available: false,
+        recursive: false,
+    };
+
+    let hostname = Query::new("hostname.bind", TYPE_TXT, CLASS_CHAOS, false);
+    found.hostname = query(addr, transport, &mut udp, &hostname, config.timeout).await
+        .and_then(|answer| answer.txt());
+
+    let outside = Query::new(&config.name, TYPE_A, CLASS_IN, true);
+    if let Some(answer) = query(addr, transport, &mut udp, &outside, config.timeout).await {
+        found.recursion_available = answer.flags & RA != 0;
+        found.recursive = answer.rcode() == 0 && answer.answers > 0;
+    }
+
+    Ok((State::Open, Some(found)))
+}
+
+/// Over `transport`, and over tcp when the udp answer was truncated
+async fn query(addr: SocketAddr, transport: Transport, udp: &mut UdpInterface, query: &Query, timeout: Duration) -> Option<Answer> {
+    match transport {
+        Transport::Udp => match query_udp(udp, query).await {
+            Ok(answer) if answer.flags & TC != 0 => query_tcp(addr, query, timeout).await.ok(),
+            Ok(answer) => Some(answer),
+            Err(_) => None
+        },
+        Transport::Tcp => query_tcp(addr, query, timeout).await.ok()
+    }
+}
+
+/// Retries unanswered datagrams, and skips answers to anything else
+async fn query_udp(iface: &mut UdpInterface, query: &Query) -> Result<Answer, std::io::Error> {
+    let message = query.encode();
+
+    for _ in 0..ATTEMPTS {
+        iface.write_iface(&message).await?;
+
+        loop {
+            let mut buf = Vec::new();
+            match iface.read_iface(&mut buf, MAX_MESSAGE).await {
+                Ok(_) => match Answer::parse(&buf, query.id) {
+                    Some(answer) => return Ok(answer),
+                    None => continue
+                },
+                Err(e) if e.kind() == std::io::ErrorKind::TimedOut => break,
+                Err(e) => return Err(e)
+            }
+        }
+    }
+
+    Err(std::io::ErrorKind::TimedOut.into())
+}
+
+/// A fresh connection for every query
+async fn query_tcp(addr: SocketAddr, query: &Query, timeout: Duration) -> Result<Answer, std::io::Error> {
+    exchange_tcp(TcpInterface::connect(addr, timeout).await?, query, timeout).await
+}
+
+/// Messages over tcp are length prefixed (RFC 1035 4.2.2).
+/// The whole exchange gets `timeout`, however slowly the answer trickles in
+async fn exchange_tcp(iface: TcpInterface, query: &Query, timeout: Duration) -> Result<Answer, std::io::Error> {
+    tokio::time::timeout(timeout, exchange(iface, query)).await
+        .unwrap_or_else(|_| Err(std::io::ErrorKind::TimedOut.into()))
+}
+
+async fn exchange(mut iface: TcpInterface, query: &Query) -> Result<Answer, std::io::Error> {
+    let message = query.encode();
+    let mut framed = (message.len() as u16).to_be_bytes().to_vec();
+    framed.extend_from_slice(&message);
+    iface.write_iface(&framed).await?;
+
+    let mut buf = Vec::new();
+    iface.read_exact_iface(&mut buf, 2).await?;
+    let length = u16::from_be_bytes([buf[0], buf[1]]) as usize;
+
+    buf.clear();
+    iface.read_exact_iface(&mut buf, length).await?;
+
+    Answer::parse(&buf, query.id)
+        .ok_or_else(|| std::io::ErrorKind::InvalidData.into())
+}
+
+#[derive(Debug, Clone)]
+struct Query {
+    id: u16,
+    name: String,
+    qtype: u16,
+    class: u16,
+    recursion: bool,
+}
+
+impl Query {
+    fn new(name: &str, qtype: u16, class: u16, recursion: bool) -> Self {
+        Self {
+            id: super::rand_u64() as u16,
+            name: name.to_string(),
+            qtype,
+            class,
+            recursion,
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let flags = if self.recursion { RD } else { 0 };
+
+        let mut message = Vec::with_capacity(32 + self.name.len());
+        message.extend_from_slice(&self.id.to_be_bytes());
+        message.extend_from_slice(&flags.to_be_bytes());
+        // one question, no answers, authorities or additionals
+        message.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+
+        for label in self.name.trim_end_matches('.').split('.').filter(|x| !x.is_empty()) {
+            message.push(label.len().min(63) as u8);
+            message.extend_from_slice(&label.as_bytes()[..label.len().min(63)]);
+        }
+        message.push(0);
+
+        message.extend_from_slice(&self.qtype.to_be_bytes());
+        message.extend_from_slice(&self.class.to_be_bytes());
+        message
+    }
+}
+
+/// The parts of a response we look at
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Answer {
+    flags: u16,
+    answers: u16,
+    /// character-strings of every TXT answer
+    txt: Vec<String>,
+}
+
+impl Answer {
+    /// `None` unless it's a response to `id`
+    fn parse(message: &[u8], id: u16) -> Option<Self> {
+        if message.len() < 12 || u16::from_be_bytes([message[0], message[1]]) != id {
+            return None
+        }
+
+        let flags = u16::from_be_bytes([message[2], message[3]]);
+        if flags & QR == 0 {
+            return None
+        }
+
+        let questions = u16::from_be_bytes([message[4], message[5]]);
+        let answers = u16::from_be_bytes([message[6], message[7]]);
+
+        let mut at = 12;
+        for _ in 0..questions {
+            at = skip_name(message, at)? + 4;
+        }
+
+        let mut txt = Vec::new();
+        for _ in 0..answers {
+            at = skip_name(message, at)?;
+            let header = message.get(at..at + 10)?;
+            let rtype = u16::from_be_bytes([header[0], header[1]]);
+            let length = u16::from_be_bytes([header[8], header[9]]) as usize;
+            let data = message.get(at + 10..at + 10 + length)?;
+            at += 10 + length;
+
+            if rtype == TYPE_TXT {
+                let mut strings = data;
+                while let Some((&size, rest)) = strings.split_first() {
+                    let string = rest.get(..size as usize)?;
+                    txt.push(String::from_utf8_lossy(string).to_string());
+                    strings = &rest[size as usize..];
+                }
+            }
+        }
+
+        Some(Self { flags, answers, txt })
+    }
+
+    fn rcode(&self) -> u16 {
+        self.flags & 0x000f
+    }
+
+    fn txt(&self) -> Option<String> {
+        if self.rcode() != 0 || self.txt.is_empty() { None }
+        else { Some(self.txt.join("")) }
+    }
+}
+
+/// Offset right after the name at `at`, compression pointers end names
+fn skip_name(message: &[u8], mut at: usize) -> Option<usize> {
+    loop {
+        let size = *message.get(at)?;
+        match size {
+            0 => return Some(at + 1),
+            size if size & 0xc0 == 0xc0 => return Some(at + 2),
+            size => at += 1 + size as usize,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::{
+        runtime::Runtime,
+        net::{TcpListener, UdpSocket},
+        io::{AsyncReadExt, AsyncWriteExt},
+    };
+
+    /// Answers CHAOS TXT queries, and A queries with recursion desired when `recursive`
+    fn answer(query: &[u8], recursive: bool) -> Vec<u8> {
+        let end = skip_name(query, 12).unwrap();
+        let qtype = u16::from_be_bytes([query[end], query[end + 1]]);
+        let desired = u16::from_be_bytes([query[2], query[3]]) & RD != 0;
+
+        let mut reply = query[..end + 4].to_vec();
+        let mut flags = QR | if desired { RD } else { 0 } | if recursive { RA } else { 0 };
+
+        let record: Option<Vec<u8>> = match qtype {
+            TYPE_TXT => {
+                let text: &[u8] = if query[13..].starts_with(b"version") { b"9.16.1-Ubuntu" } else { b"ns1" };
+                let mut data = vec![text.len() as u8];
+                data.extend_from_slice(text);
+                Some([&[0xc0, 12, 0, 16, 0, 3, 0, 0, 0, 0][..], &(data.len() as u16).to_be_bytes(), &data].concat())
+            },
+            TYPE_A if desired && recursive => Some(vec![0xc0, 12, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 93, 184, 216, 34]),
+            // REFUSED
+            _ => { flags |= 5; None }
+        };
+
+        reply[2..4].copy_from_slice(&flags.to_be_bytes());
+        reply[6..8].copy_from_slice(&(record.is_some() as u16).to_be_bytes());
+        reply.extend(record.unwrap_or_default());
+        reply
+    }
+
+    fn config() -> DnsConfig {
+        DnsConfig {
+            timeout: Duration::from_millis(500),
+            name: DEFAULT_NAME.to_string(),
+        }
+    }
+
+    #[test]
+    fn open_resolver_over_udp() {
+        let rt = Runtime::new().unwrap();
+
+        rt.block_on(async move {
+            let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let addr = socket.local_addr().unwrap();
+
+            tokio::spawn(async move {
+                let mut buf = [0; 512];
+                loop {
+                    let (n, peer) = socket.recv_from(&mut buf).await.unwrap();
+                    socket.send_to(&answer(&buf[..n], true), peer).await.unwrap();
+                }
+            });
+
+            let (state, found) = probe(addr, &config()).await.unwrap();
+            let found = found.unwrap();
+
+            assert_eq!(state, State::Open);
+            assert_eq!(found.transport, Transport::Udp);
+            assert_eq!(found.version.as_deref(), Some("9.16.1-Ubuntu"));
+            assert_eq!(found.hostname.as_deref(), Some("ns1"));
+            assert!(found.recursion_available);
+            assert!(found.recursive);
+        });
+    }
+
+    #[test]
+    fn tcp_fallback() {
+        let rt = Runtime::new().unwrap();
+
+        rt.block_on(async move {
+            // nothing on the udp side, datagrams are refused
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+
+            tokio::spawn(async move {
+                loop {
+                    let (mut con, _) = listener.accept().await.unwrap();
+                    tokio::spawn(async move {
+                        let length = con.read_u16().await.unwrap() as usize;
+                        let mut query = vec![0; length];
+                        con.read_exact(&mut query).await.unwrap();
+
+                        let reply = answer(&query, false);
+                        con.write_u16(reply.len() as u16).await.unwrap();
+                        con.write_all(&reply).await.unwrap();
+                    });
+                }
+            });
+
+            let (state, found) = probe(addr, &config()).await.unwrap();
+            let found = found.unwrap();
+
+            assert_eq!(state, State::Open);
+            assert_eq!(found.transport, Transport::Tcp);
+            assert_eq!(found.version.as_deref(), Some("9.16.1-Ubuntu"));
+            assert!(!found.recursive);
+        });
+    }
+
+    #[test]
+    fn refused_after_silence_is_closed() {
+        let rt = Runtime::new().unwrap();
+
+        rt.block_on(async move {
+            // datagrams go unanswered, and nothing listens on tcp
+            let silent = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let addr = silent.local_addr().unwrap();
+
+            let config = config();
+            let (state, found) = probe(addr, &config).await.unwrap();
+            assert_eq!(found, None);
+            assert_eq!(state, State::Closed);
+            assert!(config.budget() >= config.timeout * 11);
+        });
+    }
+
+    #[test]
+    fn encodes_query() {
+        let query = Query { id: 0x1337, name: "version.bind".to_string(), qtype: TYPE_TXT, class: CLASS_CHAOS, recursion: false };
+        assert_eq!(&query.encode()[..], &b"\x13\x37\x00\x00\x00\x01\x00\x00\x00\x00\x00\x00\x07version\x04bind\x00\x00\x10\x00\x03"[..]);
+    }
+}
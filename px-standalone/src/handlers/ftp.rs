@@ -0,0 +1,289 @@
+use px_core::{
+    pool::{JobCtrl, CRON, JobErr},
+    error::Error,
+    model::State,
+    wrapper::{NetworkInterface, TcpInterface, Protocol, Reply, CodedSession, Exchange},
+};
+use serde::Serialize;
+
+use std::{
+    net::SocketAddr,
+    time::Duration,
+};
+use super::Job;
+use crate::cli::output::{Report, ServiceInfo};
+
+/// Sent as the anonymous user's password, RFC 1635 asks for an email address
+const ANONYMOUS_PASSWORD: &str = "anonymous@example.com";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FtpInfo {
+    /// text of the greeting, every line of it
+    pub banner: String,
+    /// SYST's answer, "UNIX Type: L8"
+    pub system: Option<String>,
+    /// FEAT's list, as sent
+    pub features: Vec<String>,
+    /// logged in as "anonymous"
+    pub anonymous: bool,
+    /// offers explicit tls (RFC 4217)
+    pub auth_tls: bool,
+}
+
+impl FtpInfo {
+    fn lists_auth_tls(&self) -> bool {
+        self.features.iter().any(|feature| {
+            let mut words = feature.split_whitespace();
+            words.next().is_some_and(|x| x.eq_ignore_ascii_case("AUTH"))
+                && words.flat_map(|x| x.split(';')).any(|x| x.eq_ignore_ascii_case("TLS"))
+        })
+    }
+}
+
+impl Report for FtpInfo {
+    fn service(&self) -> Option<ServiceInfo> {
+        Some(ServiceInfo {
+            name: "ftp".to_string(),
+            product: None,
+            version: None,
+            cpe: Vec::new(),
+        })
+    }
+
+    fn details(&self) -> Option<String> {
+        let mut details = vec![self.banner.clone()];
+
+        if self.anonymous {
+            details.push("anonymous login allowed".to_string());
+        }
+        if self.auth_tls {
+            details.push("AUTH TLS".to_string());
+        }
+        if let Some(system) = &self.system {
+            details.push(system.clone());
+        }
+
+        Some(details.join("; "))
+    }
+
+    fn data(&self) -> Option<serde_json::Value> {
+        serde_json::to_value(self).ok()
+    }
+}
+
+#[derive(Debug)]
+pub struct FtpConfig {
+    pub timeout: Duration,
+}
+
+/// Reads the greeting, asks for `FEAT` and `SYST`,
+/// then tries logging in as "anonymous".
+/// `AUTH TLS` is taken from `FEAT`, servers that don't list it are asked on a second connection,
+/// the tls handshake is never started.
+/// Ports that don't greet like FTP are open, without a response.
+#[derive(Debug)]
+pub struct FtpScanner;
+
+#[async_trait::async_trait]
+impl CRON for FtpScanner {
+    type State = Job<FtpConfig>;
+    type Response = Option<FtpInfo>;
+
+    async fn exec(state: &mut Job<FtpConfig>) -> Result<JobCtrl<Self::Response>, Error> {
+        match inspect(state.addr, state.config.timeout).await {
+            Ok(found) => Ok(JobCtrl::Return(State::Open, found)),
+
+            Err(Error::IO(err)) => Ok(JobCtrl::Error(super::handle_io_error(err))),
+            Err(e) => {
+                eprintln!("unmatched error {:#?} [not io error]", e);
+                Ok(JobCtrl::Error(JobErr::Other))
+            }
+        }
+    }
+}
+
+/// Errors when the port can't be connected to,
+/// the AUTH TLS connection failing only leaves `auth_tls` unset
+pub async fn inspect(addr: SocketAddr, timeout: Duration) -> Result<Option<FtpInfo>, Error> {
+    let iface = TcpInterface::connect(addr, timeout).await?;
+    let mut con = Connection { iface, session: CodedSession::default() };
+
+    let banner = match con.greeting().await {
+        Some(banner) => banner,
+        None => return Ok(None)
+    };
+
+    let mut found = FtpInfo {
+        banner: banner.message(),
+        system: None,
+        features: Vec::new(),
+        anonymous: false,
+        auth_tls: false,
+    };
+
+    if let Some(reply) = con.command("FEAT").await.filter(|reply| reply.positive()) {
+        // the first and last lines frame the list
+        let count = reply.lines.len().saturating_sub(2);
+        found.features = reply.lines.iter()
+            .skip(1)
+            .take(count)
+            .map(|line| line.trim().to_string())
+            .filter(|line| !line.is_empty())
+            .collect();
+    }
+    found.auth_tls = found.lists_auth_tls();
+
+    found.system = con.command("SYST").await
+        .filter(|reply| reply.positive())
+        .map(|reply| reply.text().to_string());
+
+    found.anonymous = con.anonymous().await;
+    let _ = con.command("QUIT").await;
+
+    if !found.auth_tls {
+        found.auth_tls = offers_auth_tls(addr, timeout).await;
+    }
+
+    Ok(Some(found))
+}
+
+/// Asks for tls on a connection of its own, nothing can be sent in the clear after it's accepted
+async fn offers_auth_tls(addr: SocketAddr, timeout: Duration) -> bool {
+    let iface = match TcpInterface::connect(addr, timeout).await {
+        Ok(iface) => iface,
+        Err(_) => return false
+    };
+    let mut con = Connection { iface, session: CodedSession::default() };
+
+    if con.greeting().await.is_none() {
+        return false
+    }
+    matches!(con.command("AUTH TLS").await, Some(Reply { code: 234, .. }))
+}
+
+struct Connection<I> {
+    iface: I,
+    session: CodedSession,
+}
+
+impl<I: NetworkInterface> Connection<I> {
+    /// A reply (RFC 959 4.2), "123-" starts a multi-line one, ended by a line starting with "123 "
+    async fn reply(&mut self) -> Option<Reply> {
+        self.session.interact(&mut self.iface, Exchange::Read).await.ok()
+    }
+
+    async fn command(&mut self, command: &str) -> Option<Reply> {
+        self.session.interact(&mut self.iface, Exchange::command(command)).await.ok()
+    }
+
+    /// 220, possibly after a few 120s (ready in a few minutes).
+    /// 421 is an FTP server too busy to talk, nothing else is FTP
+    async fn greeting(&mut self) -> Option<Reply> {
+        loop {
+            let reply = self.reply().await?;
+            match reply.code {
+                120 => continue,
+                220 | 421 => return Some(reply),
+                _ => return None
+            }
+        }
+    }
+
+    async fn anonymous(&mut self) -> bool {
+        match self.command("USER anonymous").await {
+            Some(Reply { code: 230, .. }) => true,
+            Some(Reply { code: 331, .. }) => {
+                let pass = format!("PASS {}", ANONYMOUS_PASSWORD);
+                matches!(self.command(&pass).await, Some(Reply { code: 230, .. }))
+            },
+            _ => false
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::{
+        runtime::Runtime,
+        net::TcpListener,
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    };
+
+    /// Lists `AUTH TLS` in its features when `feat` is set, answers `AUTH TLS` either way,
+    /// and lets anyone in when `anonymous` is set
+    async fn server(feat: bool, anonymous: bool) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            loop {
+                let (con, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let mut con = BufReader::new(con);
+                    con.write_all(b"220-Welcome\r\n220 (vsFTPd 3.0.3)\r\n").await.unwrap();
+
+                    let mut line = String::new();
+                    while con.read_line(&mut line).await.unwrap_or(0) > 0 {
+                        let reply: &[u8] = match line.trim_end() {
+                            "FEAT" if feat => b"211-Features:\r\n EPRT\r\n AUTH TLS\r\n PASV\r\n UTF8\r\n211 End\r\n",
+                            "FEAT" => b"500 Unknown command.\r\n",
+                            "SYST" => b"215 UNIX Type: L8\r\n",
+                            "USER anonymous" => b"331 Please specify the password.\r\n",
+                            "PASS anonymous@example.com" if anonymous => b"230 Login successful.\r\n",
+                            "PASS anonymous@example.com" => b"530 Login incorrect.\r\n",
+                            "AUTH TLS" => b"234 Proceed with negotiation.\r\n",
+                            "QUIT" => b"221 Goodbye.\r\n",
+                            _ => b"500 Unknown command.\r\n",
+                        };
+                        con.write_all(reply).await.unwrap();
+                        line.clear();
+                    }
+                });
+            }
+        });
+
+        addr
+    }
+
+    #[test]
+    fn anonymous_with_features() {
+        Runtime::new().unwrap().block_on(async {
+            let addr = server(true, true).await;
+            let found = inspect(addr, Duration::from_secs(2)).await.unwrap().unwrap();
+
+            assert_eq!(found.banner, "Welcome (vsFTPd 3.0.3)");
+            assert_eq!(found.system.as_deref(), Some("UNIX Type: L8"));
+            assert_eq!(found.features, vec!["EPRT", "AUTH TLS", "PASV", "UTF8"]);
+            assert!(found.anonymous);
+            assert!(found.auth_tls);
+            assert_eq!(found.details().unwrap(), "Welcome (vsFTPd 3.0.3); anonymous login allowed; AUTH TLS; UNIX Type: L8");
+        });
+    }
+
+    #[test]
+    fn asks_for_tls_without_feat() {
+        Runtime::new().unwrap().block_on(async {
+            let addr = server(false, false).await;
+            let found = inspect(addr, Duration::from_secs(2)).await.unwrap().unwrap();
+
+            assert!(found.features.is_empty());
+            assert!(!found.anonymous);
+            assert!(found.auth_tls);
+        });
+    }
+
+    #[test]
+    fn not_ftp() {
+        Runtime::new().unwrap().block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            tokio::spawn(async move {
+                let (mut con, _) = listener.accept().await.unwrap();
+                con.write_all(b"SSH-2.0-OpenSSH_8.9\r\n").await.unwrap();
+            });
+
+            assert_eq!(inspect(addr, Duration::from_secs(2)).await.unwrap(), None);
+        });
+    }
+}
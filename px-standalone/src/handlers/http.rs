@@ -0,0 +1,460 @@
+use px_core::{
+    pool::{JobCtrl, CRON, JobErr},
+    error::Error,
+    model::State,
+    wrapper::{NetworkInterface, TcpInterface, TlsInterface, insecure_config},
+};
+use tokio_rustls::rustls::{ClientConfig, Session};
+use serde::Serialize;
+
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    time::Duration,
+};
+use super::Job;
+use crate::cli::output::{Report, ServiceInfo};
+
+/// Most we'll read of a response's head
+const MAX_HEAD: usize = 16 * 1024;
+/// Most we'll read of a body, looking for its title
+const MAX_BODY: usize = 64 * 1024;
+
+/// Ports that are spoken to over tls first, every other port gets plaintext first
+pub const TLS_PORTS: [u16; 5] = [443, 4443, 8443, 9443, 10443];
+
+/// base64url of the SETTINGS payload offered in the upgrade,
+/// SETTINGS_MAX_CONCURRENT_STREAMS 100 and SETTINGS_INITIAL_WINDOW_SIZE 65535
+const H2C_SETTINGS: &str = "AAMAAABkAAQAAP__";
+
+pub struct HttpConfig {
+    pub timeout: Duration,
+    /// offers only http/1.1 over ALPN, the request is sent over it
+    pub tls: Arc<ClientConfig>,
+    /// offers h2 first, only used to see what the server picks
+    pub alpn: Arc<ClientConfig>,
+}
+
+impl HttpConfig {
+    pub fn new(timeout: Duration) -> Self {
+        let mut tls = insecure_config();
+        tls.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+        let mut alpn = insecure_config();
+        alpn.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+        Self {
+            timeout,
+            tls: Arc::new(tls),
+            alpn: Arc::new(alpn),
+        }
+    }
+}
+
+impl std::fmt::Debug for HttpConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HttpConfig")
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+/// Everything a GET / had to say about the server
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HttpFingerprint {
+    /// the request went over tls
+    pub tls: bool,
+    pub status: u16,
+    pub reason: String,
+    pub server: Option<String>,
+    /// every header in the order they were sent, names as sent
+    pub headers: Vec<(String, String)>,
+    pub title: Option<String>,
+    /// `Location` of a redirect
+    pub location: Option<String>,
+    /// the server picked h2 out of ALPN
+    pub h2: bool,
+    /// the server accepted an `Upgrade: h2c`
+    pub h2c: bool,
+}
+
+impl HttpFingerprint {
+    /// First header called `name`, case-insensitive
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+impl Report for HttpFingerprint {
+    fn service(&self) -> Option<ServiceInfo> {
+        // "nginx/1.18.0 (Ubuntu)" -> nginx, 1.18.0
+        let (product, version) = match &self.server {
+            Some(server) => {
+                let first = server.split_whitespace().next().unwrap_or_default();
+                let mut parts = first.splitn(2, '/');
+
+                (
+                    parts.next().filter(|x| !x.is_empty()).map(str::to_string),
+                    parts.next().filter(|x| !x.is_empty()).map(str::to_string)
+                )
+            }
+            None => (None, None)
+        };
+
+        Some(ServiceInfo {
+            name: if self.tls { "https" } else { "http" }.to_string(),
+            product,
+            version,
+            cpe: Vec::new(),
+        })
+    }
+
+    fn details(&self) -> Option<String> {
+        let mut details = format!("{} {}", self.status, self.reason);
+
+        if let Some(title) = &self.title {
+            details.push_str(&format!(" \"{}\"", title));
+        }
+        if let Some(location) = &self.location {
+            details.push_str(&format!(" -> {}", location));
+        }
+        if self.h2 {
+            details.push_str(" [h2]");
+        }
+        if self.h2c {
+            details.push_str(" [h2c]");
+        }
+
+        Some(details)
+    }
+
+    fn data(&self) -> Option<serde_json::Value> {
+        serde_json::to_value(self).ok()
+    }
+}
+
+/// Sends an HTTP/1.1 GET / and records the response,
+/// then checks if HTTP/2 is offered through ALPN (tls) or an h2c upgrade (plaintext).
+///
+/// Ports in `TLS_PORTS` are tried over tls first,
+/// if the first attempt doesn't get an HTTP response, the other is tried.
+/// Ports answering neither are open, without a response.
+#[derive(Debug)]
+pub struct HttpScanner;
+
+#[async_trait::async_trait]
+impl CRON for HttpScanner {
+    type State = Job<HttpConfig>;
+    type Response = Option<HttpFingerprint>;
+
+    async fn exec(state: &mut Job<HttpConfig>) -> Result<JobCtrl<Self::Response>, Error> {
+        match fingerprint(state.addr, &state.config).await {
+            Ok(found) => Ok(JobCtrl::Return(State::Open, found)),
+
+            Err(Error::IO(err)) => Ok(JobCtrl::Error(super::handle_io_error(err))),
+            Err(e) => {
+                eprintln!("unmatched error {:#?} [not io error]", e);
+                Ok(JobCtrl::Error(JobErr::Other))
+            }
+        }
+    }
+}
+
+/// Errors when the port can't be connected to, for plaintext or tls.
+/// `None` when neither got an HTTP response, h2/h2c failing is only recorded as not offered
+pub async fn fingerprint(addr: SocketAddr, config: &HttpConfig) -> Result<Option<HttpFingerprint>, Error> {
+    let tls_first = TLS_PORTS.contains(&addr.port());
+
+    // proves the port is open before anything else is tried
+    let con = TcpInterface::connect(addr, config.timeout).await?;
+
+    let found = if tls_first {
+        match over_tls(con, config).await {
+            Some(found) => Some(found),
+            None => over_plaintext(addr, config).await
+        }
+    }
+    else {
+        match request(con, addr, false).await {
+            Some(found) => Some(found),
+            None => over_tls(TcpInterface::connect(addr, config.timeout).await?, config).await
+        }
+    };
+
+    let mut found = match found {
+        Some(found) => found,
+        None => return Ok(None)
+    };
+
+    if found.tls {
+        found.h2 = offers_h2(addr, config).await;
+    }
+    else {
+        found.h2c = upgrades_h2c(addr, config).await;
+    }
+
+    Ok(Some(found))
+}
+
+async fn over_plaintext(addr: SocketAddr, config: &HttpConfig) -> Option<HttpFingerprint> {
+    let con = TcpInterface::connect(addr, config.timeout).await.ok()?;
+    request(con, addr, false).await
+}
+
+async fn over_tls(con: TcpInterface, config: &HttpConfig) -> Option<HttpFingerprint> {
+    let addr = con.peer();
+    let iface = TlsInterface::handshake(con.into_inner(), addr, None, config.tls.clone(), config.timeout).await.ok()?;
+    request(iface, addr, true).await
+}
+
+async fn request<I: NetworkInterface>(mut iface: I, addr: SocketAddr, tls: bool) -> Option<HttpFingerprint> {
+    let request = format!(
+        "GET / HTTP/1.1\r\nHost: {}\r\nUser-Agent: Mozilla/5.0\r\nAccept: */*\r\nConnection: close\r\n\r\n",
+        host(addr)
+    );
+    iface.write_iface(request.as_bytes()).await.ok()?;
+
+    let (status, reason, headers, mut body) = read_head(&mut iface).await?;
+
+    let length = headers.iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.parse::<usize>().ok())
+        .unwrap_or(MAX_BODY)
+        .min(MAX_BODY);
+
+    // Connection: close, the body ends when the server hangs up
+    while body.len() < length && title(&body).is_none() {
+        let room = length - body.len();
+        match iface.read_iface(&mut body, room).await {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+    }
+
+    let mut found = HttpFingerprint {
+        tls,
+        status,
+        reason,
+        server: None,
+        title: title(&body),
+        location: None,
+        headers,
+        h2: false,
+        h2c: false,
+    };
+
+    found.server = found.header("server").map(str::to_string);
+    found.location = found.header("location").map(str::to_string);
+    Some(found)
+}
+
+/// The status line and headers,
+/// along with whatever of the body was read with them
+pub async fn read_head<I: NetworkInterface>(iface: &mut I) -> Option<(u16, String, Vec<(String, String)>, Vec<u8>)> {
+    let mut buf = Vec::new();
+    iface.read_until(&mut buf, b"\r\n\r\n", MAX_HEAD).await.ok()?;
+
+    let end = buf.windows(4).position(|x| x == b"\r\n\r\n")?;
+    let head = String::from_utf8_lossy(&buf[..end]).to_string();
+    let body = buf[end + 4..].to_vec();
+
+    let mut lines = head.split("\r\n");
+    let (status, reason) = status_line(lines.next()?)?;
+
+    let headers = lines
+        .filter_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            Some((key.trim().to_string(), value.trim().to_string()))
+        })
+        .collect();
+
+    Some((status, reason, headers, body))
+}
+
+/// "HTTP/1.1 301 Moved Permanently" -> 301, "Moved Permanently"
+fn status_line(line: &str) -> Option<(u16, String)> {
+    let mut parts = line.splitn(3, ' ');
+    if !parts.next()?.starts_with("HTTP/") {
+        return None
+    }
+
+    let status = parts.next()?.parse().ok()?;
+    Some((status, parts.next().unwrap_or_default().trim().to_string()))
+}
+
+/// Contents of the first <title>, whitespace collapsed
+fn title(body: &[u8]) -> Option<String> {
+    let body = String::from_utf8_lossy(body);
+    let lower = body.to_ascii_lowercase();
+
+    let open = lower.find("<title")?;
+    let start = open + lower[open..].find('>')? + 1;
+    let end = start + lower[start..].find("</title")?;
+
+    let title = body[start..end].split_whitespace().collect::<Vec<_>>().join(" ");
+    if title.is_empty() { None }
+    else { Some(title) }
+}
+
+/// The `Host` header's value for an address
+pub fn host(addr: SocketAddr) -> String {
+    match addr {
+        SocketAddr::V4(addr) => addr.ip().to_string(),
+        SocketAddr::V6(addr) => format!("[{}]", addr.ip()),
+    }
+}
+
+/// Handshakes again, offering h2 first
+async fn offers_h2(addr: SocketAddr, config: &HttpConfig) -> bool {
+    match TlsInterface::connect_with(addr, None, config.alpn.clone(), config.timeout).await {
+        Ok(iface) => iface.session().get_alpn_protocol() == Some(&b"h2"[..]),
+        Err(_) => false
+    }
+}
+
+/// Asks to upgrade to h2c on a fresh connection,
+/// anything after the 101 is already HTTP/2 and isn't read
+async fn upgrades_h2c(addr: SocketAddr, config: &HttpConfig) -> bool {
+    let mut iface = match TcpInterface::connect(addr, config.timeout).await {
+        Ok(iface) => iface,
+        Err(_) => return false
+    };
+
+    let request = format!(
+        "OPTIONS / HTTP/1.1\r\nHost: {}\r\nConnection: Upgrade, HTTP2-Settings\r\nUpgrade: h2c\r\nHTTP2-Settings: {}\r\n\r\n",
+        host(addr),
+        H2C_SETTINGS
+    );
+    if iface.write_iface(request.as_bytes()).await.is_err() {
+        return false
+    }
+
+    match read_head(&mut iface).await {
+        Some((101, _, headers, _)) => headers.iter()
+            .any(|(key, value)| key.eq_ignore_ascii_case("upgrade") && value.eq_ignore_ascii_case("h2c")),
+        _ => false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::{
+        runtime::Runtime,
+        net::TcpListener,
+        io::{AsyncRead, AsyncWrite, AsyncReadExt, AsyncWriteExt},
+    };
+    use tokio_rustls::{
+        TlsAcceptor,
+        rustls::{ServerConfig, NoClientAuth, Certificate, PrivateKey},
+    };
+
+    const PAGE: &[u8] = b"<html><head><title>\n  Welcome to nginx!\n</title></head></html>";
+
+    /// answers GETs with a redirect and a page, and h2c upgrades with a 101
+    async fn serve<S: AsyncRead + AsyncWrite + Unpin>(mut con: S) {
+        let mut buf = [0; 1024];
+        let n = match con.read(&mut buf).await {
+            Ok(n) => n,
+            Err(_) => return
+        };
+        let request = String::from_utf8_lossy(&buf[..n]).to_string();
+
+        let reply = if request.contains("Upgrade: h2c") {
+            b"HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\nUpgrade: h2c\r\n\r\n".to_vec()
+        }
+        else {
+            let mut reply = format!(
+                "HTTP/1.1 302 Found\r\nServer: nginx/1.18.0 (Ubuntu)\r\nLocation: /login\r\nContent-Length: {}\r\n\r\n",
+                PAGE.len()
+            ).into_bytes();
+            reply.extend_from_slice(PAGE);
+            reply
+        };
+
+        let _ = con.write_all(&reply).await;
+        let _ = con.flush().await;
+    }
+
+    fn tls_acceptor() -> TlsAcceptor {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let mut config = ServerConfig::new(NoClientAuth::new());
+        config.set_single_cert(vec![Certificate(cert.serialize_der().unwrap())], PrivateKey(cert.serialize_private_key_der())).unwrap();
+        config.set_protocols(&[b"h2".to_vec(), b"http/1.1".to_vec()]);
+        TlsAcceptor::from(Arc::new(config))
+    }
+
+    #[test]
+    fn plaintext() {
+        let rt = Runtime::new().unwrap();
+
+        rt.block_on(async move {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+
+            tokio::spawn(async move {
+                loop {
+                    let (con, _) = listener.accept().await.unwrap();
+                    tokio::spawn(serve(con));
+                }
+            });
+
+            let found = fingerprint(addr, &HttpConfig::new(Duration::from_secs(2))).await.unwrap().unwrap();
+
+            assert!(!found.tls);
+            assert_eq!(found.status, 302);
+            assert_eq!(found.reason, "Found");
+            assert_eq!(found.title.as_deref(), Some("Welcome to nginx!"));
+            assert_eq!(found.location.as_deref(), Some("/login"));
+            assert!(found.h2c);
+            assert!(!found.h2);
+
+            let service = found.service().unwrap();
+            assert_eq!(service.name, "http");
+            assert_eq!(service.product.as_deref(), Some("nginx"));
+            assert_eq!(service.version.as_deref(), Some("1.18.0"));
+        });
+    }
+
+    #[test]
+    fn alpn_h2() {
+        let rt = Runtime::new().unwrap();
+
+        rt.block_on(async move {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let acceptor = tls_acceptor();
+
+            tokio::spawn(async move {
+                loop {
+                    let (con, _) = listener.accept().await.unwrap();
+                    let acceptor = acceptor.clone();
+                    tokio::spawn(async move {
+                        if let Ok(con) = acceptor.accept(con).await {
+                            serve(con).await
+                        }
+                    });
+                }
+            });
+
+            // not a tls port, plaintext is tried first and fails
+            let found = fingerprint(addr, &HttpConfig::new(Duration::from_secs(2))).await.unwrap().unwrap();
+
+            assert!(found.tls);
+            assert_eq!(found.status, 302);
+            assert_eq!(found.server.as_deref(), Some("nginx/1.18.0 (Ubuntu)"));
+            assert!(found.h2);
+            assert!(!found.h2c);
+        });
+    }
+
+    #[test]
+    fn parses_head() {
+        assert_eq!(status_line("HTTP/1.0 200 OK"), Some((200, "OK".to_string())));
+        assert_eq!(status_line("SSH-2.0-OpenSSH_8.9"), None);
+        assert_eq!(title(b"<TITLE lang=en>Index of /</TITLE>"), Some("Index of /".to_string()));
+        assert_eq!(title(b"<title></title>"), None);
+    }
+}
@@ -0,0 +1,394 @@
+use px_core::{
+    pool::{JobCtrl, CRON, JobErr},
+    error::Error,
+    model::State,
+    wrapper::{NetworkInterface, TcpInterface},
+};
+
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    time::Duration,
+};
+use super::{canary::Canary, Job};
+use crate::cli::output::Report;
+
+/// Most we'll read of a response's head
+const MAX_HEAD: usize = 16 * 1024;
+/// Most we'll read of a response's body, looking for the canary's token
+const MAX_BODY: usize = 64 * 1024;
+
+/// How a proxy answered one of the requests
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// the canary's token made it back
+    Open,
+    /// 407
+    AuthRequired,
+    /// 403, 405, a proxy that won't do it for us, or just as likely a web server refusing the request
+    Denied(u16),
+    /// 2xx without the token, a web server answering any path, or a proxy that mangles bodies
+    Unverified(u16),
+    /// any other status
+    Other(u16),
+    /// didn't answer with HTTP
+    NotHttp,
+}
+
+impl Verdict {
+    fn from_status(status: u16) -> Self {
+        match status {
+            407 => Verdict::AuthRequired,
+            403 | 405 => Verdict::Denied(status),
+            200..=299 => Verdict::Unverified(status),
+            _ => Verdict::Other(status)
+        }
+    }
+
+    /// only an HTTP proxy would answer this way
+    pub fn is_proxy(&self) -> bool {
+        matches!(self, Verdict::Open | Verdict::AuthRequired)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyKind {
+    /// forwards `GET http://...` requests
+    Forward,
+    /// tunnels with `CONNECT`
+    Tunnel,
+    Both,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProxyReport {
+    pub addr: SocketAddr,
+    /// absolute-URI GET
+    pub forward: Verdict,
+    /// CONNECT
+    pub tunnel: Verdict,
+    /// either response had a `Proxy-*` header, "Proxy-Agent", "Proxy-Connection"...
+    pub proxy_header: bool,
+}
+
+impl ProxyReport {
+    /// What the proxy relays for us, `None` if it doesn't relay anything
+    pub fn kind(&self) -> Option<ProxyKind> {
+        match (self.forward == Verdict::Open, self.tunnel == Verdict::Open) {
+            (true, true) => Some(ProxyKind::Both),
+            (true, false) => Some(ProxyKind::Forward),
+            (false, true) => Some(ProxyKind::Tunnel),
+            (false, false) => None
+        }
+    }
+
+    pub fn auth_required(&self) -> bool {
+        self.forward == Verdict::AuthRequired || self.tunnel == Verdict::AuthRequired
+    }
+
+    pub fn denied(&self) -> bool {
+        matches!(self.forward, Verdict::Denied(_)) || matches!(self.tunnel, Verdict::Denied(_))
+    }
+
+    /// answered like a proxy to either of the requests
+    pub fn is_proxy(&self) -> bool {
+        self.forward.is_proxy() || self.tunnel.is_proxy() || self.proxy_header
+    }
+}
+
+impl Report for ProxyReport {
+    fn details(&self) -> Option<String> {
+        if !self.is_proxy() {
+            // web servers refuse both requests too
+            return match self.denied() {
+                true => Some(format!("denied [GET: {:?}, CONNECT: {:?}]", self.forward, self.tunnel)),
+                false => None
+            }
+        }
+
+        let kind = match self.kind() {
+            Some(ProxyKind::Both) => "open http proxy (GET, CONNECT)",
+            Some(ProxyKind::Forward) => "open http proxy (GET)",
+            Some(ProxyKind::Tunnel) => "open http proxy (CONNECT)",
+            None => "http proxy",
+        };
+
+        if self.auth_required() {
+            Some(format!("{}, authentication required", kind))
+        }
+        else {
+            Some(format!("{} [GET: {:?}, CONNECT: {:?}]", kind, self.forward, self.tunnel))
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct HttpProxyConfig {
+    /// requested through the proxy
+    pub canary: Arc<Canary>,
+    pub timeout: Duration,
+}
+
+/// Detects open HTTP forward proxies,
+/// by asking for the canary with an absolute-URI GET, and then with CONNECT
+#[derive(Debug)]
+pub struct HttpProxyScanner;
+
+#[async_trait::async_trait]
+impl CRON for HttpProxyScanner {
+    type State = Job<HttpProxyConfig>;
+    type Response = ProxyReport;
+
+    async fn exec(state: &mut Job<HttpProxyConfig>) -> Result<JobCtrl<Self::Response>, Error> {
+        match scan(state.addr, &state.config).await {
+            Ok(report) => Ok(JobCtrl::Return(State::Open, report)),
+
+            Err(Error::IO(err)) => Ok(JobCtrl::Error(super::handle_io_error(err))),
+            Err(e) => {
+                eprintln!("unmatched error {:#?} [not io error]", e);
+                Ok(JobCtrl::Error(JobErr::Other))
+            }
+        }
+    }
+}
+
+/// Errors when the GET's connection can't be made,
+/// the CONNECT's connection failing is only a `Verdict::NotHttp`
+pub async fn scan(addr: SocketAddr, config: &HttpProxyConfig) -> Result<ProxyReport, Error> {
+    let target = config.canary.addr();
+
+    let mut con = TcpInterface::connect(addr, config.timeout).await?;
+    let request = format!(
+        "GET http://{target}/ HTTP/1.1\r\nHost: {target}\r\nUser-Agent: Mozilla/5.0\r\nConnection: close\r\n\r\n",
+        target = target
+    );
+    let (forward, forward_header) = forward(&mut con, request.as_bytes(), &config.canary).await;
+
+    let (tunnel, tunnel_header) = match TcpInterface::connect(addr, config.timeout).await {
+        Ok(mut con) => {
+            let request = format!("CONNECT {target} HTTP/1.1\r\nHost: {target}\r\n\r\n", target = target);
+            tunnel(&mut con, request.as_bytes(), &config.canary).await
+        }
+        Err(_) => (Verdict::NotHttp, false)
+    };
+
+    Ok(ProxyReport { addr, forward, tunnel, proxy_header: forward_header || tunnel_header })
+}
+
+/// The verdict, and whether the response had a `Proxy-*` header
+async fn forward<I: NetworkInterface>(con: &mut I, request: &[u8], canary: &Canary) -> (Verdict, bool) {
+    let (status, mut buf, head_len) = match exchange(con, request).await {
+        Some(response) => response,
+        None => return (Verdict::NotHttp, false)
+    };
+    let header = proxy_header(&buf[..head_len]);
+
+    if !(200..300).contains(&status) {
+        return (Verdict::from_status(status), header)
+    }
+
+    // Connection: close, the body ends when the proxy hangs up
+    while buf.len() - head_len < MAX_BODY && !contains(&buf[head_len..], canary.token()) {
+        match con.read_iface(&mut buf, MAX_BODY).await {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+    }
+
+    if contains(&buf[head_len..], canary.token()) { (Verdict::Open, header) }
+    else { (Verdict::Unverified(status), header) }
+}
+
+/// Same as `forward`
+async fn tunnel<I: NetworkInterface>(con: &mut I, request: &[u8], canary: &Canary) -> (Verdict, bool) {
+    let (status, buf, head_len) = match exchange(con, request).await {
+        Some(response) => response,
+        None => return (Verdict::NotHttp, false)
+    };
+    let header = proxy_header(&buf[..head_len]);
+
+    if !(200..300).contains(&status) {
+        return (Verdict::from_status(status), header)
+    }
+
+    // whatever followed the head already came through the tunnel
+    let mut tunneled = buf[head_len..].to_vec();
+    let token = canary.token();
+
+    while tunneled.len() < token.len() {
+        let room = token.len() - tunneled.len();
+        match con.read_iface(&mut tunneled, room).await {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+    }
+
+    if tunneled.starts_with(token) { (Verdict::Open, header) }
+    else { (Verdict::Unverified(status), header) }
+}
+
+/// Sends `request` and reads the response's head.
+/// Returns the status, everything read so far, and where the head ends
+async fn exchange<I: NetworkInterface>(con: &mut I, request: &[u8]) -> Option<(u16, Vec<u8>, usize)> {
+    con.write_iface(request).await.ok()?;
+
+    let mut buf = Vec::new();
+    con.read_until(&mut buf, b"\r\n\r\n", MAX_HEAD).await.ok()?;
+
+    let head_len = buf.windows(4).position(|x| x == b"\r\n\r\n")? + 4;
+    Some((status(&buf)?, buf, head_len))
+}
+
+/// "HTTP/1.1 407 Proxy Authentication Required" -> 407
+fn status(response: &[u8]) -> Option<u16> {
+    let line = response.split(|b| *b == b'\n').next()?;
+    let line = std::str::from_utf8(line).ok()?;
+
+    let mut parts = line.split_whitespace();
+    if !parts.next()?.starts_with("HTTP/") {
+        return None
+    }
+
+    parts.next()?.parse().ok()
+}
+
+/// Any "Proxy-Agent:", "Proxy-Connection:"... header in `head`, the status line excluded
+fn proxy_header(head: &[u8]) -> bool {
+    String::from_utf8_lossy(head)
+        .split("\r\n")
+        .skip(1)
+        .any(|line| line.len() > 6 && line.as_bytes()[..6].eq_ignore_ascii_case(b"proxy-") && line.contains(':'))
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|x| x == needle)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::{
+        runtime::Runtime,
+        net::{TcpListener, TcpStream},
+        io::{AsyncReadExt, AsyncWriteExt, copy_bidirectional},
+    };
+
+    /// forwards absolute-URI GETs and tunnels CONNECTs,
+    /// or asks for credentials when `auth` is set
+    async fn proxy_server(listener: TcpListener, auth: bool) {
+        loop {
+            let (mut con, _) = listener.accept().await.unwrap();
+            tokio::spawn(async move {
+                let mut buf = [0; 2048];
+                let n = con.read(&mut buf).await.unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]).to_string();
+
+                if auth {
+                    let _ = con.write_all(b"HTTP/1.1 407 Proxy Authentication Required\r\nProxy-Authenticate: Basic realm=\"proxy\"\r\n\r\n").await;
+                    return
+                }
+
+                let mut words = request.split_whitespace();
+                let (method, target) = (words.next().unwrap(), words.next().unwrap());
+
+                if method == "CONNECT" {
+                    let mut upstream = TcpStream::connect(target).await.unwrap();
+                    con.write_all(b"HTTP/1.1 200 Connection established\r\n\r\n").await.unwrap();
+                    let _ = copy_bidirectional(&mut con, &mut upstream).await;
+                }
+                else {
+                    let host = target.trim_start_matches("http://").trim_end_matches('/');
+                    let mut upstream = TcpStream::connect(host).await.unwrap();
+                    upstream.write_all(format!("GET / HTTP/1.0\r\nHost: {}\r\n\r\n", host).as_bytes()).await.unwrap();
+                    let _ = copy_bidirectional(&mut con, &mut upstream).await;
+                }
+            });
+        }
+    }
+
+    async fn config() -> HttpProxyConfig {
+        HttpProxyConfig {
+            canary: Arc::new(Canary::bind("127.0.0.1:0".parse().unwrap()).await.unwrap()),
+            timeout: Duration::from_secs(2),
+        }
+    }
+
+    #[test]
+    fn open_proxy() {
+        let rt = Runtime::new().unwrap();
+
+        rt.block_on(async move {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            tokio::spawn(proxy_server(listener, false));
+
+            let report = scan(addr, &config().await).await.unwrap();
+            assert_eq!(report.forward, Verdict::Open);
+            assert_eq!(report.tunnel, Verdict::Open);
+            assert_eq!(report.kind(), Some(ProxyKind::Both));
+            assert!(!report.auth_required());
+        });
+    }
+
+    #[test]
+    fn auth_required() {
+        let rt = Runtime::new().unwrap();
+
+        rt.block_on(async move {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            tokio::spawn(proxy_server(listener, true));
+
+            let report = scan(addr, &config().await).await.unwrap();
+            assert_eq!(report.kind(), None);
+            assert!(report.auth_required());
+            assert!(report.is_proxy());
+        });
+    }
+
+    /// answers every request with `response`
+    async fn refusing_server(listener: TcpListener, response: &'static [u8]) {
+        loop {
+            let (mut con, _) = listener.accept().await.unwrap();
+            tokio::spawn(async move {
+                let mut buf = [0; 2048];
+                let _ = con.read(&mut buf).await;
+                let _ = con.write_all(response).await;
+            });
+        }
+    }
+
+    #[test]
+    fn not_a_proxy() {
+        let rt = Runtime::new().unwrap();
+
+        rt.block_on(async move {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            // what apache answers CONNECT with
+            tokio::spawn(refusing_server(listener, b"HTTP/1.1 405 Method Not Allowed\r\nAllow: GET, HEAD\r\nServer: Apache\r\n\r\n"));
+
+            let report = scan(addr, &config().await).await.unwrap();
+            assert_eq!(report.forward, Verdict::Denied(405));
+            assert_eq!(report.tunnel, Verdict::Denied(405));
+            assert!(!report.is_proxy());
+            assert_eq!(report.details().unwrap(), "denied [GET: Denied(405), CONNECT: Denied(405)]");
+        });
+    }
+
+    #[test]
+    fn denied_by_proxy() {
+        let rt = Runtime::new().unwrap();
+
+        rt.block_on(async move {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            tokio::spawn(refusing_server(listener, b"HTTP/1.1 403 Forbidden\r\nProxy-Agent: tinyproxy\r\n\r\n"));
+
+            let report = scan(addr, &config().await).await.unwrap();
+            assert_eq!(report.tunnel, Verdict::Denied(403));
+            assert!(report.is_proxy());
+            assert_eq!(report.kind(), None);
+        });
+    }
+}
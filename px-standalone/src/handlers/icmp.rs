@@ -0,0 +1,352 @@
+use px_core::{
+    pool::{JobCtrl, CRON, JobErr},
+    error::Error,
+    model::State,
+};
+
+use socket2::{Socket, Domain, Type, Protocol};
+use tokio::net::UdpSocket;
+
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::atomic::{AtomicU16, Ordering},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use super::handle_io_error;
+
+const ICMP_ECHO_REPLY: u8 = 0;
+const ICMP_ECHO: u8 = 8;
+const ICMP_TIMESTAMP: u8 = 13;
+const ICMP_TIMESTAMP_REPLY: u8 = 14;
+
+const ICMPV6_ECHO: u8 = 128;
+const ICMPV6_ECHO_REPLY: u8 = 129;
+
+const PAYLOAD: &[u8] = b"scurry-ping";
+
+static SEQUENCE: AtomicU16 = AtomicU16::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IcmpKind {
+    Echo,
+    Timestamp
+}
+
+#[derive(Debug, Clone)]
+pub struct PingReply {
+    pub addr: IpAddr,
+    pub kind: IcmpKind,
+    pub rtt: Duration,
+    /// "receive timestamp" the peer put in its timestamp reply,
+    /// milliseconds since midnight UT
+    pub remote_clock: Option<u32>,
+}
+
+/// ICMP echo request sent over an unprivileged ping socket
+/// (`SOCK_DGRAM` + `IPPROTO_ICMP`/`IPPROTO_ICMPV6`).
+/// Linux only hands these out to groups inside of `net.ipv4.ping_group_range`.
+///
+/// The port of the state is ignored.
+#[derive(Debug)]
+pub struct IcmpEcho;
+
+/// ICMP timestamp request, IPv4 only.
+///
+/// Linux refuses to send anything but echo requests over ping sockets,
+/// so this falls back to a raw socket, which requires `CAP_NET_RAW`.
+///
+/// The port of the state is ignored.
+#[derive(Debug)]
+pub struct IcmpTimestamp;
+
+#[async_trait::async_trait]
+impl CRON for IcmpEcho {
+    type State = SocketAddr;
+    type Response = PingReply;
+
+    async fn exec(state: &mut SocketAddr) -> Result<JobCtrl<Self::Response>, Error> {
+        Ok(into_ctrl(ping(state.ip(), IcmpKind::Echo).await))
+    }
+}
+
+#[async_trait::async_trait]
+impl CRON for IcmpTimestamp {
+    type State = SocketAddr;
+    type Response = PingReply;
+
+    async fn exec(state: &mut SocketAddr) -> Result<JobCtrl<Self::Response>, Error> {
+        Ok(into_ctrl(ping(state.ip(), IcmpKind::Timestamp).await))
+    }
+}
+
+fn into_ctrl(result: Result<PingReply, Error>) -> JobCtrl<PingReply> {
+    match result {
+        // host is up
+        Ok(reply) => JobCtrl::Return(State::Open, reply),
+
+        Err(Error::IO(err)) => JobCtrl::Error(handle_io_error(err)),
+        Err(e) => {
+            eprintln!("unmatched error {:#?} [not io error]", e);
+            JobCtrl::Error(JobErr::Other)
+        }
+    }
+}
+
+/// Sends a single echo/timestamp request to `addr`,
+/// and waits for its reply.
+/// Waiting is bounded by the timeout the job is ran with.
+pub async fn ping(addr: IpAddr, kind: IcmpKind) -> Result<PingReply, Error> {
+    if addr.is_ipv6() && kind == IcmpKind::Timestamp {
+        return Err(Error::IO(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "ICMPv6 has no timestamp request"
+        )))
+    }
+
+    let socket = open_socket(addr, kind)?;
+    let seq = SEQUENCE.fetch_add(1, Ordering::Relaxed);
+    // the kernel overwrites the identifier on ping sockets,
+    // but raw sockets will see every icmp packet on the host
+    let ident = std::process::id() as u16;
+
+    let mut packet = match kind {
+        IcmpKind::Echo => echo_request(addr, ident, seq),
+        IcmpKind::Timestamp => timestamp_request(ident, seq),
+    };
+    set_checksum(&mut packet);
+
+    let started = Instant::now();
+    socket.send_to(&packet, SocketAddr::new(addr, 0)).await?;
+
+    let mut buf = [0u8; 1500];
+    loop {
+        let (n, peer) = socket.recv_from(&mut buf).await?;
+
+        if peer.ip() != addr {
+            continue
+        }
+
+        if let Some(remote_clock) = match_reply(&buf[..n], addr, kind, ident, seq) {
+            return Ok(PingReply {
+                addr,
+                kind,
+                rtt: started.elapsed(),
+                remote_clock
+            })
+        }
+    }
+}
+
+fn open_socket(addr: IpAddr, kind: IcmpKind) -> Result<UdpSocket, Error> {
+    let (domain, protocol) = match addr {
+        IpAddr::V4(_) => (Domain::IPV4, Protocol::ICMPV4),
+        IpAddr::V6(_) => (Domain::IPV6, Protocol::ICMPV6),
+    };
+
+    let ty = match kind {
+        IcmpKind::Echo => Type::DGRAM,
+        IcmpKind::Timestamp => Type::RAW,
+    };
+
+    let socket = Socket::new(domain, ty, Some(protocol))?;
+    socket.set_nonblocking(true)?;
+
+    // tokio's udp socket only needs a datagram oriented file descriptor
+    Ok(UdpSocket::from_std(socket.into())?)
+}
+
+/*
++--------+--------+-----------------+
+|  TYPE  |  CODE  |    CHECKSUM     |
++--------+--------+-----------------+
+|   IDENTIFIER    |    SEQUENCE     |
++-----------------+-----------------+
+|             PAYLOAD ...           |
++-----------------------------------+*/
+fn echo_request(addr: IpAddr, ident: u16, seq: u16) -> Vec<u8> {
+    let ty = match addr {
+        IpAddr::V4(_) => ICMP_ECHO,
+        IpAddr::V6(_) => ICMPV6_ECHO,
+    };
+
+    let mut packet = Vec::with_capacity(8 + PAYLOAD.len());
+    packet.extend_from_slice(&[ty, 0, 0, 0]);
+    packet.extend_from_slice(&ident.to_be_bytes());
+    packet.extend_from_slice(&seq.to_be_bytes());
+    packet.extend_from_slice(PAYLOAD);
+    packet
+}
+
+/*
++--------+--------+-----------------+
+|  TYPE  |  CODE  |    CHECKSUM     |
++--------+--------+-----------------+
+|   IDENTIFIER    |    SEQUENCE     |
++-----------------+-----------------+
+|        ORIGINATE TIMESTAMP        |
++-----------------------------------+
+|         RECEIVE TIMESTAMP         |
++-----------------------------------+
+|        TRANSMIT TIMESTAMP         |
++-----------------------------------+*/
+fn timestamp_request(ident: u16, seq: u16) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(20);
+    packet.extend_from_slice(&[ICMP_TIMESTAMP, 0, 0, 0]);
+    packet.extend_from_slice(&ident.to_be_bytes());
+    packet.extend_from_slice(&seq.to_be_bytes());
+    packet.extend_from_slice(&ms_since_midnight().to_be_bytes());
+    packet.extend_from_slice(&[0; 8]);
+    packet
+}
+
+fn ms_since_midnight() -> u32 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+
+    (now % 86_400_000) as u32
+}
+
+/// Internet checksum over the whole icmp message,
+/// the kernel fills this in for ICMPv6 itself
+fn set_checksum(packet: &mut [u8]) {
+    packet[2] = 0;
+    packet[3] = 0;
+
+    let mut sum: u32 = packet
+        .chunks(2)
+        .map(|pair| match pair {
+            [hi, lo] => u16::from_be_bytes([*hi, *lo]) as u32,
+            [hi] => u16::from_be_bytes([*hi, 0]) as u32,
+            _ => 0
+        })
+        .sum();
+
+    while sum >> 16 > 0 {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+
+    let checksum = !(sum as u16);
+    packet[2..4].copy_from_slice(&checksum.to_be_bytes());
+}
+
+/// Returns `Some(_)` if `buf` is the reply to our request,
+/// carrying the peer's clock for timestamp replies.
+fn match_reply(buf: &[u8], addr: IpAddr, kind: IcmpKind, ident: u16, seq: u16) -> Option<Option<u32>> {
+    let msg = match kind {
+        IcmpKind::Echo => buf,
+        // raw ipv4 sockets hand us the ip header too
+        IcmpKind::Timestamp => {
+            let header_len = ((*buf.get(0)? & 0x0F) as usize) * 4;
+            buf.get(header_len..)?
+        }
+    };
+
+    if msg.len() < 8 {
+        return None
+    }
+
+    let reply_seq = u16::from_be_bytes([msg[6], msg[7]]);
+    if reply_seq != seq {
+        return None
+    }
+
+    match (kind, addr) {
+        (IcmpKind::Echo, IpAddr::V4(_)) if msg[0] == ICMP_ECHO_REPLY => Some(None),
+        (IcmpKind::Echo, IpAddr::V6(_)) if msg[0] == ICMPV6_ECHO_REPLY => Some(None),
+
+        (IcmpKind::Timestamp, _) if msg[0] == ICMP_TIMESTAMP_REPLY && msg.len() >= 20 => {
+            if u16::from_be_bytes([msg[4], msg[5]]) != ident {
+                return None
+            }
+            Some(Some(u32::from_be_bytes([msg[12], msg[13], msg[14], msg[15]])))
+        },
+
+        _ => None
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+    use tokio::runtime::Runtime;
+
+    /// Pings `addr` on loopback, `None` to skip the test:
+    /// ping sockets are only given out to `net.ipv4.ping_group_range`,
+    /// and hosts without IPv6 have no "::1" to send from
+    fn loopback(addr: &str, kind: IcmpKind) -> Option<PingReply> {
+        let rt = Runtime::new().unwrap();
+        let addr: IpAddr = addr.parse().unwrap();
+        let result = rt.block_on(async move {
+            tokio::time::timeout(Duration::from_secs(5), ping(addr, kind)).await
+        });
+
+        match result.expect("loopback didn't reply in time") {
+            Err(Error::IO(err)) if matches!(
+                err.kind(),
+                std::io::ErrorKind::PermissionDenied
+                | std::io::ErrorKind::AddrNotAvailable
+                | std::io::ErrorKind::Unsupported
+            ) => {
+                eprintln!("skipping, can't ping {}: {}", addr, err);
+                None
+            },
+            result => Some(result.unwrap())
+        }
+    }
+
+    #[test]
+    fn checksum_valid() {
+        let mut packet = echo_request("127.0.0.1".parse().unwrap(), 1, 1);
+        set_checksum(&mut packet);
+
+        // summing a packet with a valid checksum results in 0xFFFF
+        let mut copy = packet.clone();
+        let checksum = [copy[2], copy[3]];
+        set_checksum(&mut copy);
+        assert_eq!(checksum, [copy[2], copy[3]]);
+
+        let mut sum: u32 = packet.chunks(2)
+            .map(|x| u16::from_be_bytes([x[0], *x.get(1).unwrap_or(&0)]) as u32)
+            .sum();
+        while sum >> 16 > 0 {
+            sum = (sum & 0xFFFF) + (sum >> 16);
+        }
+        assert_eq!(sum, 0xFFFF);
+    }
+
+    #[test]
+    fn echo_localhost() {
+        if let Some(reply) = loopback("127.0.0.1", IcmpKind::Echo) {
+            assert_eq!(reply.kind, IcmpKind::Echo);
+            assert_eq!(reply.remote_clock, None);
+        }
+    }
+
+    #[test]
+    fn echo_localhost_v6() {
+        if let Some(reply) = loopback("::1", IcmpKind::Echo) {
+            assert_eq!(reply.addr, "::1".parse::<IpAddr>().unwrap());
+        }
+    }
+
+    #[test]
+    fn timestamp_localhost() {
+        if let Some(reply) = loopback("127.0.0.1", IcmpKind::Timestamp) {
+            assert!(reply.remote_clock.is_some());
+        }
+    }
+
+    #[test]
+    fn timestamp_rejects_v6() {
+        let rt = Runtime::new().unwrap();
+        match rt.block_on(ping("::1".parse().unwrap(), IcmpKind::Timestamp)) {
+            Err(Error::IO(err)) => assert_eq!(err.kind(), std::io::ErrorKind::Unsupported),
+            other => panic!("expected Unsupported, got {:?}", other),
+        }
+    }
+}
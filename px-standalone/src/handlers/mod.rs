@@ -1,11 +1,76 @@
-//pub mod vscan;
+pub mod vscan;
 //pub mod parsers;
 pub mod tcp;
 pub mod socks5;
+pub mod http_proxy;
+pub mod http;
+pub mod tls;
+pub mod ssh;
+pub mod dns;
+pub mod smb;
+pub mod datastore;
+pub mod ftp;
+pub mod smtp;
+pub mod ping;
+pub mod icmp;
+pub mod udp;
+pub mod syn;
+pub mod banner;
+pub mod canary;
+mod buffered;
 
 
-use px_core::pool::JobErr;
+use std::{
+    net::{SocketAddr, IpAddr},
+    sync::Arc,
+};
+
+use px_core::{
+    pool::{JobErr, Destination},
+    wrapper::pivot,
+};
+use crate::cli::output::CastAs;
+
+/// A target along with the handler's shared configuration/resources,
+/// for handlers that need more than an address.
+#[derive(Debug)]
+pub struct Job<C> {
+    pub addr: SocketAddr,
+    pub config: Arc<C>,
+}
+
+impl<C> Job<C> {
+    pub fn new(addr: SocketAddr, config: Arc<C>) -> Self {
+        Self { addr, config }
+    }
+}
+
+impl<C> Clone for Job<C> {
+    fn clone(&self) -> Self {
+        Self::new(self.addr, self.config.clone())
+    }
+}
+
+impl<C> CastAs<SocketAddr> for Job<C> {
+    fn cast(&self) -> &SocketAddr {
+        &self.addr
+    }
+}
+
+impl<C> Destination for Job<C> {
+    fn destination(&self) -> IpAddr {
+        self.addr.ip()
+    }
+}
+
 fn handle_io_error(err: std::io::Error) -> JobErr {
+    if pivot::is_fault(&err) {
+        return JobErr::Fatal(err.to_string())
+    }
+    if pivot::is_transient(&err) {
+        return JobErr::Transient(err.to_string())
+    }
+
     match err.kind() {
         std::io::ErrorKind::Other => match err.raw_os_error() {
             Some(i) => JobErr::Errno(i),
@@ -13,4 +78,12 @@ fn handle_io_error(err: std::io::Error) -> JobErr {
         },
         x => JobErr::IO(x),
     }
-}
\ No newline at end of file
+}
+
+fn rand_u64() -> u64 {
+    use std::hash::{BuildHasher, Hasher};
+    // randomly keyed per process
+    std::collections::hash_map::RandomState::new()
+        .build_hasher()
+        .finish()
+}
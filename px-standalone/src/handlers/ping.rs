@@ -0,0 +1,73 @@
+use px_core::{
+    pool::{JobCtrl, CRON},
+    error::Error,
+    model::State,
+    wrapper::{pivot, DEFAULT_TTL},
+};
+
+use std::net::SocketAddr;
+use super::handle_io_error;
+
+/// TCP connect "ping" used during host discovery.
+/// A refused connection is just as much proof of life
+/// as an accepted one, since something had to answer with a RST:
+/// `ConnectionRefused` is returned as `Closed`, and counts the host as alive.
+/// Timeouts and unreachables are errors, the host isn't alive as far as this port can tell.
+#[derive(Debug)]
+pub struct TcpPing;
+
+#[async_trait::async_trait]
+impl CRON for TcpPing
+{
+    type State = SocketAddr;
+    type Response = SocketAddr;
+
+    async fn exec(state: &mut SocketAddr) -> Result<JobCtrl<Self::Response>, Error>
+    {
+        // the pool's timeout is what bounds it
+        match pivot::connect(*state, DEFAULT_TTL).await {
+            Ok(_) => Ok(JobCtrl::Return(State::Open, *state)),
+            
+            Err(err) if err.kind() == std::io::ErrorKind::ConnectionRefused =>
+                Ok(JobCtrl::Return(State::Closed, *state)),
+            
+            Err(err) => Ok(JobCtrl::Error(handle_io_error(err)))
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::{
+        runtime::Runtime,
+        net::TcpListener
+    };
+
+    #[test]
+    fn refused_and_accepted_are_alive() {
+        let rt = Runtime::new().unwrap();
+
+        rt.block_on(async move {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let mut open = listener.local_addr().unwrap();
+            
+            // bind and drop to find a port nobody listens on
+            let mut closed = TcpListener::bind("127.0.0.1:0").await
+                .unwrap()
+                .local_addr()
+                .unwrap();
+
+            match TcpPing::exec(&mut open).await.unwrap() {
+                JobCtrl::Return(state, _) => assert_eq!(state, State::Open),
+                x => panic!("expected return, got {:?}", x)
+            }
+
+            match TcpPing::exec(&mut closed).await.unwrap() {
+                JobCtrl::Return(state, _) => assert_eq!(state, State::Closed),
+                x => panic!("expected return, got {:?}", x)
+            }
+        });
+    }
+}
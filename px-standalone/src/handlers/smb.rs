@@ -0,0 +1,807 @@
+use px_core::{
+    pool::{JobCtrl, CRON, JobErr},
+    error::Error,
+    model::State,
+    wrapper::{NetworkInterface, TcpInterface},
+};
+use serde::Serialize;
+
+use std::{
+    net::SocketAddr,
+    time::Duration,
+};
+use super::{
+    Job,
+    buffered::{Buffered, le_u16, le_u32, le_u64},
+};
+use crate::cli::output::{Report, ServiceInfo};
+
+/// Largest message we'll accept, negotiate responses are a few hundred bytes
+const MAX_MESSAGE: usize = 64 * 1024;
+
+const SMB1_MAGIC: &[u8] = b"\xffSMB";
+const SMB2_MAGIC: &[u8] = b"\xfeSMB";
+const SMB2_HEADER: usize = 64;
+
+const SMB1_NEGOTIATE: u8 = 0x72;
+const SMB2_NEGOTIATE: u16 = 0;
+const SMB2_SESSION_SETUP: u16 = 1;
+
+const STATUS_SUCCESS: u32 = 0;
+const STATUS_MORE_PROCESSING_REQUIRED: u32 = 0xC000_0016;
+
+/// SMB1 `CAP_EXTENDED_SECURITY`, the negotiate response carries a guid and a security blob
+const CAP_EXTENDED_SECURITY: u32 = 0x8000_0000;
+
+/// We're only after the challenge, it's the same for any client
+const CLIENT_GUID: [u8; 16] = *b"px-scanner-guid!";
+
+/// OIDs of SPNEGO (1.3.6.1.5.5.2) and NTLMSSP (1.3.6.1.4.1.311.2.2.10)
+const SPNEGO_OID: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x02];
+const NTLMSSP_OID: &[u8] = &[0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0x37, 0x02, 0x02, 0x0a];
+
+const NTLMSSP: &[u8] = b"NTLMSSP\0";
+const NTLM_NEGOTIATE: u32 = 1;
+const NTLM_CHALLENGE: u32 = 2;
+
+/// unicode, oem, request target, ntlm, always sign, extended session security,
+/// target info, version, 128, key exchange, 56
+const NTLM_FLAGS: u32 = 0xe288_8207;
+const NTLM_NEGOTIATE_VERSION: u32 = 0x0200_0000;
+
+/// AV_PAIR ids in the challenge's target info (MS-NLMP 2.2.2.1)
+const AV_EOL: u16 = 0;
+const AV_NB_COMPUTER: u16 = 1;
+const AV_NB_DOMAIN: u16 = 2;
+const AV_DNS_COMPUTER: u16 = 3;
+const AV_DNS_DOMAIN: u16 = 4;
+const AV_DNS_TREE: u16 = 5;
+
+/// Seconds between 1601 (FILETIME's epoch) and 1970
+const FILETIME_UNIX: i64 = 11_644_473_600;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Dialect {
+    #[serde(rename = "NT LM 0.12")]
+    Smb1,
+    #[serde(rename = "2.0.2")]
+    Smb202,
+    #[serde(rename = "2.1")]
+    Smb21,
+    #[serde(rename = "3.0")]
+    Smb30,
+    #[serde(rename = "3.0.2")]
+    Smb302,
+    #[serde(rename = "3.1.1")]
+    Smb311,
+}
+
+impl Dialect {
+    /// Newest first
+    pub const SMB2: [Dialect; 5] = [Dialect::Smb311, Dialect::Smb302, Dialect::Smb30, Dialect::Smb21, Dialect::Smb202];
+
+    /// `DialectRevision` of SMB2 negotiations
+    fn revision(self) -> u16 {
+        match self {
+            Dialect::Smb1 => 0,
+            Dialect::Smb202 => 0x0202,
+            Dialect::Smb21 => 0x0210,
+            Dialect::Smb30 => 0x0300,
+            Dialect::Smb302 => 0x0302,
+            Dialect::Smb311 => 0x0311,
+        }
+    }
+
+    fn from_revision(revision: u16) -> Option<Self> {
+        Self::SMB2.iter().copied().find(|x| x.revision() == revision)
+    }
+}
+
+impl std::fmt::Display for Dialect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Dialect::Smb1 => "NT LM 0.12",
+            Dialect::Smb202 => "2.0.2",
+            Dialect::Smb21 => "2.1",
+            Dialect::Smb30 => "3.0",
+            Dialect::Smb302 => "3.0.2",
+            Dialect::Smb311 => "3.1.1",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Signing {
+    Disabled,
+    Enabled,
+    Required,
+}
+
+impl Signing {
+    /// From an SMB2 negotiate response's `SecurityMode`
+    fn smb2(mode: u16) -> Self {
+        if mode & 0x2 != 0 { Signing::Required }
+        else if mode & 0x1 != 0 { Signing::Enabled }
+        else { Signing::Disabled }
+    }
+
+    /// From an SMB1 negotiate response's `SecurityMode`
+    fn smb1(mode: u8) -> Self {
+        if mode & 0x8 != 0 { Signing::Required }
+        else if mode & 0x4 != 0 { Signing::Enabled }
+        else { Signing::Disabled }
+    }
+}
+
+/// What the server tells about itself in its NTLM challenge, before anyone authenticates
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct NtlmInfo {
+    pub netbios_computer: Option<String>,
+    pub netbios_domain: Option<String>,
+    pub dns_computer: Option<String>,
+    pub dns_domain: Option<String>,
+    pub dns_tree: Option<String>,
+    /// windows version and build, "10.0.17763"
+    pub os_version: Option<String>,
+}
+
+impl NtlmInfo {
+    /// From a CHALLENGE message (MS-NLMP 2.2.1.2), starting at its signature
+    pub fn from_challenge(message: &[u8]) -> Option<Self> {
+        if !message.starts_with(NTLMSSP) || le_u32(message, 8)? != NTLM_CHALLENGE {
+            return None
+        }
+
+        let mut info = NtlmInfo::default();
+        let flags = le_u32(message, 20)?;
+
+        let length = le_u16(message, 40)? as usize;
+        let offset = le_u32(message, 44)? as usize;
+        let mut pairs = message.get(offset..offset + length).unwrap_or_default();
+
+        while pairs.len() >= 4 {
+            let id = le_u16(pairs, 0)?;
+            let length = le_u16(pairs, 2)? as usize;
+            if id == AV_EOL {
+                break
+            }
+
+            let value = utf16(pairs.get(4..4 + length)?);
+            match id {
+                AV_NB_COMPUTER => info.netbios_computer = Some(value),
+                AV_NB_DOMAIN => info.netbios_domain = Some(value),
+                AV_DNS_COMPUTER => info.dns_computer = Some(value),
+                AV_DNS_DOMAIN => info.dns_domain = Some(value),
+                AV_DNS_TREE => info.dns_tree = Some(value),
+                _ => {}
+            }
+            pairs = &pairs[4 + length..];
+        }
+
+        if flags & NTLM_NEGOTIATE_VERSION != 0 {
+            if let Some(version) = message.get(48..52) {
+                let build = u16::from_le_bytes([version[2], version[3]]);
+                info.os_version = Some(format!("{}.{}.{}", version[0], version[1], build));
+            }
+        }
+
+        Some(info)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SmbInfo {
+    /// oldest first
+    pub dialects: Vec<Dialect>,
+    /// of the newest dialect
+    pub signing: Signing,
+    pub server_guid: Option<String>,
+    /// server's clock, seconds since the unix epoch
+    pub system_time: Option<i64>,
+    pub ntlm: Option<NtlmInfo>,
+}
+
+impl Report for SmbInfo {
+    fn service(&self) -> Option<ServiceInfo> {
+        Some(ServiceInfo {
+            name: "microsoft-ds".to_string(),
+            product: None,
+            version: None,
+            cpe: Vec::new(),
+        })
+    }
+
+    fn details(&self) -> Option<String> {
+        let dialects: Vec<String> = self.dialects.iter().map(ToString::to_string).collect();
+        let mut details = vec![
+            format!("dialects {}", dialects.join(",")),
+            format!("signing {:?}", self.signing).to_lowercase(),
+        ];
+
+        if let Some(ntlm) = &self.ntlm {
+            let host = ntlm.dns_computer.as_ref().or_else(|| ntlm.netbios_computer.as_ref());
+            let domain = ntlm.dns_domain.as_ref().or_else(|| ntlm.netbios_domain.as_ref());
+            match (host, domain) {
+                (Some(host), Some(domain)) => details.push(format!("host {} ({})", host, domain)),
+                (Some(host), None) => details.push(format!("host {}", host)),
+                _ => {}
+            }
+            if let Some(version) = &ntlm.os_version {
+                details.push(format!("os {}", version));
+            }
+        }
+
+        Some(details.join("; "))
+    }
+
+    fn data(&self) -> Option<serde_json::Value> {
+        serde_json::to_value(self).ok()
+    }
+}
+
+#[derive(Debug)]
+pub struct SmbConfig {
+    pub timeout: Duration,
+}
+
+/// Negotiates SMB2 with every dialect at once, then each older one on its own connection,
+/// and SMB1 last.
+/// The newest negotiation is followed by an NTLM session setup, stopping at the server's challenge,
+/// nothing is ever authenticated.
+/// Ports that don't speak SMB are open, without a response.
+#[derive(Debug)]
+pub struct SmbScanner;
+
+#[async_trait::async_trait]
+impl CRON for SmbScanner {
+    type State = Job<SmbConfig>;
+    type Response = Option<SmbInfo>;
+
+    async fn exec(state: &mut Job<SmbConfig>) -> Result<JobCtrl<Self::Response>, Error> {
+        match inspect(state.addr, state.config.timeout).await {
+            Ok(found) => Ok(JobCtrl::Return(State::Open, found)),
+
+            Err(Error::IO(err)) => Ok(JobCtrl::Error(super::handle_io_error(err))),
+            Err(e) => {
+                eprintln!("unmatched error {:#?} [not io error]", e);
+                Ok(JobCtrl::Error(JobErr::Other))
+            }
+        }
+    }
+}
+
+/// Errors when the SMB2 negotiation's connection can't be made,
+/// older dialects and SMB1 are left out when theirs can't
+pub async fn inspect(addr: SocketAddr, timeout: Duration) -> Result<Option<SmbInfo>, Error> {
+    let iface = TcpInterface::connect(addr, timeout).await?;
+    let mut con = Connection::new(iface);
+    let mut found = None;
+
+    if let Some(negotiated) = con.negotiate(&Dialect::SMB2).await {
+        let ntlm = con.challenge().await;
+        let newest = negotiated.dialect;
+
+        let mut info = negotiated.into_info(ntlm);
+        // the server picked its newest, anything older has to be asked for on its own
+        for dialect in Dialect::SMB2.iter().filter(|x| x.revision() < newest.revision()) {
+            let mut con = match TcpInterface::connect(addr, timeout).await {
+                Ok(iface) => Connection::new(iface),
+                Err(_) => continue
+            };
+
+            if let Some(negotiated) = con.negotiate(&[*dialect]).await {
+                info.dialects.push(negotiated.dialect);
+            }
+        }
+        found = Some(info);
+    }
+
+    if let Ok(iface) = TcpInterface::connect(addr, timeout).await {
+        if let Some(negotiated) = Connection::new(iface).negotiate_smb1().await {
+            match &mut found {
+                Some(info) => info.dialects.push(Dialect::Smb1),
+                None => found = Some(negotiated.into_info(None)),
+            }
+        }
+    }
+
+    if let Some(info) = &mut found {
+        info.dialects.sort_by_key(|x| x.revision());
+        info.dialects.dedup();
+    }
+    Ok(found)
+}
+
+/// The parts of a negotiate response we keep
+struct Negotiated {
+    dialect: Dialect,
+    signing: Signing,
+    server_guid: Option<[u8; 16]>,
+    /// FILETIME
+    system_time: u64,
+}
+
+impl Negotiated {
+    fn into_info(self, ntlm: Option<NtlmInfo>) -> SmbInfo {
+        SmbInfo {
+            dialects: vec![self.dialect],
+            signing: self.signing,
+            server_guid: self.server_guid.as_ref().map(guid),
+            system_time: match self.system_time {
+                0 => None,
+                time => Some((time / 10_000_000) as i64 - FILETIME_UNIX),
+            },
+            ntlm,
+        }
+    }
+}
+
+struct Connection<I> {
+    con: Buffered<I>,
+    message_id: u64,
+}
+
+impl<I: NetworkInterface> Connection<I> {
+    fn new(iface: I) -> Self {
+        Self { con: Buffered::new(iface), message_id: 0 }
+    }
+
+    /// Frames `message` in a direct tcp transport header
+    async fn send(&mut self, message: &[u8]) -> Option<()> {
+        let mut packet = Vec::with_capacity(4 + message.len());
+        packet.extend_from_slice(&(message.len() as u32).to_be_bytes());
+        packet.extend_from_slice(message);
+        self.con.write(&packet).await
+    }
+
+    /// The next message, without its transport header
+    async fn message(&mut self) -> Option<Vec<u8>> {
+        self.con.fill(4).await?;
+
+        let length = u32::from_be_bytes([self.con.buf[0], self.con.buf[1], self.con.buf[2], self.con.buf[3]]) as usize;
+        if length > MAX_MESSAGE {
+            return None
+        }
+
+        self.con.fill(4 + length).await?;
+        Some(self.con.take(4 + length).split_off(4))
+    }
+
+    /// Sends an SMB2 request, returns the response's status and the whole response,
+    /// its offsets count from the header
+    async fn request(&mut self, command: u16, body: &[u8]) -> Option<(u32, Vec<u8>)> {
+        let mut message = smb2_header(command, self.message_id);
+        message.extend_from_slice(body);
+        self.message_id += 1;
+
+        self.send(&message).await?;
+        let response = self.message().await?;
+
+        if !response.starts_with(SMB2_MAGIC) || le_u16(&response, 12)? != command {
+            return None
+        }
+        Some((le_u32(&response, 8)?, response))
+    }
+
+    async fn negotiate(&mut self, dialects: &[Dialect]) -> Option<Negotiated> {
+        let (status, response) = self.request(SMB2_NEGOTIATE, &negotiate_request(dialects)).await?;
+        if status != STATUS_SUCCESS {
+            return None
+        }
+
+        let body = response.get(SMB2_HEADER..)?;
+        let dialect = Dialect::from_revision(le_u16(body, 4)?)
+            .filter(|x| dialects.contains(x))?;
+
+        let mut server_guid = [0; 16];
+        server_guid.copy_from_slice(body.get(8..24)?);
+
+        Some(Negotiated {
+            dialect,
+            signing: Signing::smb2(le_u16(body, 2)?),
+            server_guid: Some(server_guid),
+            system_time: le_u64(body, 40)?,
+        })
+    }
+
+    /// Starts an NTLM session setup, the server's challenge describes it
+    async fn challenge(&mut self) -> Option<NtlmInfo> {
+        let token = spnego(&ntlm_negotiate());
+
+        let mut body = Vec::with_capacity(24 + token.len());
+        body.extend_from_slice(&25u16.to_le_bytes());
+        // flags, security mode: signing enabled
+        body.extend_from_slice(&[0, 1]);
+        // capabilities, channel
+        body.extend_from_slice(&[0; 8]);
+        body.extend_from_slice(&((SMB2_HEADER + 24) as u16).to_le_bytes());
+        body.extend_from_slice(&(token.len() as u16).to_le_bytes());
+        // previous session id
+        body.extend_from_slice(&[0; 8]);
+        body.extend_from_slice(&token);
+
+        let (status, response) = self.request(SMB2_SESSION_SETUP, &body).await?;
+        if status != STATUS_MORE_PROCESSING_REQUIRED {
+            return None
+        }
+
+        let offset = le_u16(&response, SMB2_HEADER + 4)? as usize;
+        let length = le_u16(&response, SMB2_HEADER + 6)? as usize;
+        let blob = response.get(offset..offset + length)?;
+
+        // wrapped in SPNEGO or not, the challenge is the tail of the blob
+        let start = blob.windows(NTLMSSP.len()).position(|x| x == NTLMSSP)?;
+        NtlmInfo::from_challenge(&blob[start..])
+    }
+
+    /// Offers only "NT LM 0.12"
+    async fn negotiate_smb1(&mut self) -> Option<Negotiated> {
+        let mut message = SMB1_MAGIC.to_vec();
+        message.push(SMB1_NEGOTIATE);
+        // status
+        message.extend_from_slice(&[0; 4]);
+        // flags: case insensitive, canonicalized paths
+        message.push(0x18);
+        // flags2: unicode, nt status, extended security, long names
+        message.extend_from_slice(&0xc801u16.to_le_bytes());
+        // pid high, security features, reserved, tid, pid, uid, mid
+        message.extend_from_slice(&[0; 12]);
+        message.extend_from_slice(&[0xff, 0xff, 0, 0, 0, 0, 0, 0]);
+
+        let dialects = b"\x02NT LM 0.12\0";
+        // no parameters
+        message.push(0);
+        message.extend_from_slice(&(dialects.len() as u16).to_le_bytes());
+        message.extend_from_slice(dialects);
+
+        self.send(&message).await?;
+        let response = self.message().await?;
+
+        // a 17 word response, the only one with a chosen "NT LM 0.12"
+        if !response.starts_with(SMB1_MAGIC) || response.get(4) != Some(&SMB1_NEGOTIATE) || response.get(32) != Some(&17) {
+            return None
+        }
+        if le_u32(&response, 5)? != STATUS_SUCCESS || le_u16(&response, 33)? != 0 {
+            return None
+        }
+
+        let params = &response[33..];
+        let server_guid = if le_u32(params, 19)? & CAP_EXTENDED_SECURITY != 0 {
+            let mut server_guid = [0; 16];
+            // after the parameters' word count and the byte count
+            server_guid.copy_from_slice(response.get(69..85)?);
+            Some(server_guid)
+        } else {
+            None
+        };
+
+        Some(Negotiated {
+            dialect: Dialect::Smb1,
+            signing: Signing::smb1(*params.get(2)?),
+            server_guid,
+            system_time: le_u64(params, 23)?,
+        })
+    }
+}
+
+fn smb2_header(command: u16, message_id: u64) -> Vec<u8> {
+    let mut header = Vec::with_capacity(SMB2_HEADER);
+    header.extend_from_slice(SMB2_MAGIC);
+    header.extend_from_slice(&(SMB2_HEADER as u16).to_le_bytes());
+    // credit charge, status
+    header.extend_from_slice(&[0; 6]);
+    header.extend_from_slice(&command.to_le_bytes());
+    // credits requested
+    header.extend_from_slice(&1u16.to_le_bytes());
+    // flags, next command
+    header.extend_from_slice(&[0; 8]);
+    header.extend_from_slice(&message_id.to_le_bytes());
+    // process id, tree id, session id, signature
+    header.extend_from_slice(&[0; 32]);
+    header
+}
+
+/// The body of a NEGOTIATE request (MS-SMB2 2.2.3),
+/// offering 3.1.1 needs preauth integrity and encryption contexts
+fn negotiate_request(dialects: &[Dialect]) -> Vec<u8> {
+    let contexts = dialects.contains(&Dialect::Smb311);
+
+    let mut body = Vec::new();
+    body.extend_from_slice(&36u16.to_le_bytes());
+    body.extend_from_slice(&(dialects.len() as u16).to_le_bytes());
+    // security mode: signing enabled
+    body.extend_from_slice(&1u16.to_le_bytes());
+    // reserved, capabilities
+    body.extend_from_slice(&[0; 6]);
+    body.extend_from_slice(&CLIENT_GUID);
+    // negotiate context offset and count, filled in below
+    body.extend_from_slice(&[0; 8]);
+    for dialect in dialects {
+        body.extend_from_slice(&dialect.revision().to_le_bytes());
+    }
+
+    if !contexts {
+        return body
+    }
+
+    // contexts are 8 byte aligned, counting from the header
+    let align = |body: &mut Vec<u8>| while (SMB2_HEADER + body.len()) % 8 != 0 { body.push(0) };
+    align(&mut body);
+    let offset = (SMB2_HEADER + body.len()) as u32;
+    body[28..32].copy_from_slice(&offset.to_le_bytes());
+    body[32..34].copy_from_slice(&2u16.to_le_bytes());
+
+    // preauth integrity: SHA-512, with a salt
+    let mut preauth = Vec::new();
+    preauth.extend_from_slice(&1u16.to_le_bytes());
+    preauth.extend_from_slice(&32u16.to_le_bytes());
+    preauth.extend_from_slice(&1u16.to_le_bytes());
+    preauth.extend_from_slice(&[0x5a; 32]);
+
+    // encryption: AES-128-GCM, AES-128-CCM
+    let mut encryption = Vec::new();
+    encryption.extend_from_slice(&2u16.to_le_bytes());
+    encryption.extend_from_slice(&2u16.to_le_bytes());
+    encryption.extend_from_slice(&1u16.to_le_bytes());
+
+    for (kind, data) in [(1u16, preauth), (2u16, encryption)].iter() {
+        align(&mut body);
+        body.extend_from_slice(&kind.to_le_bytes());
+        body.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        body.extend_from_slice(&[0; 4]);
+        body.extend_from_slice(data);
+    }
+
+    body
+}
+
+/// An NTLM NEGOTIATE message (MS-NLMP 2.2.1.1), asking for target info and the server's version
+fn ntlm_negotiate() -> Vec<u8> {
+    let mut message = NTLMSSP.to_vec();
+    message.extend_from_slice(&NTLM_NEGOTIATE.to_le_bytes());
+    message.extend_from_slice(&NTLM_FLAGS.to_le_bytes());
+    // domain and workstation fields, left empty
+    message.extend_from_slice(&[0; 16]);
+    // our version, 6.1.7601, ntlm revision 15
+    message.extend_from_slice(&[6, 1, 0xb1, 0x1d, 0, 0, 0, 15]);
+    message
+}
+
+/// Wraps an NTLM token in a SPNEGO NegTokenInit (RFC 4178)
+fn spnego(token: &[u8]) -> Vec<u8> {
+    let mechanisms = der(0xa0, &der(0x30, &der(0x06, NTLMSSP_OID)));
+    let token = der(0xa2, &der(0x04, token));
+    let init = der(0xa0, &der(0x30, &[mechanisms, token].concat()));
+
+    der(0x60, &[der(0x06, SPNEGO_OID), init].concat())
+}
+
+fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut encoded = vec![tag];
+    match content.len() {
+        length if length < 0x80 => encoded.push(length as u8),
+        length if length <= 0xff => encoded.extend_from_slice(&[0x81, length as u8]),
+        length => encoded.extend_from_slice(&[0x82, (length >> 8) as u8, length as u8]),
+    }
+    encoded.extend_from_slice(content);
+    encoded
+}
+
+/// "xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx", the first three groups are little endian
+fn guid(bytes: &[u8; 16]) -> String {
+    let hex = |bytes: &[u8]| bytes.iter().map(|x| format!("{:02x}", x)).collect::<String>();
+    let swapped = |bytes: &[u8]| hex(&bytes.iter().rev().copied().collect::<Vec<u8>>());
+
+    format!("{}-{}-{}-{}-{}",
+        swapped(&bytes[0..4]),
+        swapped(&bytes[4..6]),
+        swapped(&bytes[6..8]),
+        hex(&bytes[8..10]),
+        hex(&bytes[10..16]),
+    )
+}
+
+fn utf16(bytes: &[u8]) -> String {
+    let units: Vec<u16> = bytes.chunks_exact(2)
+        .map(|x| u16::from_le_bytes([x[0], x[1]]))
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::{
+        runtime::Runtime,
+        net::{TcpListener, TcpStream},
+        io::{AsyncReadExt, AsyncWriteExt},
+    };
+
+    const GUID: [u8; 16] = [0x78, 0x56, 0x34, 0x12, 0x34, 0x12, 0x78, 0x56, 0xaa, 0xbb, 1, 2, 3, 4, 5, 6];
+    /// 2020-09-13, as a FILETIME
+    const TIME: u64 = (1_600_000_000 + FILETIME_UNIX as u64) * 10_000_000;
+
+    fn av(id: u16, value: &str) -> Vec<u8> {
+        let value: Vec<u8> = value.encode_utf16().flat_map(|x| x.to_le_bytes().to_vec()).collect();
+        let mut pair = id.to_le_bytes().to_vec();
+        pair.extend_from_slice(&(value.len() as u16).to_le_bytes());
+        pair.extend_from_slice(&value);
+        pair
+    }
+
+    fn challenge() -> Vec<u8> {
+        let pairs = [
+            av(AV_NB_DOMAIN, "CORP"),
+            av(AV_NB_COMPUTER, "FS01"),
+            av(AV_DNS_DOMAIN, "corp.local"),
+            av(AV_DNS_COMPUTER, "fs01.corp.local"),
+            av(AV_EOL, ""),
+        ].concat();
+
+        let mut message = NTLMSSP.to_vec();
+        message.extend_from_slice(&NTLM_CHALLENGE.to_le_bytes());
+        message.extend_from_slice(&[0, 0, 0, 0, 56, 0, 0, 0]);
+        message.extend_from_slice(&NTLM_FLAGS.to_le_bytes());
+        message.extend_from_slice(&[7; 16]);
+        message.extend_from_slice(&(pairs.len() as u16).to_le_bytes());
+        message.extend_from_slice(&(pairs.len() as u16).to_le_bytes());
+        message.extend_from_slice(&56u32.to_le_bytes());
+        // 10.0.17763
+        message.extend_from_slice(&[10, 0, 0x63, 0x45, 0, 0, 0, 15]);
+        message.extend_from_slice(&pairs);
+        message
+    }
+
+    async fn reply(con: &mut TcpStream, message: &[u8]) {
+        con.write_all(&(message.len() as u32).to_be_bytes()).await.unwrap();
+        con.write_all(message).await.unwrap();
+    }
+
+    fn smb2_response(command: u16, status: u32, body: &[u8]) -> Vec<u8> {
+        let mut message = smb2_header(command, 0);
+        message[8..12].copy_from_slice(&status.to_le_bytes());
+        message.extend_from_slice(body);
+        message
+    }
+
+    /// Speaks the `dialects` it's given, and SMB1 when `smb1` is set.
+    /// Signing is required, session setups are answered with `challenge()`
+    async fn server(dialects: &'static [u16], smb1: bool) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            loop {
+                let (mut con, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    loop {
+                        let mut length = [0; 4];
+                        if con.read_exact(&mut length).await.is_err() {
+                            return
+                        }
+                        let mut request = vec![0; u32::from_be_bytes(length) as usize];
+                        con.read_exact(&mut request).await.unwrap();
+
+                        if request.starts_with(SMB1_MAGIC) {
+                            if !smb1 {
+                                return
+                            }
+                            let mut response = request[..32].to_vec();
+                            response.push(17);
+                            // dialect 0, signing enabled
+                            response.extend_from_slice(&[0, 0, 0x07]);
+                            response.extend_from_slice(&[0; 16]);
+                            response.extend_from_slice(&CAP_EXTENDED_SECURITY.to_le_bytes());
+                            response.extend_from_slice(&TIME.to_le_bytes());
+                            response.extend_from_slice(&[0; 3]);
+                            response.extend_from_slice(&16u16.to_le_bytes());
+                            response.extend_from_slice(&GUID);
+                            reply(&mut con, &response).await;
+                            continue
+                        }
+
+                        match le_u16(&request, 12).unwrap() {
+                            SMB2_NEGOTIATE => {
+                                let count = le_u16(&request, SMB2_HEADER + 2).unwrap() as usize;
+                                let offered: Vec<u16> = (0..count)
+                                    .map(|i| le_u16(&request, SMB2_HEADER + 36 + i * 2).unwrap())
+                                    .collect();
+
+                                let dialect = match dialects.iter().rev().find(|x| offered.contains(x)) {
+                                    Some(dialect) => *dialect,
+                                    None => {
+                                        reply(&mut con, &smb2_response(SMB2_NEGOTIATE, 0xC000_00BB, &[9, 0, 0, 0, 0, 0, 0, 0, 0])).await;
+                                        return
+                                    }
+                                };
+
+                                let mut body = 65u16.to_le_bytes().to_vec();
+                                body.extend_from_slice(&3u16.to_le_bytes());
+                                body.extend_from_slice(&dialect.to_le_bytes());
+                                body.extend_from_slice(&[0; 2]);
+                                body.extend_from_slice(&GUID);
+                                body.extend_from_slice(&[0; 16]);
+                                body.extend_from_slice(&TIME.to_le_bytes());
+                                body.extend_from_slice(&[0; 8]);
+                                body.extend_from_slice(&128u16.to_le_bytes());
+                                body.extend_from_slice(&[0; 6]);
+                                reply(&mut con, &smb2_response(SMB2_NEGOTIATE, STATUS_SUCCESS, &body)).await;
+                            },
+                            SMB2_SESSION_SETUP => {
+                                // a SPNEGO NegTokenResp's beginning, then the challenge
+                                let blob = [&[0xa1, 0x81, 0xa0, 0x30][..], &challenge()].concat();
+                                let mut body = 9u16.to_le_bytes().to_vec();
+                                body.extend_from_slice(&[0; 2]);
+                                body.extend_from_slice(&((SMB2_HEADER + 8) as u16).to_le_bytes());
+                                body.extend_from_slice(&(blob.len() as u16).to_le_bytes());
+                                body.extend_from_slice(&blob);
+                                reply(&mut con, &smb2_response(SMB2_SESSION_SETUP, STATUS_MORE_PROCESSING_REQUIRED, &body)).await;
+                            },
+                            _ => return
+                        }
+                    }
+                });
+            }
+        });
+
+        addr
+    }
+
+    #[test]
+    fn negotiates_dialects_and_challenge() {
+        Runtime::new().unwrap().block_on(async {
+            let addr = server(&[0x0202, 0x0210, 0x0300], false).await;
+            let found = inspect(addr, Duration::from_secs(2)).await.unwrap().unwrap();
+
+            assert_eq!(found.dialects, vec![Dialect::Smb202, Dialect::Smb21, Dialect::Smb30]);
+            assert_eq!(found.signing, Signing::Required);
+            assert_eq!(found.server_guid.as_deref(), Some("12345678-1234-5678-aabb-010203040506"));
+            assert_eq!(found.system_time, Some(1_600_000_000));
+
+            let ntlm = found.ntlm.clone().unwrap();
+            assert_eq!(ntlm.netbios_computer.as_deref(), Some("FS01"));
+            assert_eq!(ntlm.netbios_domain.as_deref(), Some("CORP"));
+            assert_eq!(ntlm.dns_computer.as_deref(), Some("fs01.corp.local"));
+            assert_eq!(ntlm.dns_domain.as_deref(), Some("corp.local"));
+            assert_eq!(ntlm.dns_tree, None);
+            assert_eq!(ntlm.os_version.as_deref(), Some("10.0.17763"));
+
+            assert_eq!(
+                found.details().unwrap(),
+                "dialects 2.0.2,2.1,3.0; signing required; host fs01.corp.local (corp.local); os 10.0.17763"
+            );
+        });
+    }
+
+    #[test]
+    fn falls_back_to_smb1() {
+        Runtime::new().unwrap().block_on(async {
+            let addr = server(&[], true).await;
+            let found = inspect(addr, Duration::from_secs(2)).await.unwrap().unwrap();
+
+            assert_eq!(found.dialects, vec![Dialect::Smb1]);
+            assert_eq!(found.signing, Signing::Enabled);
+            assert_eq!(found.server_guid.as_deref(), Some("12345678-1234-5678-aabb-010203040506"));
+            assert_eq!(found.system_time, Some(1_600_000_000));
+            assert_eq!(found.ntlm, None);
+        });
+    }
+
+    #[test]
+    fn offers_smb311_with_contexts() {
+        let body = negotiate_request(&Dialect::SMB2);
+        let offset = le_u32(&body, 28).unwrap() as usize;
+
+        assert_eq!(le_u16(&body, 2), Some(5));
+        assert_eq!(le_u16(&body, 32), Some(2));
+        assert_eq!(offset % 8, 0);
+        // preauth integrity first, counted from the header
+        assert_eq!(le_u16(&body, offset - SMB2_HEADER), Some(1));
+
+        let body = negotiate_request(&[Dialect::Smb202]);
+        assert_eq!(body.len(), 38);
+        assert_eq!(le_u32(&body, 28), Some(0));
+    }
+}
@@ -0,0 +1,325 @@
+use px_core::{
+    pool::{JobCtrl, CRON, JobErr},
+    error::Error,
+    model::State,
+    wrapper::{NetworkInterface, TcpInterface, Protocol, Reply, CodedSession, Exchange},
+};
+use serde::Serialize;
+use tokio::time::timeout;
+
+use std::{
+    net::SocketAddr,
+    time::Duration,
+};
+use super::Job;
+use crate::cli::output::{Report, ServiceInfo};
+
+/// Who we say we are in EHLO/HELO
+const HELO_NAME: &str = "scanner.example.com";
+/// Neither the sender nor the recipient belong to the server,
+/// both domains are reserved (RFC 2606), nothing could ever be delivered
+const RELAY_FROM: &str = "relay-test@example.com";
+const RELAY_TO: &str = "relay-test@example.net";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SmtpInfo {
+    /// text of the greeting, every line of it
+    pub banner: String,
+    /// the name the server gives itself in its EHLO reply
+    pub hostname: Option<String>,
+    /// EHLO's keywords, with their parameters
+    pub extensions: Vec<String>,
+    pub starttls: bool,
+    /// AUTH's mechanisms
+    pub auth: Vec<String>,
+    /// largest message accepted, in bytes, 0 means no limit
+    pub size: Option<u64>,
+    /// a recipient on an outside domain was accepted from an outside sender, unauthenticated.
+    /// Only tested with `SmtpConfig::relay`
+    pub open_relay: Option<bool>,
+}
+
+impl SmtpInfo {
+    fn extensions(&mut self, reply: &Reply) {
+        let mut texts = reply.texts();
+        self.hostname = texts.next()
+            .and_then(|greeting| greeting.split_whitespace().next())
+            .map(str::to_string);
+
+        for extension in texts.map(str::trim).filter(|x| !x.is_empty()) {
+            // "AUTH=LOGIN" is how some servers said it before AUTH was standardized
+            let mut words = extension.split(|c: char| c.is_whitespace() || c == '=');
+            let keyword = words.next().unwrap_or_default().to_ascii_uppercase();
+
+            match keyword.as_str() {
+                "STARTTLS" => self.starttls = true,
+                "AUTH" => for mechanism in words.filter(|x| !x.is_empty()) {
+                    let mechanism = mechanism.to_ascii_uppercase();
+                    if !self.auth.contains(&mechanism) {
+                        self.auth.push(mechanism);
+                    }
+                },
+                "SIZE" => self.size = words.next().and_then(|x| x.parse().ok()).or(Some(0)),
+                _ => {}
+            }
+
+            self.extensions.push(extension.to_string());
+        }
+    }
+}
+
+impl Report for SmtpInfo {
+    fn service(&self) -> Option<ServiceInfo> {
+        Some(ServiceInfo {
+            name: "smtp".to_string(),
+            product: None,
+            version: None,
+            cpe: Vec::new(),
+        })
+    }
+
+    fn details(&self) -> Option<String> {
+        let mut details = vec![self.banner.clone()];
+
+        if self.starttls {
+            details.push("STARTTLS".to_string());
+        }
+        if !self.auth.is_empty() {
+            details.push(format!("AUTH {}", self.auth.join(",")));
+        }
+        if let Some(size) = self.size {
+            details.push(format!("SIZE {}", size));
+        }
+        match self.open_relay {
+            Some(true) => details.push("open relay".to_string()),
+            Some(false) => details.push("no relay".to_string()),
+            None => {}
+        }
+
+        Some(details.join("; "))
+    }
+
+    fn data(&self) -> Option<serde_json::Value> {
+        serde_json::to_value(self).ok()
+    }
+}
+
+#[derive(Debug)]
+pub struct SmtpConfig {
+    pub timeout: Duration,
+    /// test for an open relay, up to RCPT, DATA is never sent
+    pub relay: bool,
+}
+
+/// Reads the greeting, and the extensions EHLO advertises (HELO when it's refused).
+/// With `SmtpConfig::relay`, sends MAIL/RCPT between two outside domains,
+/// resets the transaction and quits before DATA.
+/// STARTTLS is only noted, never negotiated.
+/// Ports that don't greet like SMTP are open, without a response.
+#[derive(Debug)]
+pub struct SmtpScanner;
+
+#[async_trait::async_trait]
+impl CRON for SmtpScanner {
+    type State = Job<SmtpConfig>;
+    type Response = Option<SmtpInfo>;
+
+    async fn exec(state: &mut Job<SmtpConfig>) -> Result<JobCtrl<Self::Response>, Error> {
+        match inspect(state.addr, &state.config).await {
+            Ok(found) => Ok(JobCtrl::Return(State::Open, found)),
+
+            Err(Error::IO(err)) => Ok(JobCtrl::Error(super::handle_io_error(err))),
+            Err(e) => {
+                eprintln!("unmatched error {:#?} [not io error]", e);
+                Ok(JobCtrl::Error(JobErr::Other))
+            }
+        }
+    }
+}
+
+/// Errors when the port can't be connected to,
+/// a server going quiet after its greeting is still reported with what it said
+pub async fn inspect(addr: SocketAddr, config: &SmtpConfig) -> Result<Option<SmtpInfo>, Error> {
+    let iface = TcpInterface::connect(addr, config.timeout).await?;
+    let mut con = Connection { iface, session: CodedSession::default(), timeout: config.timeout };
+
+    let greeting = match con.greeting().await {
+        // 554 is a server refusing us service, still SMTP
+        Some(reply) if reply.code == 220 || reply.code == 554 => reply,
+        _ => return Ok(None)
+    };
+
+    let mut found = SmtpInfo {
+        banner: greeting.texts().map(str::trim).collect::<Vec<_>>().join(" "),
+        hostname: None,
+        extensions: Vec::new(),
+        starttls: false,
+        auth: Vec::new(),
+        size: None,
+        open_relay: None,
+    };
+
+    if greeting.code != 220 {
+        return Ok(Some(found))
+    }
+
+    let greeted = match con.command(&format!("EHLO {}", HELO_NAME)).await {
+        Some(reply) if reply.positive() => {
+            found.extensions(&reply);
+            true
+        },
+        Some(_) => con.command(&format!("HELO {}", HELO_NAME)).await
+            .is_some_and(|reply| reply.positive()),
+        None => false
+    };
+
+    if greeted && config.relay {
+        found.open_relay = Some(con.relays().await);
+        let _ = con.command("RSET").await;
+    }
+    let _ = con.command("QUIT").await;
+
+    Ok(Some(found))
+}
+
+struct Connection<I> {
+    iface: I,
+    session: CodedSession,
+    /// given to the greeting, and every command along with its reply
+    timeout: Duration,
+}
+
+impl<I: NetworkInterface> Connection<I> {
+    /// A reply (RFC 5321 4.2), every line but the last has a "-" after its code
+    async fn reply(&mut self) -> Option<Reply> {
+        self.session.interact(&mut self.iface, Exchange::Read).await.ok()
+    }
+
+    async fn greeting(&mut self) -> Option<Reply> {
+        timeout(self.timeout, self.reply()).await.ok()?
+    }
+
+    async fn command(&mut self, command: &str) -> Option<Reply> {
+        let exchange = self.session.interact(&mut self.iface, Exchange::command(command));
+        timeout(self.timeout, exchange).await.ok()?.ok()
+    }
+
+    /// Accepting the recipient is enough, whatever the server would do after DATA
+    async fn relays(&mut self) -> bool {
+        match self.command(&format!("MAIL FROM:<{}>", RELAY_FROM)).await {
+            Some(reply) if reply.positive() => {},
+            _ => return false
+        }
+
+        match self.command(&format!("RCPT TO:<{}>", RELAY_TO)).await {
+            Some(reply) => reply.positive(),
+            None => false
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    };
+    use tokio::{
+        runtime::Runtime,
+        net::TcpListener,
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    };
+
+    /// Relays when `relay` is set, turns EHLO away when `ehlo` isn't.
+    /// The flag's set if DATA is ever sent
+    async fn server(ehlo: bool, relay: bool) -> (SocketAddr, Arc<AtomicBool>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let data = Arc::new(AtomicBool::new(false));
+        let sent = data.clone();
+
+        tokio::spawn(async move {
+            let (con, _) = listener.accept().await.unwrap();
+            let mut con = BufReader::new(con);
+            con.write_all(b"220-mail.example.org ESMTP Postfix\r\n220 no UCE\r\n").await.unwrap();
+
+            let mut line = String::new();
+            while con.read_line(&mut line).await.unwrap_or(0) > 0 {
+                let command = line.trim_end();
+                let reply: &[u8] = if command.starts_with("EHLO") && ehlo {
+                    b"250-mail.example.org Hello\r\n250-PIPELINING\r\n250-SIZE 10240000\r\n250-STARTTLS\r\n250-AUTH PLAIN LOGIN\r\n250-AUTH=LOGIN\r\n250 8BITMIME\r\n"
+                } else if command.starts_with("EHLO") {
+                    b"502 5.5.2 Error: command not recognized\r\n"
+                } else if command.starts_with("HELO") {
+                    b"250 mail.example.org\r\n"
+                } else if command == "MAIL FROM:<relay-test@example.com>" {
+                    b"250 2.1.0 Ok\r\n"
+                } else if command == "RCPT TO:<relay-test@example.net>" && relay {
+                    b"250 2.1.5 Ok\r\n"
+                } else if command == "RCPT TO:<relay-test@example.net>" {
+                    b"554 5.7.1 <relay-test@example.net>: Relay access denied\r\n"
+                } else if command == "DATA" {
+                    sent.store(true, Ordering::SeqCst);
+                    b"354 End data with <CR><LF>.<CR><LF>\r\n"
+                } else if command == "RSET" {
+                    b"250 2.0.0 Ok\r\n"
+                } else if command == "QUIT" {
+                    b"221 2.0.0 Bye\r\n"
+                } else {
+                    b"500 5.5.2 Error: command not recognized\r\n"
+                };
+
+                con.write_all(reply).await.unwrap();
+                line.clear();
+            }
+        });
+
+        (addr, data)
+    }
+
+    #[test]
+    fn extensions() {
+        Runtime::new().unwrap().block_on(async {
+            let (addr, _) = server(true, true).await;
+            let config = SmtpConfig { timeout: Duration::from_secs(2), relay: false };
+            let found = inspect(addr, &config).await.unwrap().unwrap();
+
+            assert_eq!(found.banner, "mail.example.org ESMTP Postfix no UCE");
+            assert_eq!(found.hostname.as_deref(), Some("mail.example.org"));
+            assert_eq!(found.extensions, vec!["PIPELINING", "SIZE 10240000", "STARTTLS", "AUTH PLAIN LOGIN", "AUTH=LOGIN", "8BITMIME"]);
+            assert!(found.starttls);
+            assert_eq!(found.auth, vec!["PLAIN", "LOGIN"]);
+            assert_eq!(found.size, Some(10_240_000));
+            // not asked for
+            assert_eq!(found.open_relay, None);
+        });
+    }
+
+    #[test]
+    fn open_relay() {
+        Runtime::new().unwrap().block_on(async {
+            let (addr, data) = server(true, true).await;
+            let config = SmtpConfig { timeout: Duration::from_secs(2), relay: true };
+            let found = inspect(addr, &config).await.unwrap().unwrap();
+
+            assert_eq!(found.open_relay, Some(true));
+            assert!(found.details().unwrap().ends_with("; open relay"));
+            assert!(!data.load(Ordering::SeqCst));
+        });
+    }
+
+    #[test]
+    fn relay_denied_after_helo() {
+        Runtime::new().unwrap().block_on(async {
+            let (addr, data) = server(false, false).await;
+            let config = SmtpConfig { timeout: Duration::from_secs(2), relay: true };
+            let found = inspect(addr, &config).await.unwrap().unwrap();
+
+            assert!(found.extensions.is_empty());
+            assert!(!found.starttls);
+            assert_eq!(found.open_relay, Some(false));
+            assert!(!data.load(Ordering::SeqCst));
+        });
+    }
+}
@@ -1,23 +1,37 @@
 use px_core::{
     pool::{JobCtrl, CRON, JobErr},
     error::Error,
-    model::State
+    model::State,
+    wrapper::{NetworkInterface, TcpInterface},
 };
 
-use tokio::{
-    io::{AsyncWriteExt, AsyncReadExt},
-    net::TcpStream
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::Arc,
+    time::Duration,
 };
+use super::{canary::Canary, Job};
+use crate::cli::output::Report;
 
-use std::net::SocketAddr;
+/// SOCKS4 replies to a request for this address are only read to identify the server,
+/// it's not expected to connect anywhere
+const NOWHERE: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 0);
 
 #[derive(Debug, Clone)]
 pub enum ScanResult {
-    SockProxy((AuthMethod, SocketAddr)),
+    SockProxy(SocksReport),
     Other(SocketAddr),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocksVersion {
+    V4,
+    /// SOCKS4 with hostnames
+    V4a,
+    V5,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum AuthMethod {
     NoAuth,
     GSSAPI,
@@ -26,17 +40,100 @@ pub enum AuthMethod {
     Other(u8)
 }
 
+/// The outcome of asking the proxy to connect to the canary
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Relay {
+    /// no canary configured, or the proxy wants credentials we don't have
+    Untested,
+    /// the canary's token came back through the proxy
+    Open,
+    /// the proxy refused the connection, with its reply code
+    Refused(u8),
+    /// the proxy claimed to connect, but the token never showed up
+    Unverified,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SocksReport {
+    pub addr: SocketAddr,
+    pub version: SocksVersion,
+    /// the method a SOCKS5 server picked, SOCKS4 has none
+    pub auth: AuthMethod,
+    /// outcome of username/password sub-negotiation, when it took place
+    pub authenticated: Option<bool>,
+    pub relay: Relay,
+}
+
+impl SocksReport {
+    fn new(addr: SocketAddr, version: SocksVersion, auth: AuthMethod) -> Self {
+        Self {
+            addr,
+            version,
+            auth,
+            authenticated: None,
+            relay: Relay::Untested,
+        }
+    }
+
+    /// connects anywhere for anyone (with the supplied credentials)
+    pub fn is_open_relay(&self) -> bool {
+        self.relay == Relay::Open
+    }
+}
+
+impl std::fmt::Display for SocksReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.version {
+            SocksVersion::V4 => write!(f, "socks4")?,
+            SocksVersion::V4a => write!(f, "socks4a")?,
+            SocksVersion::V5 => write!(f, "socks5 {:?}", self.auth)?,
+        }
+
+        match self.authenticated {
+            Some(true) => write!(f, " (credentials accepted)")?,
+            Some(false) => write!(f, " (credentials rejected)")?,
+            None => {}
+        }
+
+        match self.relay {
+            Relay::Untested => Ok(()),
+            Relay::Open => write!(f, ", open relay"),
+            Relay::Refused(code) => write!(f, ", relay refused ({:#04x})", code),
+            Relay::Unverified => write!(f, ", relay unverified"),
+        }
+    }
+}
+
+impl Report for ScanResult {
+    fn details(&self) -> Option<String> {
+        match self {
+            ScanResult::SockProxy(report) => Some(report.to_string()),
+            ScanResult::Other(_) => None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SocksConfig {
+    /// when set, proxies are asked to connect to it
+    pub canary: Option<Arc<Canary>>,
+    /// username/password offered to SOCKS5 servers (RFC 1929),
+    /// the username doubles as SOCKS4's userid
+    pub credentials: Option<(String, String)>,
+    pub timeout: Duration,
+}
+
 #[derive(Debug)]
 pub struct Socks5Scanner;
 
 #[async_trait::async_trait]
 impl CRON for Socks5Scanner {
-    type State = SocketAddr;
+    type State = Job<SocksConfig>;
     type Response = ScanResult;
 
-    async fn exec(addr: &mut SocketAddr) -> Result<JobCtrl<Self::Response>, Error>
+    async fn exec(state: &mut Job<SocksConfig>) -> Result<JobCtrl<Self::Response>, Error>
     {
-        match scan(*addr).await {
+        match scan(state.addr, &state.config).await {
             Ok(method) => return Ok(JobCtrl::Return(State::Open, method)),
 
             Err(Error::IO(x)) => return Ok(JobCtrl::Error(super::handle_io_error(x))),
@@ -44,41 +141,57 @@ impl CRON for Socks5Scanner {
             Err(e) => {
                 eprintln!("unmatched error {:#?} [not io error]", e);
                 Ok(JobCtrl::Error(JobErr::Other))
-                
+
             }
         }
     }
 }
 
+/// Tries SOCKS5, then SOCKS4 on a new connection
+pub async fn scan(addr: SocketAddr, config: &SocksConfig) -> Result<ScanResult, Error> {
+    let mut con = TcpInterface::connect(addr, config.timeout).await?;
+
+    // anything that doesn't talk SOCKS5 may still talk SOCKS4,
+    // the port is open either way
+    if let Ok(Some(report)) = socks5(&mut con, config).await {
+        return Ok(ScanResult::SockProxy(report))
+    }
+
+    if let Ok(Some(report)) = socks4(addr, config).await {
+        return Ok(ScanResult::SockProxy(report))
+    }
+
+    Ok(ScanResult::Other(addr))
+}
 
-async fn scan(addr: SocketAddr) -> Result<ScanResult, Error> {
+async fn socks5<I: NetworkInterface>(con: &mut I, config: &SocksConfig) -> Result<Option<SocksReport>, Error> {
     /*
     +----+----------+----------+
     |VER | NMETHODS | METHODS  |
     +----+----------+----------+
     | 1  |    1     | 1 to 255 |
-    +----+----------+----------+*/  
-    const GREETING: [u8; 3] = [
-        5, // version
-        1, // nmethods: 1-255 (1)
-        0  // auth-methods: No-auth (1)   
-    ];
-    
-    let mut con = TcpStream::connect(addr).await?;
-    con.write_all(&GREETING).await?;    
+    +----+----------+----------+*/
+    let greeting: &[u8] = match config.credentials {
+        // auth-methods: No-auth, username/password
+        Some(_) => &[5, 2, 0, 2],
+        // auth-methods: No-auth
+        None => &[5, 1, 0],
+    };
+
+    con.write_iface(greeting).await?;
     /*
     +----+--------+
     |VER | METHOD |
     +----+--------+
     | 1  |   1    |
     +----+--------+*/
-    let mut buf: [u8; 2] = [0; 2];
-    con.read_exact(&mut buf).await?;
-    
+    let mut buf = Vec::with_capacity(2);
+    con.read_exact_iface(&mut buf, 2).await?;
+
     if buf[0] != 5 {
-        return Ok(ScanResult::Other(addr))
+        return Ok(None)
     }
-    
+
     let auth_method = match buf[1] {
         0x00 => AuthMethod::NoAuth,
         0x01 => AuthMethod::GSSAPI,
@@ -86,7 +199,337 @@ async fn scan(addr: SocketAddr) -> Result<ScanResult, Error> {
         0xFF => AuthMethod::NoAcceptableMethods,
         x => AuthMethod::Other(x)
     };
-     
 
-    Ok(ScanResult::SockProxy((auth_method, addr)))
+    let mut report = SocksReport::new(con.peer(), SocksVersion::V5, auth_method);
+
+    let usable = match (&report.auth, &config.credentials) {
+        (AuthMethod::NoAuth, _) => true,
+        (AuthMethod::Creds, Some((user, pass))) => {
+            let accepted = authenticate(con, user, pass).await?;
+            report.authenticated = Some(accepted);
+            accepted
+        }
+        _ => false
+    };
+
+    if let (true, Some(canary)) = (usable, &config.canary) {
+        report.relay = socks5_connect(con, canary).await?;
+    }
+
+    Ok(Some(report))
+}
+
+/// Username/password sub-negotiation (RFC 1929)
+async fn authenticate<I: NetworkInterface>(con: &mut I, user: &str, pass: &str) -> Result<bool, Error> {
+    /*
+    +----+------+----------+------+----------+
+    |VER | ULEN |  UNAME   | PLEN |  PASSWD  |
+    +----+------+----------+------+----------+
+    | 1  |  1   | 1 to 255 |  1   | 1 to 255 |
+    +----+------+----------+------+----------+*/
+    let (user, pass) = (truncate(user.as_bytes()), truncate(pass.as_bytes()));
+
+    let mut request = vec![1, user.len() as u8];
+    request.extend_from_slice(user);
+    request.push(pass.len() as u8);
+    request.extend_from_slice(pass);
+
+    con.write_iface(&request).await?;
+    /*
+    +----+--------+
+    |VER | STATUS |
+    +----+--------+
+    | 1  |   1    |
+    +----+--------+*/
+    let mut buf = Vec::with_capacity(2);
+    con.read_exact_iface(&mut buf, 2).await?;
+
+    Ok(buf[1] == 0)
+}
+
+async fn socks5_connect<I: NetworkInterface>(con: &mut I, canary: &Canary) -> Result<Relay, Error> {
+    /*
+    +----+-----+-------+------+----------+----------+
+    |VER | CMD |  RSV  | ATYP | DST.ADDR | DST.PORT |
+    +----+-----+-------+------+----------+----------+
+    | 1  |  1  | X'00' |  1   | Variable |    2     |
+    +----+-----+-------+------+----------+----------+*/
+    let target = canary.addr();
+    let mut request = vec![5, 1, 0];
+    match target.ip() {
+        IpAddr::V4(ip) => {
+            request.push(1);
+            request.extend_from_slice(&ip.octets());
+        }
+        IpAddr::V6(ip) => {
+            request.push(4);
+            request.extend_from_slice(&ip.octets());
+        }
+    }
+    request.extend_from_slice(&target.port().to_be_bytes());
+
+    con.write_iface(&request).await?;
+    /*
+    +----+-----+-------+------+----------+----------+
+    |VER | REP |  RSV  | ATYP | BND.ADDR | BND.PORT |
+    +----+-----+-------+------+----------+----------+
+    | 1  |  1  | X'00' |  1   | Variable |    2     |
+    +----+-----+-------+------+----------+----------+*/
+    let mut buf = Vec::with_capacity(22);
+    con.read_exact_iface(&mut buf, 4).await?;
+
+    if buf[1] != 0 {
+        return Ok(Relay::Refused(buf[1]))
+    }
+
+    let bound = match buf[3] {
+        1 => 4,
+        4 => 16,
+        // domain name, length prefixed
+        3 => {
+            con.read_exact_iface(&mut buf, 1).await?;
+            buf[4] as usize
+        }
+        _ => return Ok(Relay::Unverified)
+    };
+    con.read_exact_iface(&mut buf, bound + 2).await?;
+
+    Ok(expect_token(con, canary).await)
+}
+
+/// SOCKS4 (and 4a) have no greeting, the CONNECT request is the first thing sent
+async fn socks4(addr: SocketAddr, config: &SocksConfig) -> Result<Option<SocksReport>, Error> {
+    let userid = config.credentials.as_ref()
+        .map(|(user, _)| user.as_str())
+        .unwrap_or("");
+
+    let canary = match &config.canary {
+        Some(canary) if canary.addr().is_ipv4() => Some(canary),
+        _ => None
+    };
+
+    let target = canary.map(|x| x.addr()).unwrap_or(NOWHERE);
+
+    let mut con = TcpInterface::connect(addr, config.timeout).await?;
+    let granted = match socks4_connect(&mut con, target, userid, None).await? {
+        Some(granted) => granted,
+        None => return Ok(None)
+    };
+
+    let mut report = SocksReport::new(addr, SocksVersion::V4, AuthMethod::NoAuth);
+    let canary = match canary {
+        Some(canary) => canary,
+        None => return Ok(Some(report))
+    };
+
+    report.relay = match granted {
+        Ok(()) => expect_token(&mut con, canary).await,
+        Err(code) => Relay::Refused(code)
+    };
+
+    // 4a takes a hostname instead of an address,
+    // the canary's address spelled out works without a resolver
+    let mut con = TcpInterface::connect(addr, config.timeout).await?;
+    let hostname = canary.addr().ip().to_string();
+    if let Ok(Some(Ok(()))) = socks4_connect(&mut con, canary.addr(), userid, Some(&hostname)).await {
+        if expect_token(&mut con, canary).await == Relay::Open {
+            report.version = SocksVersion::V4a;
+        }
+    }
+
+    Ok(Some(report))
+}
+
+/// `None` when the reply isn't SOCKS4,
+/// otherwise if the request was granted, or the reply code it was rejected with
+async fn socks4_connect<I: NetworkInterface>(con: &mut I, target: SocketAddr, userid: &str, hostname: Option<&str>) -> Result<Option<Result<(), u8>>, Error> {
+    /*
+    +----+----+----+----+----+----+----+----+----+----+....+----+
+    | VN | CD | DSTPORT |      DSTIP        | USERID       |NULL|
+    +----+----+----+----+----+----+----+----+----+----+....+----+
+       1    1      2              4           variable       1  */
+    let ip = match (target.ip(), hostname) {
+        // 0.0.0.x tells a 4a server a hostname follows
+        (_, Some(_)) => [0, 0, 0, 1],
+        (IpAddr::V4(ip), None) => ip.octets(),
+        (IpAddr::V6(_), None) => return Ok(None)
+    };
+
+    let mut request = vec![4, 1];
+    request.extend_from_slice(&target.port().to_be_bytes());
+    request.extend_from_slice(&ip);
+    request.extend_from_slice(userid.as_bytes());
+    request.push(0);
+
+    if let Some(hostname) = hostname {
+        request.extend_from_slice(hostname.as_bytes());
+        request.push(0);
+    }
+
+    con.write_iface(&request).await?;
+    /*
+    +----+----+----+----+----+----+----+----+
+    | VN | CD | DSTPORT |      DSTIP        |
+    +----+----+----+----+----+----+----+----+
+       1    1      2              4         */
+    let mut buf = Vec::with_capacity(8);
+    con.read_exact_iface(&mut buf, 8).await?;
+
+    Ok(match (buf[0], buf[1]) {
+        (0, 0x5A) => Some(Ok(())),
+        // rejected, no identd, identd mismatch
+        (0, code @ 0x5B..=0x5D) => Some(Err(code)),
+        _ => None
+    })
+}
+
+/// Reads the canary's token through an established tunnel
+async fn expect_token<I: NetworkInterface>(con: &mut I, canary: &Canary) -> Relay {
+    let token = canary.token();
+    let mut buf = Vec::with_capacity(token.len());
+
+    match con.read_exact_iface(&mut buf, token.len()).await {
+        Ok(()) if buf == token => Relay::Open,
+        _ => Relay::Unverified
+    }
+}
+
+fn truncate(field: &[u8]) -> &[u8] {
+    &field[..field.len().min(255)]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::{
+        runtime::Runtime,
+        net::{TcpListener, TcpStream},
+        io::{AsyncReadExt, AsyncWriteExt, copy_bidirectional},
+    };
+
+    /// just enough of a SOCKS5 server (RFC 1928/1929) to relay ipv4 CONNECTs
+    async fn socks5_server(listener: TcpListener, creds: Option<(&'static str, &'static str)>) {
+        loop {
+            let (mut con, _) = listener.accept().await.unwrap();
+            tokio::spawn(async move {
+                let mut greeting = [0; 2];
+                con.read_exact(&mut greeting).await.unwrap();
+                let mut methods = vec![0; greeting[1] as usize];
+                con.read_exact(&mut methods).await.unwrap();
+
+                match creds {
+                    Some((user, pass)) if methods.contains(&2) => {
+                        con.write_all(&[5, 2]).await.unwrap();
+
+                        let mut buf = [0; 513];
+                        let n = con.read(&mut buf).await.unwrap();
+                        let expected = [&[1, user.len() as u8][..], user.as_bytes(), &[pass.len() as u8], pass.as_bytes()].concat();
+                        let ok = buf[..n] == expected[..];
+
+                        con.write_all(&[1, if ok { 0 } else { 1 }]).await.unwrap();
+                        if !ok { return }
+                    }
+                    Some(_) => {
+                        con.write_all(&[5, 0xFF]).await.unwrap();
+                        return
+                    }
+                    None => con.write_all(&[5, 0]).await.unwrap()
+                }
+
+                let mut request = [0; 10];
+                con.read_exact(&mut request).await.unwrap();
+                let target = SocketAddr::from((
+                    [request[4], request[5], request[6], request[7]],
+                    u16::from_be_bytes([request[8], request[9]])
+                ));
+
+                let mut upstream = TcpStream::connect(target).await.unwrap();
+                con.write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 0]).await.unwrap();
+                let _ = copy_bidirectional(&mut con, &mut upstream).await;
+            });
+        }
+    }
+
+    /// SOCKS4 server that refuses everything
+    async fn socks4_server(listener: TcpListener) {
+        loop {
+            let (mut con, _) = listener.accept().await.unwrap();
+            tokio::spawn(async move {
+                let mut buf = [0; 64];
+                let n = con.read(&mut buf).await.unwrap();
+                if n > 0 && buf[0] == 4 {
+                    con.write_all(&[0, 0x5B, 0, 0, 0, 0, 0, 0]).await.unwrap();
+                }
+            });
+        }
+    }
+
+    async fn config(creds: Option<(&str, &str)>) -> SocksConfig {
+        SocksConfig {
+            canary: Some(Arc::new(Canary::bind("127.0.0.1:0".parse().unwrap()).await.unwrap())),
+            credentials: creds.map(|(user, pass)| (user.to_string(), pass.to_string())),
+            timeout: Duration::from_secs(2),
+        }
+    }
+
+    fn report(result: ScanResult) -> SocksReport {
+        match result {
+            ScanResult::SockProxy(report) => report,
+            ScanResult::Other(addr) => panic!("{} not identified as socks", addr)
+        }
+    }
+
+    #[test]
+    fn socks5_open_relay() {
+        let rt = Runtime::new().unwrap();
+
+        rt.block_on(async move {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            tokio::spawn(socks5_server(listener, None));
+
+            let found = report(scan(addr, &config(None).await).await.unwrap());
+            assert_eq!(found.version, SocksVersion::V5);
+            assert_eq!(found.auth, AuthMethod::NoAuth);
+            assert!(found.is_open_relay());
+        });
+    }
+
+    #[test]
+    fn socks5_credentials() {
+        let rt = Runtime::new().unwrap();
+
+        rt.block_on(async move {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            tokio::spawn(socks5_server(listener, Some(("admin", "hunter2"))));
+
+            let found = report(scan(addr, &config(Some(("admin", "hunter2"))).await).await.unwrap());
+            assert_eq!(found.auth, AuthMethod::Creds);
+            assert_eq!(found.authenticated, Some(true));
+            assert!(found.is_open_relay());
+
+            let found = report(scan(addr, &config(Some(("admin", "wrong"))).await).await.unwrap());
+            assert_eq!(found.authenticated, Some(false));
+            assert_eq!(found.relay, Relay::Untested);
+
+            let found = report(scan(addr, &config(None).await).await.unwrap());
+            assert_eq!(found.auth, AuthMethod::NoAcceptableMethods);
+        });
+    }
+
+    #[test]
+    fn socks4_detected() {
+        let rt = Runtime::new().unwrap();
+
+        rt.block_on(async move {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            tokio::spawn(socks4_server(listener));
+
+            let found = report(scan(addr, &config(None).await).await.unwrap());
+            assert_eq!(found.version, SocksVersion::V4);
+            assert_eq!(found.relay, Relay::Refused(0x5B));
+        });
+    }
 }
@@ -0,0 +1,543 @@
+use px_core::{
+    pool::{JobCtrl, CRON, JobErr},
+    error::Error,
+    model::State,
+    wrapper::{NetworkInterface, TcpInterface},
+};
+use ring::{
+    agreement::{self, EphemeralPrivateKey},
+    digest::{digest, SHA256},
+    rand::SystemRandom,
+};
+use serde::Serialize;
+
+use std::{
+    net::SocketAddr,
+    time::Duration,
+};
+use super::{Job, buffered::Buffered};
+use crate::cli::output::{Report, ServiceInfo};
+
+/// Ours, sent once the server identified itself, it has to see one to start the key exchange
+const IDENTIFICATION: &[u8] = b"SSH-2.0-OpenSSH_8.9\r\n";
+/// Lines the server may send before its identification (RFC 4253 4.2)
+const MAX_PREAMBLE: usize = 8 * 1024;
+/// Largest packet we'll accept, RFC 4253 6.1 asks for 35000
+const MAX_PACKET: usize = 35000;
+
+const MSG_KEXINIT: u8 = 20;
+const MSG_KEX_ECDH_INIT: u8 = 30;
+const MSG_KEX_ECDH_REPLY: u8 = 31;
+
+/// What we can complete a key exchange with, far enough for the host key
+const KEX: [&str; 3] = ["curve25519-sha256", "curve25519-sha256@libssh.org", "ecdh-sha2-nistp256"];
+
+/// Offered algorithms considered weak, by prefix
+const WEAK: [&str; 16] = [
+    "diffie-hellman-group1-sha1",
+    "diffie-hellman-group14-sha1",
+    "diffie-hellman-group-exchange-sha1",
+    "gss-",
+    "ssh-dss",
+    "ssh-rsa-cert-v00",
+    "3des-",
+    "arcfour",
+    "blowfish-",
+    "cast128-",
+    "des-",
+    "aes128-cbc",
+    "aes192-cbc",
+    "aes256-cbc",
+    "hmac-md5",
+    "hmac-sha1-96",
+];
+
+/// The name-lists of a KEXINIT (RFC 4253 7.1)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Algorithms {
+    pub kex: Vec<String>,
+    pub host_key: Vec<String>,
+    pub ciphers_client_server: Vec<String>,
+    pub ciphers_server_client: Vec<String>,
+    pub macs_client_server: Vec<String>,
+    pub macs_server_client: Vec<String>,
+    pub compression_client_server: Vec<String>,
+    pub compression_server_client: Vec<String>,
+}
+
+impl Algorithms {
+    /// From a KEXINIT's payload, message number included
+    pub fn parse(payload: &[u8]) -> Option<Self> {
+        if payload.first() != Some(&MSG_KEXINIT) {
+            return None
+        }
+
+        // message number, cookie
+        let mut reader = Reader(payload.get(17..)?);
+        let mut lists = Vec::with_capacity(8);
+        for _ in 0..8 {
+            lists.push(reader.name_list()?);
+        }
+
+        let mut lists = lists.into_iter();
+        Some(Self {
+            kex: lists.next()?,
+            host_key: lists.next()?,
+            ciphers_client_server: lists.next()?,
+            ciphers_server_client: lists.next()?,
+            macs_client_server: lists.next()?,
+            macs_server_client: lists.next()?,
+            compression_client_server: lists.next()?,
+            compression_server_client: lists.next()?,
+        })
+    }
+
+    /// Every offered algorithm in `WEAK`, and "none" ciphers/macs
+    pub fn weak(&self) -> Vec<String> {
+        let offered = self.kex.iter()
+            .chain(&self.host_key)
+            .chain(&self.ciphers_client_server)
+            .chain(&self.ciphers_server_client)
+            .chain(&self.macs_client_server)
+            .chain(&self.macs_server_client);
+
+        let mut weak: Vec<String> = Vec::new();
+        for name in offered {
+            let is_weak = name == "none" || WEAK.iter().any(|prefix| name.starts_with(prefix));
+            if is_weak && !weak.contains(name) {
+                weak.push(name.clone());
+            }
+        }
+        weak
+    }
+
+    /// Our KEXINIT. Only key exchanges we can complete are offered,
+    /// everything else is the server's own list, so that negotiation can't fail over them
+    fn reply(&self) -> Vec<u8> {
+        let mut payload = vec![MSG_KEXINIT];
+        payload.extend_from_slice(&super::rand_u64().to_be_bytes());
+        payload.extend_from_slice(&super::rand_u64().to_be_bytes());
+
+        let kex: Vec<String> = KEX.iter().map(|x| x.to_string()).collect();
+        for list in [
+            &kex,
+            &self.host_key,
+            &self.ciphers_client_server,
+            &self.ciphers_server_client,
+            &self.macs_client_server,
+            &self.macs_server_client,
+            &self.compression_client_server,
+            &self.compression_server_client,
+        ].iter() {
+            put_string(&mut payload, list.join(",").as_bytes());
+        }
+
+        // languages, first_kex_packet_follows, reserved
+        put_string(&mut payload, b"");
+        put_string(&mut payload, b"");
+        payload.push(0);
+        payload.extend_from_slice(&[0; 4]);
+        payload
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HostKey {
+    /// "ssh-ed25519", "ecdsa-sha2-nistp256" ...
+    pub algorithm: String,
+    /// like OpenSSH prints them, "SHA256:<base64>"
+    pub fingerprint: String,
+}
+
+impl HostKey {
+    /// From the public key blob sent in the key exchange
+    pub fn from_blob(blob: &[u8]) -> Option<Self> {
+        let algorithm = Reader(blob).string()?;
+
+        Some(Self {
+            algorithm: String::from_utf8_lossy(algorithm).to_string(),
+            fingerprint: format!("SHA256:{}", base64::encode_config(digest(&SHA256, blob), base64::STANDARD_NO_PAD)),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SshInfo {
+    /// the whole line, "SSH-2.0-OpenSSH_8.9p1 Ubuntu-3"
+    pub identification: String,
+    /// "2.0", or "1.99" for servers that still speak SSH-1
+    pub protocol: String,
+    pub software: String,
+    pub comments: Option<String>,
+    pub algorithms: Algorithms,
+    /// Only collected from servers offering one of `KEX` (curve25519 or nistp256 ECDH),
+    /// `None` otherwise, the host key algorithms it offered are still in `algorithms`
+    pub host_key: Option<HostKey>,
+    pub weak: Vec<String>,
+}
+
+impl SshInfo {
+    /// "SSH-2.0-OpenSSH_8.9p1 Ubuntu-3"
+    fn identify(line: &str) -> Option<Self> {
+        let rest = line.strip_prefix("SSH-")?;
+        let (protocol, rest) = rest.split_once('-')?;
+        let (software, comments) = match rest.split_once(' ') {
+            Some((software, comments)) => (software, Some(comments.to_string())),
+            None => (rest, None)
+        };
+
+        Some(Self {
+            identification: line.to_string(),
+            protocol: protocol.to_string(),
+            software: software.to_string(),
+            comments,
+            algorithms: Algorithms::default(),
+            host_key: None,
+            weak: Vec::new(),
+        })
+    }
+}
+
+impl Report for SshInfo {
+    fn service(&self) -> Option<ServiceInfo> {
+        // "OpenSSH_8.9p1" -> OpenSSH, 8.9p1
+        let (product, version) = match self.software.split_once('_') {
+            Some((product, version)) => (product.to_string(), Some(version.to_string())),
+            None => (self.software.clone(), None)
+        };
+
+        Some(ServiceInfo {
+            name: "ssh".to_string(),
+            product: Some(product),
+            version,
+            cpe: Vec::new(),
+        })
+    }
+
+    fn details(&self) -> Option<String> {
+        let mut details = vec![format!("protocol {}", self.protocol)];
+
+        match &self.host_key {
+            Some(key) => details.push(format!("{} {}", key.algorithm, key.fingerprint)),
+            None if !self.algorithms.host_key.is_empty() =>
+                details.push(format!("host keys: {}", self.algorithms.host_key.join(","))),
+            None => {}
+        }
+        if !self.weak.is_empty() {
+            details.push(format!("weak: {}", self.weak.join(",")));
+        }
+
+        Some(details.join("; "))
+    }
+
+    fn data(&self) -> Option<serde_json::Value> {
+        serde_json::to_value(self).ok()
+    }
+}
+
+#[derive(Debug)]
+pub struct SshConfig {
+    pub timeout: Duration,
+}
+
+/// Reads the identification string, exchanges KEXINITs,
+/// and goes as far into an ECDH key exchange as the server's reply, which holds its host key.
+/// Nothing is authenticated or encrypted.
+/// Ports that don't identify as SSH are open, without a response.
+#[derive(Debug)]
+pub struct SshScanner;
+
+#[async_trait::async_trait]
+impl CRON for SshScanner {
+    type State = Job<SshConfig>;
+    type Response = Option<SshInfo>;
+
+    async fn exec(state: &mut Job<SshConfig>) -> Result<JobCtrl<Self::Response>, Error> {
+        match inspect(state.addr, state.config.timeout).await {
+            Ok(found) => Ok(JobCtrl::Return(State::Open, found)),
+
+            Err(Error::IO(err)) => Ok(JobCtrl::Error(super::handle_io_error(err))),
+            Err(e) => {
+                eprintln!("unmatched error {:#?} [not io error]", e);
+                Ok(JobCtrl::Error(JobErr::Other))
+            }
+        }
+    }
+}
+
+/// Errors when the port can't be connected to,
+/// everything after the identification line is best effort and left out when it fails
+pub async fn inspect(addr: SocketAddr, timeout: Duration) -> Result<Option<SshInfo>, Error> {
+    let iface = TcpInterface::connect(addr, timeout).await?;
+    let mut con = Connection { con: Buffered::new(iface) };
+
+    let mut found = match con.identification().await {
+        Some(found) => found,
+        None => return Ok(None)
+    };
+
+    // SSH-1 only servers don't have a KEXINIT
+    if found.protocol != "2.0" && found.protocol != "1.99" {
+        return Ok(Some(found))
+    }
+
+    if con.con.write(IDENTIFICATION).await.is_none() {
+        return Ok(Some(found))
+    }
+
+    let algorithms = match con.packet().await.and_then(|payload| Algorithms::parse(&payload)) {
+        Some(algorithms) => algorithms,
+        None => return Ok(Some(found))
+    };
+
+    found.weak = algorithms.weak();
+    found.host_key = con.host_key(&algorithms).await;
+    found.algorithms = algorithms;
+
+    Ok(Some(found))
+}
+
+struct Connection<I> {
+    con: Buffered<I>,
+}
+
+impl<I: NetworkInterface> Connection<I> {
+
+    /// Skips anything sent before the line starting with "SSH-"
+    async fn identification(&mut self) -> Option<SshInfo> {
+        let mut scanned = 0;
+        loop {
+            let end = match self.con.buf[scanned..].iter().position(|b| *b == b'\n') {
+                Some(at) => scanned + at,
+                None if self.con.buf.len() >= MAX_PREAMBLE => return None,
+                None => {
+                    scanned = self.con.buf.len();
+                    self.con.more(MAX_PREAMBLE).await?;
+                    continue
+                }
+            };
+            scanned = 0;
+
+            let line = self.con.take(end + 1);
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(|c| c == '\r' || c == '\n');
+
+            if line.starts_with("SSH-") {
+                return SshInfo::identify(line)
+            }
+        }
+    }
+
+    /// The payload of the next unencrypted binary packet (RFC 4253 6)
+    async fn packet(&mut self) -> Option<Vec<u8>> {
+        self.con.fill(5).await?;
+
+        let length = u32::from_be_bytes([self.con.buf[0], self.con.buf[1], self.con.buf[2], self.con.buf[3]]) as usize;
+        let padding = self.con.buf[4] as usize;
+        if length > MAX_PACKET || padding + 1 > length {
+            return None
+        }
+
+        self.con.fill(4 + length).await?;
+        let packet = self.con.take(4 + length);
+        Some(packet[5..4 + length - padding].to_vec())
+    }
+
+    async fn send(&mut self, payload: &[u8]) -> Option<()> {
+        // at least 4 bytes of padding, everything a multiple of 8
+        let mut padding = 8 - (4 + 1 + payload.len()) % 8;
+        if padding < 4 {
+            padding += 8;
+        }
+
+        let mut packet = Vec::with_capacity(5 + payload.len() + padding);
+        packet.extend_from_slice(&((1 + payload.len() + padding) as u32).to_be_bytes());
+        packet.push(padding as u8);
+        packet.extend_from_slice(payload);
+        packet.extend(std::iter::repeat(0).take(padding));
+        self.con.write(&packet).await
+    }
+
+    /// Sends our KEXINIT and an ECDH init,
+    /// the server's reply starts with its host key
+    async fn host_key(&mut self, server: &Algorithms) -> Option<HostKey> {
+        // negotiation picks the first of ours the server also offers
+        let kex = KEX.iter().find(|ours| server.kex.iter().any(|x| x == *ours))?;
+        let curve = match *kex {
+            "ecdh-sha2-nistp256" => &agreement::ECDH_P256,
+            _ => &agreement::X25519,
+        };
+
+        let secret = EphemeralPrivateKey::generate(curve, &SystemRandom::new()).ok()?;
+        let public = secret.compute_public_key().ok()?;
+
+        self.send(&server.reply()).await?;
+
+        let mut init = vec![MSG_KEX_ECDH_INIT];
+        put_string(&mut init, public.as_ref());
+        self.send(&init).await?;
+
+        let reply = self.packet().await?;
+        if reply.first() != Some(&MSG_KEX_ECDH_REPLY) {
+            return None
+        }
+
+        HostKey::from_blob(Reader(&reply[1..]).string()?)
+    }
+}
+
+/// Walks the encodings of RFC 4251 5
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn string(&mut self) -> Option<&'a [u8]> {
+        let length = match self.0.get(..4)? {
+            [a, b, c, d] => u32::from_be_bytes([*a, *b, *c, *d]) as usize,
+            _ => return None
+        };
+        let value = self.0.get(4..4 + length)?;
+        self.0 = &self.0[4 + length..];
+        Some(value)
+    }
+
+    fn name_list(&mut self) -> Option<Vec<String>> {
+        let list = std::str::from_utf8(self.string()?).ok()?;
+        Some(list.split(',').filter(|x| !x.is_empty()).map(str::to_string).collect())
+    }
+}
+
+fn put_string(buf: &mut Vec<u8>, value: &[u8]) {
+    buf.extend_from_slice(&(value.len() as u32).to_be_bytes());
+    buf.extend_from_slice(value);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::{
+        runtime::Runtime,
+        net::TcpListener,
+        io::{AsyncReadExt, AsyncWriteExt},
+        task::JoinHandle,
+    };
+
+    fn kexinit(lists: [&str; 8]) -> Vec<u8> {
+        let mut payload = vec![MSG_KEXINIT];
+        payload.extend_from_slice(&[7; 16]);
+        for list in lists.iter() {
+            put_string(&mut payload, list.as_bytes());
+        }
+        put_string(&mut payload, b"");
+        put_string(&mut payload, b"");
+        payload.extend_from_slice(&[0; 5]);
+        payload
+    }
+
+    fn packet(payload: &[u8]) -> Vec<u8> {
+        let mut packet = ((payload.len() + 5) as u32).to_be_bytes().to_vec();
+        packet.push(4);
+        packet.extend_from_slice(payload);
+        packet.extend_from_slice(&[0; 4]);
+        packet
+    }
+
+    fn host_key_blob() -> Vec<u8> {
+        let mut blob = Vec::new();
+        put_string(&mut blob, b"ssh-ed25519");
+        put_string(&mut blob, &[9; 32]);
+        blob
+    }
+
+    /// identifies after a banner line, offers `lists`,
+    /// and answers the client's ECDH init with a made up host key.
+    /// Hands back the client's identification, and the message numbers it sent
+    async fn server(lists: [&'static str; 8]) -> (SocketAddr, JoinHandle<(Vec<u8>, Vec<u8>)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let handle = tokio::spawn(async move {
+            let (mut con, _) = listener.accept().await.unwrap();
+
+            let mut hello = b"Welcome\r\nSSH-2.0-OpenSSH_7.4 Debian-10\r\n".to_vec();
+            hello.extend_from_slice(&packet(&kexinit(lists)));
+            con.write_all(&hello).await.unwrap();
+
+            let mut line = Vec::new();
+            while !line.ends_with(b"\n") {
+                line.push(con.read_u8().await.unwrap());
+            }
+
+            // KEXINIT, ECDH init
+            let mut messages = Vec::new();
+            for _ in 0..2 {
+                let length = con.read_u32().await.unwrap() as usize;
+                let mut packet = vec![0; length];
+                con.read_exact(&mut packet).await.unwrap();
+                messages.push(packet[1]);
+            }
+
+            let mut reply = vec![MSG_KEX_ECDH_REPLY];
+            put_string(&mut reply, &host_key_blob());
+            put_string(&mut reply, &[1; 32]);
+            put_string(&mut reply, b"signature");
+            con.write_all(&packet(&reply)).await.unwrap();
+            (line, messages)
+        });
+
+        (addr, handle)
+    }
+
+    #[test]
+    fn inventory() {
+        let rt = Runtime::new().unwrap();
+
+        rt.block_on(async move {
+            let (addr, handle) = server([
+                "curve25519-sha256,diffie-hellman-group1-sha1",
+                "ssh-ed25519,ssh-dss",
+                "aes128-ctr,3des-cbc",
+                "aes128-ctr,3des-cbc",
+                "hmac-sha2-256,hmac-md5",
+                "hmac-sha2-256,hmac-md5",
+                "none",
+                "none",
+            ]).await;
+
+            let found = inspect(addr, Duration::from_secs(2)).await.unwrap().unwrap();
+
+            let (line, messages) = handle.await.unwrap();
+            assert_eq!(line, IDENTIFICATION);
+            assert_eq!(messages, vec![MSG_KEXINIT, MSG_KEX_ECDH_INIT]);
+
+            assert_eq!(found.identification, "SSH-2.0-OpenSSH_7.4 Debian-10");
+            assert_eq!(found.protocol, "2.0");
+            assert_eq!(found.comments.as_deref(), Some("Debian-10"));
+            assert_eq!(found.algorithms.host_key, vec!["ssh-ed25519", "ssh-dss"]);
+            assert_eq!(found.weak, vec!["diffie-hellman-group1-sha1", "ssh-dss", "3des-cbc", "hmac-md5"]);
+
+            let key = found.host_key.clone().unwrap();
+            assert_eq!(key.algorithm, "ssh-ed25519");
+            assert_eq!(key.fingerprint, HostKey::from_blob(&host_key_blob()).unwrap().fingerprint);
+            assert!(key.fingerprint.starts_with("SHA256:"));
+
+            let service = found.service().unwrap();
+            assert_eq!(service.product.as_deref(), Some("OpenSSH"));
+            assert_eq!(service.version.as_deref(), Some("7.4"));
+        });
+    }
+
+    #[test]
+    fn parses_kexinit() {
+        let payload = kexinit(["a,b", "c", "d", "e", "f", "g", "none", ""]);
+        let algorithms = Algorithms::parse(&payload).unwrap();
+
+        assert_eq!(algorithms.kex, vec!["a", "b"]);
+        assert_eq!(algorithms.compression_server_client, Vec::<String>::new());
+        assert!(Algorithms::parse(&payload[..40]).is_none());
+
+        // our reply parses back, with our key exchanges
+        let reply = Algorithms::parse(&algorithms.reply()).unwrap();
+        assert_eq!(reply.kex, KEX.to_vec());
+        assert_eq!(reply.host_key, vec!["c"]);
+    }
+}
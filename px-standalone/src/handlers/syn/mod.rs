@@ -0,0 +1,314 @@
+use px_core::{
+    pool::{JobCtrl, CRON, JobErr},
+    error::Error,
+    model::State,
+};
+
+use socket2::{Socket, Domain, Type, Protocol};
+use tokio::{
+    net::UdpSocket,
+    sync::oneshot,
+    task::JoinHandle,
+    time::timeout,
+};
+
+use std::{
+    collections::{HashMap, hash_map::DefaultHasher},
+    hash::Hasher,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use super::{handle_io_error, rand_u64, Job};
+
+pub mod packet;
+
+/// SYNs sent before the port is considered filtered
+pub const ATTEMPTS: u8 = 2;
+/// Longest a receive task sleeps after its socket errors, doubling from a millisecond
+const MAX_BACKOFF: Duration = Duration::from_secs(1);
+
+type Pending = Arc<Mutex<HashMap<SocketAddr, oneshot::Sender<State>>>>;
+
+/// Half-open scan, driven by a `SynEngine`.
+/// SYN-ACK means open, RST means closed, and silence filtered.
+#[derive(Debug)]
+pub struct SynProbe;
+
+#[async_trait::async_trait]
+impl CRON for SynProbe {
+    type State = Job<SynEngine>;
+    type Response = SocketAddr;
+
+    async fn exec(state: &mut Job<SynEngine>) -> Result<JobCtrl<Self::Response>, Error> {
+        match state.config.probe(state.addr).await {
+            Ok(netstate) => Ok(JobCtrl::Return(netstate, state.addr)),
+
+            Err(Error::IO(err)) => Ok(JobCtrl::Error(handle_io_error(err))),
+            Err(e) => {
+                eprintln!("unmatched error {:#?} [not io error]", e);
+                Ok(JobCtrl::Error(JobErr::Other))
+            }
+        }
+    }
+}
+
+/// Sends hand crafted SYNs over raw sockets, requires `CAP_NET_RAW`.
+///
+/// A receive task per address family reads every tcp segment coming into the host,
+/// and hands SYN-ACK/RSTs back to the probe waiting on them.
+/// Replies are matched with a cookie in the sequence number of the SYN,
+/// keyed on the peer and our source port, so nothing needs to be stored per probe to verify it
+/// and stray segments can't pass for an answer.
+#[derive(Debug)]
+pub struct SynEngine {
+    v4: Option<Arc<UdpSocket>>,
+    v6: Option<Arc<UdpSocket>>,
+
+    source_port: u16,
+    secret: u64,
+
+    pending: Pending,
+    receivers: Vec<JoinHandle<()>>,
+
+    wait: Duration,
+    attempts: u8,
+}
+
+impl SynEngine {
+    /// Opens the raw sockets and starts the receive tasks,
+    /// must be called inside of a runtime.
+    /// IPv6 is skipped quietly if the host doesn't have it
+    pub fn new(wait: Duration, attempts: u8) -> Result<Self, Error> {
+        let source_port = 40000 + (rand_u64() % 20000) as u16;
+        let secret = rand_u64();
+        let pending: Pending = Arc::new(Mutex::new(HashMap::new()));
+
+        let v4 = Arc::new(open_socket(Domain::IPV4)?);
+        let v6 = open_socket(Domain::IPV6).ok().map(Arc::new);
+
+        let mut receivers = vec![
+            tokio::spawn(receive(v4.clone(), false, source_port, secret, pending.clone()))
+        ];
+
+        if let Some(v6) = &v6 {
+            receivers.push(tokio::spawn(receive(v6.clone(), true, source_port, secret, pending.clone())));
+        }
+
+        Ok(Self {
+            v4: Some(v4),
+            v6,
+            source_port,
+            secret,
+            pending,
+            receivers,
+            wait,
+            attempts,
+        })
+    }
+
+    /// Upper bound on a probe, every SYN waited on,
+    /// with one more wait to spare for sending them
+    pub fn budget(&self) -> Duration {
+        self.wait * self.attempts as u32 + self.wait
+    }
+
+    /// Sends up to `attempts` SYNs to `addr`, and waits on an answer for each
+    pub async fn probe(&self, addr: SocketAddr) -> Result<State, Error> {
+        let socket = match addr {
+            SocketAddr::V4(_) => self.v4.as_ref(),
+            SocketAddr::V6(_) => self.v6.as_ref(),
+        }
+        .ok_or_else(|| std::io::Error::new(
+            std::io::ErrorKind::AddrNotAvailable,
+            format!("no raw socket for {}", addr)
+        ))?;
+
+        let source = SocketAddr::new(source_ip(addr)?, self.source_port);
+        let segment = packet::syn(source, addr, cookie(self.secret, addr, self.source_port));
+
+        for _ in 0..self.attempts {
+            let (tx, rx) = oneshot::channel();
+            self.pending.lock().unwrap().insert(addr, tx);
+
+            // port is ignored on raw sockets
+            socket.send_to(&segment, SocketAddr::new(addr.ip(), 0)).await?;
+
+            if let Ok(Ok(state)) = timeout(self.wait, rx).await {
+                return Ok(state)
+            }
+        }
+
+        self.pending.lock().unwrap().remove(&addr);
+        Ok(State::Filtered)
+    }
+}
+
+impl Drop for SynEngine {
+    fn drop(&mut self) {
+        for receiver in &self.receivers {
+            receiver.abort();
+        }
+    }
+}
+
+fn open_socket(domain: Domain) -> Result<UdpSocket, Error> {
+    let socket = Socket::new(domain, Type::RAW, Some(Protocol::TCP))?;
+    socket.set_nonblocking(true)?;
+
+    // tokio's udp socket only needs a datagram oriented file descriptor
+    Ok(UdpSocket::from_std(socket.into())?)
+}
+
+/// Reads every tcp segment the socket sees,
+/// answering the probes waiting on them
+async fn receive(socket: Arc<UdpSocket>, ipv6: bool, source_port: u16, secret: u64, pending: Pending) {
+    let mut buf = [0u8; 1500];
+    let mut backoff = Duration::from_millis(1);
+
+    loop {
+        let (n, peer) = match socket.recv_from(&mut buf).await {
+            Ok(x) => x,
+            // whatever broke may not go away, probes waiting meanwhile are filtered
+            Err(_) => {
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+                continue
+            }
+        };
+        backoff = Duration::from_millis(1);
+
+        let segment = if ipv6 { Some(&buf[..n]) } else { packet::strip_ipv4_header(&buf[..n]) }
+            .and_then(packet::parse);
+
+        let segment = match segment {
+            Some(segment) if segment.destination_port == source_port => segment,
+            _ => continue
+        };
+
+        let remote = SocketAddr::new(peer.ip(), segment.source_port);
+        if segment.ack != cookie(secret, remote, source_port).wrapping_add(1) {
+            continue
+        }
+
+        let state = if segment.flags & packet::RST != 0 {
+            State::Closed
+        }
+        else if segment.flags & (packet::SYN | packet::ACK) == packet::SYN | packet::ACK {
+            State::Open
+        }
+        else {
+            continue
+        };
+
+        if let Some(waiting) = pending.lock().unwrap().remove(&remote) {
+            let _ = waiting.send(state);
+        }
+    }
+}
+
+/// Sequence number of the SYN sent to `remote`,
+/// the peer acknowledges it + 1 in both SYN-ACKs and RSTs
+fn cookie(secret: u64, remote: SocketAddr, source_port: u16) -> u32 {
+    let mut hasher = DefaultHasher::new();
+    hasher.write_u64(secret);
+    match remote.ip() {
+        IpAddr::V4(ip) => hasher.write(&ip.octets()),
+        IpAddr::V6(ip) => hasher.write(&ip.octets()),
+    }
+    hasher.write_u16(remote.port());
+    hasher.write_u16(source_port);
+    hasher.finish() as u32
+}
+
+/// The local address the kernel routes `remote` through,
+/// needed for the checksum's pseudo header
+fn source_ip(remote: SocketAddr) -> Result<IpAddr, Error> {
+    let bind: SocketAddr = match remote {
+        SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+        SocketAddr::V6(_) => ([0u16; 8], 0).into(),
+    };
+
+    // connecting a udp socket only looks up the route, nothing is sent
+    let socket = std::net::UdpSocket::bind(bind)?;
+    socket.connect(remote)?;
+    Ok(socket.local_addr()?.ip())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::{
+        runtime::Runtime,
+        net::TcpListener,
+    };
+
+    fn engine() -> Option<SynEngine> {
+        match SynEngine::new(Duration::from_millis(300), ATTEMPTS) {
+            Ok(engine) => Some(engine),
+            Err(Error::IO(e)) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+                eprintln!("skipping, raw sockets need CAP_NET_RAW");
+                None
+            }
+            Err(e) => panic!("{:?}", e)
+        }
+    }
+
+    #[test]
+    fn checksum_verifies() {
+        let source: SocketAddr = "10.0.0.1:40000".parse().unwrap();
+        let destination: SocketAddr = "10.0.0.2:80".parse().unwrap();
+
+        let segment = packet::syn(source, destination, 0xdeadbeef);
+        assert_eq!(packet::checksum(source.ip(), destination.ip(), &segment), 0);
+
+        let parsed = packet::parse(&segment).unwrap();
+        assert_eq!(parsed.seq, 0xdeadbeef);
+        assert_eq!(parsed.flags, packet::SYN);
+        assert_eq!((parsed.source_port, parsed.destination_port), (40000, 80));
+    }
+
+    #[test]
+    fn syn_loopback() {
+        let rt = Runtime::new().unwrap();
+
+        rt.block_on(async move {
+            let engine = match engine() {
+                Some(engine) => engine,
+                None => return
+            };
+
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let open = listener.local_addr().unwrap();
+
+            // bind and drop to find a port nobody listens on
+            let closed = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+
+            assert_eq!(engine.probe(open).await.unwrap(), State::Open);
+            assert_eq!(engine.probe(closed).await.unwrap(), State::Closed);
+            drop(listener);
+        });
+    }
+
+    #[test]
+    fn syn_loopback_v6() {
+        let rt = Runtime::new().unwrap();
+
+        rt.block_on(async move {
+            let engine = match engine() {
+                Some(engine) => engine,
+                None => return
+            };
+
+            let listener = match TcpListener::bind("[::1]:0").await {
+                Ok(listener) => listener,
+                // no ipv6 on loopback
+                Err(_) => return
+            };
+            let open = listener.local_addr().unwrap();
+
+            assert_eq!(engine.probe(open).await.unwrap(), State::Open);
+        });
+    }
+}
@@ -0,0 +1,113 @@
+use std::net::{IpAddr, SocketAddr};
+
+pub const SYN: u8 = 0x02;
+pub const RST: u8 = 0x04;
+pub const ACK: u8 = 0x10;
+
+const IPPROTO_TCP: u8 = 6;
+const WINDOW: u16 = 1024;
+/// mss of 1460
+const OPTIONS: [u8; 4] = [2, 4, 0x05, 0xb4];
+const HEADER_LEN: usize = 20 + OPTIONS.len();
+
+/// The fields of a tcp header we care about
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Segment {
+    pub source_port: u16,
+    pub destination_port: u16,
+    pub seq: u32,
+    pub ack: u32,
+    pub flags: u8,
+}
+
+/*
++-----------------------------------+-----------------------------------+
+|            SOURCE PORT            |         DESTINATION PORT          |
++-----------------------------------+-----------------------------------+
+|                            SEQUENCE NUMBER                            |
++-----------------------------------------------------------------------+
+|                         ACKNOWLEDGMENT NUMBER                         |
++--------+--------+-----------------+-----------------------------------+
+| OFFSET |  RSVD  |      FLAGS      |              WINDOW               |
++--------+--------+-----------------+-----------------------------------+
+|             CHECKSUM              |          URGENT POINTER           |
++-----------------------------------+-----------------------------------+
+|                               OPTIONS                                 |
++-----------------------------------------------------------------------+*/
+/// A SYN segment from `source` to `destination`, without an ip header.
+/// The kernel adds the ip header on raw tcp sockets, but the checksum is ours to fill in
+pub fn syn(source: SocketAddr, destination: SocketAddr, seq: u32) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(HEADER_LEN);
+
+    packet.extend_from_slice(&source.port().to_be_bytes());
+    packet.extend_from_slice(&destination.port().to_be_bytes());
+    packet.extend_from_slice(&seq.to_be_bytes());
+    packet.extend_from_slice(&0u32.to_be_bytes());
+    packet.push(((HEADER_LEN / 4) as u8) << 4);
+    packet.push(SYN);
+    packet.extend_from_slice(&WINDOW.to_be_bytes());
+    packet.extend_from_slice(&[0, 0, 0, 0]);
+    packet.extend_from_slice(&OPTIONS);
+
+    let checksum = checksum(source.ip(), destination.ip(), &packet);
+    packet[16..18].copy_from_slice(&checksum.to_be_bytes());
+    packet
+}
+
+/// Parses the tcp header at the start of `buf`
+pub fn parse(buf: &[u8]) -> Option<Segment> {
+    if buf.len() < 20 {
+        return None
+    }
+
+    Some(Segment {
+        source_port: u16::from_be_bytes([buf[0], buf[1]]),
+        destination_port: u16::from_be_bytes([buf[2], buf[3]]),
+        seq: u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]),
+        ack: u32::from_be_bytes([buf[8], buf[9], buf[10], buf[11]]),
+        flags: buf[13],
+    })
+}
+
+/// Skips the ip header raw ipv4 sockets hand over,
+/// raw ipv6 sockets never include it
+pub fn strip_ipv4_header(buf: &[u8]) -> Option<&[u8]> {
+    let header_len = ((*buf.first()? & 0x0F) as usize) * 4;
+    buf.get(header_len..)
+}
+
+/// Internet checksum over the tcp segment and its pseudo header
+pub fn checksum(source: IpAddr, destination: IpAddr, segment: &[u8]) -> u16 {
+    let mut pseudo = Vec::with_capacity(40);
+
+    match (source, destination) {
+        (IpAddr::V4(src), IpAddr::V4(dst)) => {
+            pseudo.extend_from_slice(&src.octets());
+            pseudo.extend_from_slice(&dst.octets());
+            pseudo.extend_from_slice(&[0, IPPROTO_TCP]);
+            pseudo.extend_from_slice(&(segment.len() as u16).to_be_bytes());
+        }
+        (IpAddr::V6(src), IpAddr::V6(dst)) => {
+            pseudo.extend_from_slice(&src.octets());
+            pseudo.extend_from_slice(&dst.octets());
+            pseudo.extend_from_slice(&(segment.len() as u32).to_be_bytes());
+            pseudo.extend_from_slice(&[0, 0, 0, IPPROTO_TCP]);
+        }
+        _ => unreachable!("source and destination are always the same family")
+    }
+
+    let mut sum: u32 = pseudo.chunks(2)
+        .chain(segment.chunks(2))
+        .map(|pair| match pair {
+            [hi, lo] => u16::from_be_bytes([*hi, *lo]) as u32,
+            [hi] => u16::from_be_bytes([*hi, 0]) as u32,
+            _ => 0
+        })
+        .sum();
+
+    while sum >> 16 > 0 {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+
+    !(sum as u16)
+}
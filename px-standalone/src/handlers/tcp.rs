@@ -2,28 +2,55 @@ use px_core::{
     pool::{JobCtrl, CRON, JobErr},
     error::Error,
     model::State,
+    wrapper::pivot,
 };
+use serde::Serialize;
+use tokio::{
+    io::AsyncReadExt,
+    time::timeout_at,
+};
+
+use px_nmap::service_probe::ServiceMatch;
+
+use std::{
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+use super::{Job, handle_io_error};
+use crate::cli::output::{Report, ServiceInfo};
 
-use tokio::net::TcpStream;
-use std::net::SocketAddr;
-use super::handle_io_error;
+/// `tcpwrappedms` of nmap's NULL probe,
+/// for when the probe database can't be read
+pub const WRAPPED_WAIT: Duration = Duration::from_millis(3000);
 
 
+#[derive(Debug)]
+pub struct ConnectConfig {
+    pub timeout: Duration,
+}
+
+/// Completes a connection, and lets go of it.
+/// Through pivots, the last one's answer about the target is its state, closed or filtered.
+/// Nothing is waited for once connected, so tcpwrapped ports are open like any other,
+/// `TcpHold` tells them apart
 #[derive(Debug)]
 pub struct TcpProbe;
 
 #[async_trait::async_trait]
 impl CRON for TcpProbe
 {
-    type State = SocketAddr;
+    type State = Job<ConnectConfig>;
     type Response = SocketAddr;
 
-    async fn exec(state: &mut SocketAddr) -> Result<JobCtrl<Self::Response>, Error>
+    async fn exec(state: &mut Job<ConnectConfig>) -> Result<JobCtrl<Self::Response>, Error>
     {
-        match scan(*state).await {
-            Ok(_) => Ok(JobCtrl::Return(State::Open, *state)),
+        match scan(state.addr, state.config.timeout).await {
+            Ok(_) => Ok(JobCtrl::Return(State::Open, state.addr)),
 
-            Err(Error::IO(err)) => Ok(JobCtrl::Error(handle_io_error(err))),
+            Err(Error::IO(err)) => match pivot::reply_state(&err) {
+                Some(net_state) => Ok(JobCtrl::Return(net_state, state.addr)),
+                None => Ok(JobCtrl::Error(handle_io_error(err)))
+            },
             Err(e) => {
                 eprintln!("unmatched error {:#?} [not io error]", e);
                 Ok(JobCtrl::Error(JobErr::Other))
@@ -32,19 +59,146 @@ impl CRON for TcpProbe
     }
 }
 
-async fn scan(addr: SocketAddr) -> Result<(), Error> {
-    TcpStream::connect(addr).await?;
+async fn scan(addr: SocketAddr, timeout: Duration) -> Result<(), Error> {
+    pivot::connect(addr, timeout).await?;
     Ok(())
 }
 
+/// How an open connection went while we held it, without sending anything
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Hold {
+    /// bytes the server sent on its own
+    pub received: usize,
+    /// the server closed (or reset) the connection before we let go
+    pub closed: bool,
+    /// milliseconds between connecting and the server closing, or us giving up
+    pub open_ms: u128,
+    /// closed without a word within `HoldConfig::wrapped_wait`
+    pub wrapped: bool,
+}
+
+impl Hold {
+    pub fn sent_data(&self) -> bool {
+        self.received > 0
+    }
+
+    /// Closed by the server, silently and quickly, like tcp wrappers turning the client away
+    pub fn is_wrapped(&self) -> bool {
+        self.wrapped
+    }
+}
+
+impl Report for Hold {
+    fn service(&self) -> Option<ServiceInfo> {
+        if !self.is_wrapped() {
+            return None
+        }
+
+        Some(ServiceInfo {
+            name: ServiceMatch::TCPWRAPPED.to_string(),
+            product: None,
+            version: None,
+            cpe: Vec::new(),
+        })
+    }
+
+    fn details(&self) -> Option<String> {
+        let data = match self.received {
+            0 => "no data".to_string(),
+            n => format!("sent {} bytes", n),
+        };
+        let end = if self.closed { "closed after" } else { "held" };
+        Some(format!("{}, {} {}ms", data, end, self.open_ms))
+    }
+
+    fn data(&self) -> Option<serde_json::Value> {
+        serde_json::to_value(self).ok()
+    }
+}
+
+#[derive(Debug)]
+pub struct HoldConfig {
+    /// how long connecting may take
+    pub connect: Duration,
+    /// how long to keep the connection before closing it ourselves
+    pub hold: Duration,
+    /// servers closing the connection without a word sooner than this are tcpwrapped,
+    /// the NULL probe's `tcpwrappedms`
+    pub wrapped_wait: Duration,
+}
+
+impl HoldConfig {
+    /// Longest a hold can take, connecting, then holding on,
+    /// with another connect to spare so the pool never races the hold's own deadline
+    pub fn budget(&self) -> Duration {
+        self.connect * 2 + self.hold
+    }
+}
+
+/// Completes a connection and keeps it open until the peer closes it,
+/// or `hold` runs out, recording what the server did meanwhile.
+/// Ports a pivot couldn't connect to are closed or filtered, without a response
+#[derive(Debug)]
+pub struct TcpHold;
+
+#[async_trait::async_trait]
+impl CRON for TcpHold {
+    type State = Job<HoldConfig>;
+    type Response = Option<Hold>;
+
+    async fn exec(state: &mut Job<HoldConfig>) -> Result<JobCtrl<Self::Response>, Error> {
+        match hold(state.addr, &state.config).await {
+            Ok(held) => Ok(JobCtrl::Return(State::Open, Some(held))),
+
+            Err(Error::IO(err)) => match pivot::reply_state(&err) {
+                Some(net_state) => Ok(JobCtrl::Return(net_state, None)),
+                None => Ok(JobCtrl::Error(handle_io_error(err)))
+            },
+            Err(e) => {
+                eprintln!("unmatched error {:#?} [not io error]", e);
+                Ok(JobCtrl::Error(JobErr::Other))
+            }
+        }
+    }
+}
+
+async fn hold(addr: SocketAddr, config: &HoldConfig) -> Result<Hold, Error> {
+    let mut stream = pivot::connect(addr, config.connect).await?;
+    let start = Instant::now();
+    let deadline = start + config.hold;
+
+    let mut buf = [0; 4096];
+    let mut received = 0;
+    let closed = loop {
+        match timeout_at(deadline.into(), stream.read(&mut buf)).await {
+            Ok(Ok(0)) => break true,
+            Ok(Ok(n)) => received += n,
+            // a reset still ends the connection on the server's side
+            Ok(Err(_)) => break true,
+            Err(_) => break false,
+        }
+    };
+
+    let open = start.elapsed();
+    Ok(Hold {
+        received,
+        closed,
+        open_ms: open.as_millis(),
+        wrapped: closed && received == 0 && open < config.wrapped_wait,
+    })
+}
+
 
 #[cfg(test)]
 mod test {
     // extern crate test;
     
-    // use super::*;
-    // use tokio::runtime::Runtime;
-    // use tokio::net::TcpListener;
+    use super::*;
+    use tokio::{
+        runtime::Runtime,
+        net::TcpListener,
+        io::AsyncWriteExt,
+    };
 
     // #[bench]
     // /// Test how fast tokio's connect is.
@@ -62,4 +216,84 @@ mod test {
         
     //     b.iter(|| rt.block_on(TcpStream::connect(addr)));
     // }
-}
\ No newline at end of file
+
+    fn config(hold: Duration) -> HoldConfig {
+        HoldConfig { connect: Duration::from_secs(5), hold, wrapped_wait: WRAPPED_WAIT }
+    }
+
+    #[test]
+    fn holds_until_closed() {
+        Runtime::new().unwrap().block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            tokio::spawn(async move {
+                let (mut con, _) = listener.accept().await.unwrap();
+                con.write_all(b"220 hello\r\n").await.unwrap();
+            });
+
+            let held = hold(addr, &config(Duration::from_secs(5))).await.unwrap();
+            assert!(held.sent_data());
+            assert_eq!(held.received, 11);
+            assert!(held.closed);
+            assert!(held.open_ms < 5000);
+            assert!(!held.is_wrapped());
+        });
+    }
+
+    #[test]
+    fn silent_close_is_tcpwrapped() {
+        Runtime::new().unwrap().block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            tokio::spawn(async move {
+                let (con, _) = listener.accept().await.unwrap();
+                drop(con);
+            });
+
+            let held = hold(addr, &config(Duration::from_secs(5))).await.unwrap();
+            assert!(held.closed);
+            assert!(held.is_wrapped());
+            assert_eq!(held.service().unwrap().name, "tcpwrapped");
+        });
+    }
+
+    #[test]
+    fn silent_close_after_wrapped_wait() {
+        Runtime::new().unwrap().block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            tokio::spawn(async move {
+                let (con, _) = listener.accept().await.unwrap();
+                tokio::time::sleep(Duration::from_millis(300)).await;
+                drop(con);
+            });
+
+            let config = HoldConfig {
+                connect: Duration::from_secs(5),
+                hold: Duration::from_secs(5),
+                wrapped_wait: Duration::from_millis(100),
+            };
+            let held = hold(addr, &config).await.unwrap();
+            assert!(held.closed);
+            assert!(!held.is_wrapped());
+        });
+    }
+
+    #[test]
+    fn lets_go_of_silent_peers() {
+        Runtime::new().unwrap().block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            tokio::spawn(async move {
+                let (_con, _) = listener.accept().await.unwrap();
+                tokio::time::sleep(Duration::from_secs(5)).await;
+            });
+
+            let held = hold(addr, &config(Duration::from_millis(300))).await.unwrap();
+            assert!(!held.sent_data());
+            assert!(!held.closed);
+            assert!(held.open_ms >= 300);
+            assert_eq!(held.details().unwrap(), format!("no data, held {}ms", held.open_ms));
+        });
+    }
+}
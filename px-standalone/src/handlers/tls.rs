@@ -0,0 +1,662 @@
+use px_core::{
+    pool::{JobCtrl, CRON, JobErr},
+    error::Error,
+    model::State,
+    wrapper::{NetworkInterface, TcpInterface, TlsInterface, insecure_config},
+};
+use tokio_rustls::rustls::{ClientConfig, ProtocolVersion, Session};
+use x509_parser::{
+    parse_x509_certificate,
+    certificate::X509Certificate,
+    extensions::GeneralName,
+    objects::{oid2sn, oid_registry},
+    public_key::PublicKey,
+    time::ASN1Time,
+};
+use serde::Serialize;
+
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    time::Duration,
+};
+use super::{Job, buffered::Buffered};
+use crate::cli::output::{Report, ServiceInfo};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub enum TlsVersion {
+    #[serde(rename = "TLSv1.0")]
+    Tls10,
+    #[serde(rename = "TLSv1.1")]
+    Tls11,
+    #[serde(rename = "TLSv1.2")]
+    Tls12,
+    #[serde(rename = "TLSv1.3")]
+    Tls13,
+}
+
+impl TlsVersion {
+    pub const ALL: [TlsVersion; 4] = [TlsVersion::Tls10, TlsVersion::Tls11, TlsVersion::Tls12, TlsVersion::Tls13];
+
+    /// on the wire, in the record layer and hellos
+    fn wire(self) -> [u8; 2] {
+        match self {
+            TlsVersion::Tls10 => [3, 1],
+            TlsVersion::Tls11 => [3, 2],
+            TlsVersion::Tls12 => [3, 3],
+            TlsVersion::Tls13 => [3, 4],
+        }
+    }
+
+    fn from_rustls(version: ProtocolVersion) -> Option<Self> {
+        match version {
+            ProtocolVersion::TLSv1_0 => Some(TlsVersion::Tls10),
+            ProtocolVersion::TLSv1_1 => Some(TlsVersion::Tls11),
+            ProtocolVersion::TLSv1_2 => Some(TlsVersion::Tls12),
+            ProtocolVersion::TLSv1_3 => Some(TlsVersion::Tls13),
+            _ => None
+        }
+    }
+}
+
+impl std::fmt::Display for TlsVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            TlsVersion::Tls10 => "TLSv1.0",
+            TlsVersion::Tls11 => "TLSv1.1",
+            TlsVersion::Tls12 => "TLSv1.2",
+            TlsVersion::Tls13 => "TLSv1.3",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CertificateInfo {
+    pub subject: String,
+    pub issuer: String,
+    /// dns names and ip addresses it's issued to
+    pub sans: Vec<String>,
+    pub serial: String,
+    pub not_before: String,
+    pub not_after: String,
+    /// "RSA", "EC", "ED25519" ...
+    pub key_type: String,
+    pub key_bits: usize,
+    /// sha256 of the DER encoding
+    pub sha256: String,
+    pub expired: bool,
+    /// issued by itself, and signed with its own key
+    pub self_signed: bool,
+}
+
+impl CertificateInfo {
+    pub fn parse(der: &[u8]) -> Option<Self> {
+        let (_, cert) = parse_x509_certificate(der).ok()?;
+        let validity = cert.validity();
+
+        let (key_type, key_bits) = key(&cert);
+
+        Some(Self {
+            subject: cert.subject().to_string(),
+            issuer: cert.issuer().to_string(),
+            sans: sans(&cert),
+            serial: cert.raw_serial_as_string(),
+            not_before: validity.not_before.to_rfc2822(),
+            not_after: validity.not_after.to_rfc2822(),
+            key_type,
+            key_bits,
+            sha256: fingerprint(der),
+            expired: validity.not_after.timestamp() < ASN1Time::now().timestamp(),
+            self_signed: cert.subject() == cert.issuer() && cert.verify_signature(None).is_ok(),
+        })
+    }
+}
+
+fn sans(cert: &X509Certificate<'_>) -> Vec<String> {
+    let names = match cert.subject_alternative_name() {
+        Ok(Some(ext)) => &ext.value.general_names,
+        _ => return Vec::new()
+    };
+
+    names.iter()
+        .filter_map(|name| match name {
+            GeneralName::DNSName(name) => Some(name.to_string()),
+            GeneralName::IPAddress([a, b, c, d]) => Some(std::net::Ipv4Addr::new(*a, *b, *c, *d).to_string()),
+            GeneralName::IPAddress(ip) if ip.len() == 16 => {
+                let mut octets = [0; 16];
+                octets.copy_from_slice(ip);
+                Some(std::net::Ipv6Addr::from(octets).to_string())
+            }
+            GeneralName::RFC822Name(mail) => Some(mail.to_string()),
+            GeneralName::URI(uri) => Some(uri.to_string()),
+            _ => None
+        })
+        .collect()
+}
+
+fn key(cert: &X509Certificate<'_>) -> (String, usize) {
+    let spki = cert.public_key();
+    let named = || oid2sn(&spki.algorithm.algorithm, oid_registry())
+        .map(str::to_string)
+        .unwrap_or_else(|_| spki.algorithm.algorithm.to_id_string());
+
+    match spki.parsed() {
+        Ok(PublicKey::RSA(key)) => ("RSA".to_string(), key.key_size()),
+        Ok(PublicKey::EC(key)) => ("EC".to_string(), key.key_size()),
+        Ok(PublicKey::DSA(key)) => ("DSA".to_string(), key.len() * 8),
+        // ed25519 and friends, the key is the bit string
+        _ => (named(), spki.subject_public_key.data.len() * 8),
+    }
+}
+
+fn fingerprint(der: &[u8]) -> String {
+    ring::digest::digest(&ring::digest::SHA256, der)
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// What a server's tls looks like
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TlsInventory {
+    /// negotiated when offering everything we support (TLSv1.2, TLSv1.3),
+    /// `None` when the server only speaks older versions
+    pub version: Option<TlsVersion>,
+    pub cipher: Option<String>,
+    /// as presented, the leaf first
+    pub chain: Vec<CertificateInfo>,
+    /// versions the server agreed to when offered only that version
+    pub accepted: Vec<TlsVersion>,
+}
+
+impl TlsInventory {
+    pub fn leaf(&self) -> Option<&CertificateInfo> {
+        self.chain.first()
+    }
+
+    pub fn expired(&self) -> bool {
+        self.leaf().map(|cert| cert.expired).unwrap_or(false)
+    }
+
+    pub fn self_signed(&self) -> bool {
+        self.leaf().map(|cert| cert.self_signed).unwrap_or(false)
+    }
+}
+
+impl Report for TlsInventory {
+    fn service(&self) -> Option<ServiceInfo> {
+        Some(ServiceInfo {
+            name: "tls".to_string(),
+            product: None,
+            version: self.version.map(|x| x.to_string()),
+            cpe: Vec::new(),
+        })
+    }
+
+    fn details(&self) -> Option<String> {
+        let mut details = Vec::new();
+
+        if let Some(cipher) = &self.cipher {
+            details.push(cipher.clone());
+        }
+        if let Some(leaf) = self.leaf() {
+            details.push(format!("subject: {}", leaf.subject));
+            details.push(format!("expires: {}", leaf.not_after));
+        }
+        if self.expired() {
+            details.push("expired".to_string());
+        }
+        if self.self_signed() {
+            details.push("self-signed".to_string());
+        }
+
+        let accepted: Vec<String> = self.accepted.iter().map(ToString::to_string).collect();
+        details.push(format!("accepts: {}", accepted.join(",")));
+
+        Some(details.join("; "))
+    }
+
+    fn data(&self) -> Option<serde_json::Value> {
+        serde_json::to_value(self).ok()
+    }
+}
+
+pub struct TlsScanConfig {
+    pub timeout: Duration,
+    /// offers everything rustls supports
+    pub any: Arc<ClientConfig>,
+    pub tls12: Arc<ClientConfig>,
+    pub tls13: Arc<ClientConfig>,
+}
+
+impl TlsScanConfig {
+    pub fn new(timeout: Duration) -> Self {
+        let only = |version| {
+            let mut config = insecure_config();
+            config.versions = vec![version];
+            Arc::new(config)
+        };
+
+        Self {
+            timeout,
+            any: Arc::new(insecure_config()),
+            tls12: only(ProtocolVersion::TLSv1_2),
+            tls13: only(ProtocolVersion::TLSv1_3),
+        }
+    }
+}
+
+impl std::fmt::Debug for TlsScanConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TlsScanConfig")
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+/// Handshakes with the server to record its certificates,
+/// and then once more for every tls version to see which it accepts.
+///
+/// TLSv1.2 and TLSv1.3 are full handshakes,
+/// rustls doesn't speak anything older, so TLSv1.0 and TLSv1.1
+/// only go as far as the server's reply to a hand-written ClientHello.
+/// Servers that only speak those have their certificates read out of that reply.
+#[derive(Debug)]
+pub struct TlsScanner;
+
+#[async_trait::async_trait]
+impl CRON for TlsScanner {
+    type State = Job<TlsScanConfig>;
+    type Response = Option<TlsInventory>;
+
+    async fn exec(state: &mut Job<TlsScanConfig>) -> Result<JobCtrl<Self::Response>, Error> {
+        match inventory(state.addr, &state.config).await {
+            Ok(found) => Ok(JobCtrl::Return(State::Open, found)),
+
+            Err(Error::IO(err)) => Ok(JobCtrl::Error(super::handle_io_error(err))),
+            Err(e) => {
+                eprintln!("unmatched error {:#?} [not io error]", e);
+                Ok(JobCtrl::Error(JobErr::Other))
+            }
+        }
+    }
+}
+
+/// Errors when the certificate handshake's connection can't be made, every version is still tried
+/// when that handshake fails. `None` when the server didn't accept any version
+pub async fn inventory(addr: SocketAddr, config: &TlsScanConfig) -> Result<Option<TlsInventory>, Error> {
+    let con = TcpInterface::connect(addr, config.timeout).await?;
+
+    let mut found = TlsInventory {
+        version: None,
+        cipher: None,
+        chain: Vec::new(),
+        accepted: Vec::new(),
+    };
+
+    if let Ok(iface) = TlsInterface::handshake(con.into_inner(), addr, None, config.any.clone(), config.timeout).await {
+        let session = iface.session();
+
+        found.version = session.get_protocol_version().and_then(TlsVersion::from_rustls);
+        found.cipher = session.get_negotiated_ciphersuite().map(|x| format!("{:?}", x.suite));
+        found.chain = session.get_peer_certificates()
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|cert| CertificateInfo::parse(&cert.0))
+            .collect();
+    }
+
+    for version in TlsVersion::ALL.iter().copied() {
+        if let Some(chain) = accepts(addr, version, config).await {
+            found.accepted.push(version);
+            // the certificate handshake failed, older versions still present a chain
+            if found.chain.is_empty() {
+                found.chain = chain;
+            }
+        }
+    }
+
+    if found.accepted.is_empty() && found.version.is_none() {
+        return Ok(None)
+    }
+
+    Ok(Some(found))
+}
+
+/// `None` when `version` isn't accepted,
+/// otherwise the chain presented along the way, only read for TLSv1.0 and TLSv1.1
+async fn accepts(addr: SocketAddr, version: TlsVersion, config: &TlsScanConfig) -> Option<Vec<CertificateInfo>> {
+    let rustls = match version {
+        TlsVersion::Tls12 => &config.tls12,
+        TlsVersion::Tls13 => &config.tls13,
+        _ => return legacy_accepts(addr, version, config.timeout).await,
+    };
+
+    TlsInterface::connect_with(addr, None, rustls.clone(), config.timeout).await.ok()?;
+    Some(Vec::new())
+}
+
+/// Most we'll read of the server's first flight
+const MAX_FLIGHT: usize = 64 * 1024;
+
+/// Sends a ClientHello for `version` with widely supported cipher suites,
+/// a ServerHello answering with the same version means it's accepted.
+/// The Certificate message following it is read for the chain, up to ServerHelloDone,
+/// an empty chain when the server sends none, or it can't be read
+async fn legacy_accepts(addr: SocketAddr, version: TlsVersion, timeout: Duration) -> Option<Vec<CertificateInfo>> {
+    let mut iface = TcpInterface::connect(addr, timeout).await.ok()?;
+    iface.write_iface(&client_hello(version)).await.ok()?;
+
+    // record header, handshake header, server_version
+    let mut con = Buffered::new(iface);
+    con.fill(5 + 4 + 2).await?;
+    if server_hello_version(&con.buf) != Some(version.wire()) {
+        return None
+    }
+
+    let chain = flight_certificates(&mut con).await
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|der| CertificateInfo::parse(der))
+        .collect();
+    Some(chain)
+}
+
+/// Reassembles handshake messages out of the server's records,
+/// the certificates of its Certificate message as they're presented
+async fn flight_certificates<I: NetworkInterface>(con: &mut Buffered<I>) -> Option<Vec<Vec<u8>>> {
+    let mut handshake = Vec::new();
+    let mut read = 0;
+
+    while read < MAX_FLIGHT {
+        con.fill(5).await?;
+        // handshake records only, an alert ends the flight
+        if con.buf[0] != 0x16 {
+            return None
+        }
+        let length = u16::from_be_bytes([con.buf[3], con.buf[4]]) as usize;
+        con.fill(5 + length).await?;
+        handshake.extend_from_slice(&con.take(5 + length)[5..]);
+        read += 5 + length;
+
+        while handshake.len() >= 4 {
+            let length = u32::from_be_bytes([0, handshake[1], handshake[2], handshake[3]]) as usize;
+            if handshake.len() < 4 + length {
+                break
+            }
+
+            let message: Vec<u8> = handshake.drain(..4 + length).collect();
+            match message[0] {
+                // Certificate
+                11 => return certificates(&message[4..]),
+                // ServerHelloDone, no certificate with anonymous suites
+                14 => return None,
+                _ => {}
+            }
+        }
+    }
+
+    None
+}
+
+/// The DER certificates of a Certificate message's body, each prefixed by its 24 bit length
+fn certificates(body: &[u8]) -> Option<Vec<Vec<u8>>> {
+    let u24 = |at: usize| -> Option<usize> {
+        let bytes = body.get(at..at + 3)?;
+        Some(u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]) as usize)
+    };
+
+    let end = 3 + u24(0)?;
+    let mut at = 3;
+    let mut chain = Vec::new();
+    while at < end {
+        let length = u24(at)?;
+        chain.push(body.get(at + 3..at + 3 + length)?.to_vec());
+        at += 3 + length;
+    }
+    Some(chain)
+}
+
+/// ECDHE, DHE and plain RSA suites, with AES-CBC and 3DES
+const LEGACY_SUITES: [u16; 12] = [
+    0xc014, 0xc013, 0xc00a, 0xc009,
+    0x0039, 0x0033,
+    0x0035, 0x002f, 0x000a,
+    0xc012, 0x0016, 0x00ff,
+];
+
+fn client_hello(version: TlsVersion) -> Vec<u8> {
+    let mut hello = Vec::new();
+    hello.extend_from_slice(&version.wire());
+    hello.extend_from_slice(&super::rand_u64().to_be_bytes());
+    hello.extend_from_slice(&super::rand_u64().to_be_bytes());
+    hello.extend_from_slice(&super::rand_u64().to_be_bytes());
+    hello.extend_from_slice(&super::rand_u64().to_be_bytes());
+    // no session id
+    hello.push(0);
+
+    hello.extend_from_slice(&((LEGACY_SUITES.len() * 2) as u16).to_be_bytes());
+    for suite in LEGACY_SUITES.iter() {
+        hello.extend_from_slice(&suite.to_be_bytes());
+    }
+    // null compression
+    hello.extend_from_slice(&[1, 0]);
+
+    let extensions: &[u8] = &[
+        // supported_groups: secp256r1, secp384r1, secp521r1
+        0x00, 0x0a, 0x00, 0x08, 0x00, 0x06, 0x00, 0x17, 0x00, 0x18, 0x00, 0x19,
+        // ec_point_formats: uncompressed
+        0x00, 0x0b, 0x00, 0x02, 0x01, 0x00,
+        // renegotiation_info: empty
+        0xff, 0x01, 0x00, 0x01, 0x00,
+    ];
+    hello.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+    hello.extend_from_slice(extensions);
+
+    let mut handshake = vec![1];
+    handshake.extend_from_slice(&(hello.len() as u32).to_be_bytes()[1..]);
+    handshake.extend_from_slice(&hello);
+
+    // record layer stays at TLSv1.0, like every client does
+    let mut record = vec![0x16, 3, 1];
+    record.extend_from_slice(&(handshake.len() as u16).to_be_bytes());
+    record.extend_from_slice(&handshake);
+    record
+}
+
+/// `server_version` of a ServerHello, `None` for alerts and everything else
+fn server_hello_version(record: &[u8]) -> Option<[u8; 2]> {
+    // handshake record, holding a ServerHello
+    if record.len() < 11 || record[0] != 0x16 || record[5] != 0x02 {
+        return None
+    }
+
+    Some([record[9], record[10]])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::{
+        runtime::Runtime,
+        net::TcpListener,
+    };
+    use tokio_rustls::{
+        TlsAcceptor,
+        rustls::{ServerConfig, NoClientAuth, Certificate, PrivateKey},
+    };
+    use rcgen::{CertificateParams, DistinguishedName, DnType, IsCa, BasicConstraints, date_time_ymd};
+
+    fn params(name: &str) -> CertificateParams {
+        let mut params = CertificateParams::new(vec!["localhost".to_string()]);
+        let mut dn = DistinguishedName::new();
+        dn.push(DnType::CommonName, name);
+        params.distinguished_name = dn;
+        params
+    }
+
+    /// serves `chain`, signed by `key`, and only completes the handshake
+    async fn server(chain: Vec<Vec<u8>>, key: Vec<u8>) -> SocketAddr {
+        let mut config = ServerConfig::new(NoClientAuth::new());
+        config.set_single_cert(chain.into_iter().map(Certificate).collect(), PrivateKey(key)).unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            loop {
+                let (con, _) = listener.accept().await.unwrap();
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    let _ = acceptor.accept(con).await;
+                });
+            }
+        });
+
+        addr
+    }
+
+    #[test]
+    fn signed_chain() {
+        let rt = Runtime::new().unwrap();
+
+        rt.block_on(async move {
+            let mut ca = params("px test ca");
+            ca.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+            let ca = rcgen::Certificate::from_params(ca).unwrap();
+            let leaf = rcgen::Certificate::from_params(params("localhost")).unwrap();
+
+            let chain = vec![leaf.serialize_der_with_signer(&ca).unwrap(), ca.serialize_der().unwrap()];
+            let addr = server(chain, leaf.serialize_private_key_der()).await;
+
+            let found = inventory(addr, &TlsScanConfig::new(Duration::from_secs(2))).await.unwrap().unwrap();
+
+            assert_eq!(found.version, Some(TlsVersion::Tls13));
+            assert!(found.cipher.is_some());
+            assert_eq!(found.accepted, vec![TlsVersion::Tls12, TlsVersion::Tls13]);
+
+            assert_eq!(found.chain.len(), 2);
+            let leaf = found.leaf().unwrap();
+            assert_eq!(leaf.subject, "CN=localhost");
+            assert_eq!(leaf.issuer, "CN=px test ca");
+            assert_eq!(leaf.sans, vec!["localhost".to_string()]);
+            assert_eq!((leaf.key_type.as_str(), leaf.key_bits), ("EC", 256));
+            assert_eq!(leaf.sha256.len(), 32 * 3 - 1);
+            assert!(!found.expired());
+            assert!(!found.self_signed());
+            assert!(found.chain[1].self_signed);
+        });
+    }
+
+    #[test]
+    fn expired_self_signed() {
+        let rt = Runtime::new().unwrap();
+
+        rt.block_on(async move {
+            let mut params = params("localhost");
+            params.not_before = date_time_ymd(2000, 1, 1);
+            params.not_after = date_time_ymd(2001, 1, 1);
+            let cert = rcgen::Certificate::from_params(params).unwrap();
+
+            let addr = server(vec![cert.serialize_der().unwrap()], cert.serialize_private_key_der()).await;
+            let found = inventory(addr, &TlsScanConfig::new(Duration::from_secs(2))).await.unwrap().unwrap();
+
+            assert!(found.expired());
+            assert!(found.self_signed());
+            assert!(found.details().unwrap().contains("expired; self-signed"));
+        });
+    }
+
+    /// speaks only TLSv1.0, and splits its Certificate message across two records
+    async fn legacy_server(der: Vec<u8>) -> SocketAddr {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let handshake = |kind: u8, body: &[u8]| {
+            let mut message = vec![kind];
+            message.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+            message.extend_from_slice(body);
+            message
+        };
+        let record = |fragment: &[u8]| {
+            let mut record = vec![0x16, 3, 1];
+            record.extend_from_slice(&(fragment.len() as u16).to_be_bytes());
+            record.extend_from_slice(fragment);
+            record
+        };
+
+        // version, random, no session id, TLS_RSA_WITH_AES_128_CBC_SHA, null compression
+        let mut hello = vec![3, 1];
+        hello.extend_from_slice(&[7; 32]);
+        hello.extend_from_slice(&[0, 0x00, 0x2f, 0]);
+
+        let mut entries = (der.len() as u32).to_be_bytes()[1..].to_vec();
+        entries.extend_from_slice(&der);
+        let mut body = (entries.len() as u32).to_be_bytes()[1..].to_vec();
+        body.extend_from_slice(&entries);
+        let certificate = handshake(11, &body);
+        let (head, tail) = certificate.split_at(certificate.len() / 2);
+
+        let flight = [
+            record(&handshake(2, &hello)),
+            record(head),
+            record(&[tail, &handshake(14, &[])[..]].concat()),
+        ].concat();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            loop {
+                let (mut con, _) = listener.accept().await.unwrap();
+                let flight = flight.clone();
+                tokio::spawn(async move {
+                    if con.read(&mut [0; 1024]).await.unwrap_or(0) > 0 {
+                        let _ = con.write_all(&flight).await;
+                        let _ = con.read(&mut [0; 16]).await;
+                    }
+                });
+            }
+        });
+
+        addr
+    }
+
+    #[test]
+    fn legacy_chain() {
+        let rt = Runtime::new().unwrap();
+
+        rt.block_on(async move {
+            let cert = rcgen::Certificate::from_params(params("localhost")).unwrap();
+            let addr = legacy_server(cert.serialize_der().unwrap()).await;
+
+            let found = inventory(addr, &TlsScanConfig::new(Duration::from_secs(2))).await.unwrap().unwrap();
+
+            assert_eq!(found.version, None);
+            assert_eq!(found.accepted, vec![TlsVersion::Tls10]);
+            assert_eq!(found.chain.len(), 1);
+            assert_eq!(found.leaf().unwrap().subject, "CN=localhost");
+            assert!(found.self_signed());
+        });
+    }
+
+    #[test]
+    fn legacy_hello() {
+        let hello = client_hello(TlsVersion::Tls10);
+        assert_eq!(&hello[..3], &[0x16, 3, 1]);
+        assert_eq!(u16::from_be_bytes([hello[3], hello[4]]) as usize, hello.len() - 5);
+        // ClientHello, client_version
+        assert_eq!(hello[5], 1);
+        assert_eq!(&hello[9..11], &[3, 1]);
+
+        let server_hello = [0x16, 3, 2, 0, 0x4a, 0x02, 0, 0, 0x46, 3, 2];
+        assert_eq!(server_hello_version(&server_hello), Some(TlsVersion::Tls11.wire()));
+
+        // protocol_version alert
+        let alert = [0x15, 3, 1, 0, 2, 2, 70, 0, 0, 0, 0];
+        assert_eq!(server_hello_version(&alert), None);
+    }
+}
@@ -0,0 +1,353 @@
+use px_core::{
+    pool::{JobCtrl, CRON, JobErr},
+    error::Error,
+    model::State,
+    wrapper::{NetworkInterface, UdpInterface, io_state},
+};
+
+use socket2::{Socket, Domain, Type, Protocol};
+use tokio::net::UdpSocket;
+
+use std::{
+    net::SocketAddr,
+    time::Duration,
+};
+
+use super::{handle_io_error, banner, Job};
+use crate::cli::output::Report;
+
+/// Datagrams sent before the port is considered open|filtered,
+/// udp is lossy, one unanswered datagram doesn't say much
+pub const ATTEMPTS: u8 = 2;
+
+const MAX_DATAGRAM: usize = 65535;
+
+#[derive(Debug)]
+pub struct UdpConfig {
+    /// given to each datagram to be answered
+    pub wait: Duration,
+}
+
+impl UdpConfig {
+    /// Upper bound on a probe, every datagram waited on,
+    /// with one more wait to spare for setting up the socket
+    pub fn budget(&self) -> Duration {
+        self.wait * ATTEMPTS as u32 + self.wait
+    }
+}
+
+/// UDP port scan over connected udp sockets.
+///
+/// A protocol specific payload is sent for well known ports, an empty datagram otherwise.
+/// Any answer means open, ICMP port unreachable means closed,
+/// any other ICMP unreachable means filtered, and silence is open|filtered.
+///
+/// On Linux `IP_RECVERR` is enabled so every ICMP error is queued on the socket,
+/// and read back from its error queue, no raw sockets needed.
+#[derive(Debug)]
+pub struct UdpProbe;
+
+#[async_trait::async_trait]
+impl CRON for UdpProbe {
+    type State = Job<UdpConfig>;
+    /// whatever the port answered with
+    type Response = Vec<u8>;
+
+    async fn exec(state: &mut Job<UdpConfig>) -> Result<JobCtrl<Self::Response>, Error> {
+        match probe(state.addr, state.config.wait, ATTEMPTS).await {
+            Ok((netstate, reply)) => Ok(JobCtrl::Return(netstate, reply)),
+
+            Err(Error::IO(err)) => Ok(JobCtrl::Error(handle_io_error(err))),
+            Err(e) => {
+                eprintln!("unmatched error {:#?} [not io error]", e);
+                Ok(JobCtrl::Error(JobErr::Other))
+            }
+        }
+    }
+}
+
+/// the reply to the probe, if the port answered
+impl Report for Vec<u8> {
+    fn details(&self) -> Option<String> {
+        if self.is_empty() { None }
+        else { Some(banner::printable(self)) }
+    }
+}
+
+/// Sends up to `attempts` datagrams to `addr`, waiting `wait` on each for an answer.
+/// Errors are only returned when they don't say anything about the port (no route, out of fds)
+pub async fn probe(addr: SocketAddr, wait: Duration, attempts: u8) -> Result<(State, Vec<u8>), Error> {
+    let socket = open_socket(addr)?;
+    let errors = ErrorQueue::from(&socket);
+
+    let mut iface = UdpInterface::from_socket(
+        UdpSocket::from_std(socket.into())?,
+        addr,
+        wait
+    );
+
+    let payload = payload(addr.port());
+
+    for _ in 0..attempts {
+        let mut buf = Vec::new();
+
+        let result = match iface.write_iface(payload).await {
+            Ok(_) => iface.read_iface(&mut buf, MAX_DATAGRAM).await,
+            Err(e) => Err(e),
+        };
+
+        match result {
+            Ok(_) => return Ok((State::Open, buf)),
+            Err(e) if e.kind() == std::io::ErrorKind::TimedOut => continue,
+            Err(e) => return match errors.icmp_state() {
+                Some(state) => Ok((state, buf)),
+                // without the error queue, refused is the only sure thing
+                None if e.kind() == std::io::ErrorKind::ConnectionRefused => Ok((io_state(&e), buf)),
+                None => Err(e.into())
+            }
+        }
+    }
+
+    Ok((State::OpenFiltered, Vec::new()))
+}
+
+fn open_socket(addr: SocketAddr) -> Result<Socket, std::io::Error> {
+    let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_nonblocking(true)?;
+    set_recverr(&socket, addr)?;
+    socket.connect(&addr.into())?;
+    Ok(socket)
+}
+
+/// Payload sent to `port`, empty when there's nothing better to send
+pub fn payload(port: u16) -> &'static [u8] {
+    match port {
+        53 => DNS_VERSION_BIND,
+        123 => &NTP_CLIENT,
+        137 => NETBIOS_NBSTAT,
+        161 => SNMP_V1_SYSDESCR,
+        1900 => SSDP_MSEARCH,
+        11211 => MEMCACHED_STATS,
+        _ => &[]
+    }
+}
+
+/// TXT CHAOS query for "version.bind"
+const DNS_VERSION_BIND: &[u8] =
+    b"\x13\x37\x01\x00\x00\x01\x00\x00\x00\x00\x00\x00\x07version\x04bind\x00\x00\x10\x00\x03";
+
+/// NTPv4 client request (li = 3, vn = 4, mode = 3)
+const NTP_CLIENT: [u8; 48] = {
+    let mut packet = [0; 48];
+    packet[0] = 0xE3;
+    packet
+};
+
+/// NBSTAT query for "*"
+const NETBIOS_NBSTAT: &[u8] =
+    b"\x80\xf0\x00\x00\x00\x01\x00\x00\x00\x00\x00\x00\x20CKAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA\x00\x00\x21\x00\x01";
+
+/// SNMPv1 get-request for sysDescr.0 with the community "public"
+const SNMP_V1_SYSDESCR: &[u8] = &[
+    0x30, 0x26, 0x02, 0x01, 0x00, 0x04, 0x06, b'p', b'u', b'b', b'l', b'i', b'c',
+    0xa0, 0x19, 0x02, 0x01, 0x01, 0x02, 0x01, 0x00, 0x02, 0x01, 0x00,
+    0x30, 0x0e, 0x30, 0x0c, 0x06, 0x08, 0x2b, 0x06, 0x01, 0x02, 0x01, 0x01, 0x01, 0x00, 0x05, 0x00,
+];
+
+const SSDP_MSEARCH: &[u8] =
+    b"M-SEARCH * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\nMAN: \"ssdp:discover\"\r\nMX: 1\r\nST: ssdp:all\r\n\r\n";
+
+/// "stats" behind memcached's udp frame header
+const MEMCACHED_STATS: &[u8] = b"\x00\x01\x00\x00\x00\x01\x00\x00stats\r\n";
+
+/// What an ICMP error says about the port it came back for
+fn icmp_verdict(ipv6: bool, icmp_type: u8, icmp_code: u8) -> State {
+    const DEST_UNREACH: u8 = 3;
+    const PORT_UNREACH: u8 = 3;
+
+    const ICMPV6_DEST_UNREACH: u8 = 1;
+    const ICMPV6_PORT_UNREACH: u8 = 4;
+
+    match (ipv6, icmp_type, icmp_code) {
+        (false, DEST_UNREACH, PORT_UNREACH) => State::Closed,
+        (true, ICMPV6_DEST_UNREACH, ICMPV6_PORT_UNREACH) => State::Closed,
+        // host/net unreachable, administratively prohibited, ...
+        _ => State::Filtered
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn set_recverr(socket: &Socket, addr: SocketAddr) -> Result<(), std::io::Error> {
+    use std::os::unix::io::AsRawFd;
+
+    let (level, name) = match addr {
+        SocketAddr::V4(_) => (libc::SOL_IP, libc::IP_RECVERR),
+        SocketAddr::V6(_) => (libc::SOL_IPV6, libc::IPV6_RECVERR),
+    };
+
+    let enable: libc::c_int = 1;
+    let ret = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            level,
+            name,
+            &enable as *const _ as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t
+        )
+    };
+
+    if ret < 0 {
+        return Err(std::io::Error::last_os_error())
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_recverr(_socket: &Socket, _addr: SocketAddr) -> Result<(), std::io::Error> {
+    Ok(())
+}
+
+/// Reads ICMP errors queued on a socket with `IP_RECVERR`.
+/// Only holds the descriptor, the socket must outlive it
+struct ErrorQueue {
+    #[cfg(target_os = "linux")]
+    fd: std::os::unix::io::RawFd,
+}
+
+impl From<&Socket> for ErrorQueue {
+    #[cfg(target_os = "linux")]
+    fn from(socket: &Socket) -> Self {
+        use std::os::unix::io::AsRawFd;
+        Self { fd: socket.as_raw_fd() }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn from(_socket: &Socket) -> Self {
+        Self {}
+    }
+}
+
+impl ErrorQueue {
+    /// Pops the oldest error off the queue,
+    /// and returns what it says about the port if it was an ICMP error
+    #[cfg(target_os = "linux")]
+    fn icmp_state(&self) -> Option<State> {
+        let mut data = [0u8; 512];
+        let mut control = [0u8; 512];
+
+        let mut iov = libc::iovec {
+            iov_base: data.as_mut_ptr() as *mut libc::c_void,
+            iov_len: data.len(),
+        };
+
+        let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = control.len() as _;
+
+        if unsafe { libc::recvmsg(self.fd, &mut msg, libc::MSG_ERRQUEUE | libc::MSG_DONTWAIT) } < 0 {
+            return None
+        }
+
+        let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(&msg) };
+        while !cmsg.is_null() {
+            let header = unsafe { &*cmsg };
+
+            let queued = (header.cmsg_level == libc::SOL_IP && header.cmsg_type == libc::IP_RECVERR)
+                || (header.cmsg_level == libc::SOL_IPV6 && header.cmsg_type == libc::IPV6_RECVERR);
+
+            if queued {
+                let err = unsafe {
+                    std::ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::sock_extended_err)
+                };
+
+                return match err.ee_origin {
+                    libc::SO_EE_ORIGIN_ICMP => Some(icmp_verdict(false, err.ee_type, err.ee_code)),
+                    libc::SO_EE_ORIGIN_ICMP6 => Some(icmp_verdict(true, err.ee_type, err.ee_code)),
+                    // raised locally, not by the network
+                    _ => None
+                }
+            }
+
+            cmsg = unsafe { libc::CMSG_NXTHDR(&msg, cmsg) };
+        }
+
+        None
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn icmp_state(&self) -> Option<State> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::runtime::Runtime;
+
+    const TEST_WAIT: Duration = Duration::from_millis(200);
+
+    #[test]
+    fn answered_is_open() {
+        let rt = Runtime::new().unwrap();
+
+        rt.block_on(async move {
+            let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let addr = server.local_addr().unwrap();
+
+            tokio::spawn(async move {
+                let mut buf = [0; 512];
+                let (_, peer) = server.recv_from(&mut buf).await.unwrap();
+                server.send_to(b"hello", peer).await.unwrap();
+            });
+
+            let (state, reply) = probe(addr, TEST_WAIT, ATTEMPTS).await.unwrap();
+            assert_eq!(state, State::Open);
+            assert_eq!(reply, b"hello");
+        });
+    }
+
+    #[test]
+    fn port_unreachable_is_closed() {
+        let rt = Runtime::new().unwrap();
+
+        rt.block_on(async move {
+            for bind in &["127.0.0.1:0", "[::1]:0"] {
+                // bind and drop to find a port nobody listens on
+                let addr = match UdpSocket::bind(bind).await {
+                    Ok(socket) => socket.local_addr().unwrap(),
+                    // no ipv6 on loopback
+                    Err(_) => continue
+                };
+
+                let (state, _) = probe(addr, TEST_WAIT, ATTEMPTS).await.unwrap();
+                assert_eq!(state, State::Closed, "{}", addr);
+            }
+        });
+    }
+
+    #[test]
+    fn silence_is_open_filtered() {
+        let rt = Runtime::new().unwrap();
+
+        rt.block_on(async move {
+            let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            let addr = server.local_addr().unwrap();
+
+            let (state, reply) = probe(addr, TEST_WAIT, ATTEMPTS).await.unwrap();
+            assert_eq!(state, State::OpenFiltered);
+            assert!(reply.is_empty());
+            drop(server);
+        });
+    }
+
+    #[test]
+    fn icmp_verdicts() {
+        assert_eq!(icmp_verdict(false, 3, 3), State::Closed);
+        assert_eq!(icmp_verdict(false, 3, 13), State::Filtered);
+        assert_eq!(icmp_verdict(true, 1, 4), State::Closed);
+        assert_eq!(icmp_verdict(true, 1, 1), State::Filtered);
+    }
+}
@@ -0,0 +1,270 @@
+use px_core::{
+    pool::{JobCtrl, CRON, JobErr},
+    error::Error,
+    model::State,
+    wrapper::{NetworkInterface, TcpInterface},
+};
+use px_nmap::service_probe::{
+    ChainedProbes, Link, ServiceMatch,
+    parser::model::Protocol,
+};
+
+use std::{
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+use tokio::time::timeout;
+
+use super::Job;
+use crate::cli::output::{Report, ServiceInfo};
+
+/// Most we'll read in response to a single probe
+const MAX_RESPONSE: usize = 64 * 1024;
+
+#[derive(Debug)]
+pub struct VScanConfig {
+    pub probes: ChainedProbes,
+    /// probes rarer than this are only sent to the ports they're registered to
+    pub intensity: u8,
+    /// time given to every connection to complete
+    pub connect_timeout: Duration,
+}
+
+impl VScanConfig {
+    /// Upper bound on the time spent on `port`, the NULL probe and every probe selected for it,
+    /// each with a connection, and a wait for its response
+    pub fn budget(&self, port: u16) -> Duration {
+        if self.probes.is_excluded(port) {
+            return self.connect_timeout
+        }
+
+        self.probes.select(Protocol::TCP, port, self.intensity).iter()
+            // connecting, then sending the payload
+            .map(|link| link.wait() + self.connect_timeout * 2)
+            .sum::<Duration>()
+            + self.probes.null().wait() + self.connect_timeout
+    }
+}
+
+/// Version scan driven by nmap's service probes.
+///
+/// The NULL probe (just listening) goes first,
+/// then every probe `ChainedProbes::select` picks for the port, each on a fresh connection,
+/// until one of the responses is matched.
+/// Servers closing the connection during the NULL probe's `tcpwrappedms`, without sending anything,
+/// are reported as tcpwrapped.
+/// Ports nothing matched on are still open, with no service.
+#[derive(Debug)]
+pub struct VScan;
+
+#[async_trait::async_trait]
+impl CRON for VScan {
+    type State = Job<VScanConfig>;
+    type Response = Option<ServiceMatch>;
+
+    async fn exec(state: &mut Job<VScanConfig>) -> Result<JobCtrl<Self::Response>, Error> {
+        let budget = state.config.budget(state.addr.port());
+        let identified = match timeout(budget, identify(state.addr, &state.config)).await {
+            Ok(identified) => identified,
+            Err(_) => return Ok(JobCtrl::Error(JobErr::IO(std::io::ErrorKind::TimedOut)))
+        };
+
+        match identified {
+            Ok(service) => Ok(JobCtrl::Return(State::Open, service)),
+
+            Err(Error::IO(err)) => Ok(JobCtrl::Error(super::handle_io_error(err))),
+            Err(e) => {
+                eprintln!("unmatched error {:#?} [not io error]", e);
+                Ok(JobCtrl::Error(JobErr::Other))
+            }
+        }
+    }
+}
+
+impl Report for ServiceMatch {
+    fn service(&self) -> Option<ServiceInfo> {
+        Some(ServiceInfo {
+            name: self.name.clone(),
+            product: self.product.clone(),
+            version: self.version.clone(),
+            cpe: self.cpe.clone(),
+        })
+    }
+
+    fn details(&self) -> Option<String> {
+        let details: Vec<String> = [
+            self.info.clone(),
+            self.operating_system.as_ref().map(|x| format!("os: {}", x)),
+            self.hostname.as_ref().map(|x| format!("host: {}", x)),
+            self.device_type.as_ref().map(|x| format!("device: {}", x)),
+        ]
+        .iter()
+        .flatten()
+        .cloned()
+        .collect();
+
+        if details.is_empty() { None }
+        else { Some(details.join("; ")) }
+    }
+}
+
+/// Only the first connection's errors are returned,
+/// they say the port isn't open.
+/// Once it's known to be open, probes that fail to connect are skipped
+pub async fn identify(addr: SocketAddr, config: &VScanConfig) -> Result<Option<ServiceMatch>, Error> {
+    let probes = &config.probes;
+    let mut iface = TcpInterface::connect(addr, config.connect_timeout).await?;
+
+    if probes.is_excluded(addr.port()) {
+        return Ok(None)
+    }
+
+    let mut soft = None;
+    let mut buf = Vec::new();
+    let start = Instant::now();
+    if let Some(found) = exchange(&mut iface, probes.null(), probes, &mut soft, &mut buf).await {
+        return Ok(Some(found))
+    }
+    if is_wrapped(&iface, &buf, start.elapsed(), probes.null()) {
+        return Ok(Some(ServiceMatch::tcpwrapped()))
+    }
+    drop(iface);
+
+    for link in probes.select(Protocol::TCP, addr.port(), config.intensity) {
+        // a softmatch narrowed down the service, only probes that can refine it are worth sending
+        if let Some(service) = &soft {
+            if !link.identifies(&service.name) {
+                continue
+            }
+        }
+
+        let mut iface = match TcpInterface::connect(addr, config.connect_timeout).await {
+            Ok(iface) => iface,
+            Err(_) => continue
+        };
+
+        if iface.write_iface(&link.payload).await.is_err() {
+            continue
+        }
+
+        if let Some(found) = exchange(&mut iface, link, probes, &mut soft, &mut Vec::new()).await {
+            return Ok(Some(found))
+        }
+    }
+
+    Ok(soft)
+}
+
+/// The server closed the connection on its own, before saying anything,
+/// and before `link`'s `tcpwrappedms` ran out
+fn is_wrapped<I: NetworkInterface>(iface: &I, response: &[u8], elapsed: Duration, link: &Link) -> bool {
+    response.is_empty()
+        && matches!(iface.state(), State::Closed)
+        && elapsed < link.wrapped_wait()
+}
+
+/// Reads for up to `link.wait()` into `buf`, matching what's been read so far after every read.
+/// Returns the first hard match, softmatches are kept in `soft`
+async fn exchange<I: NetworkInterface>(iface: &mut I, link: &Link, probes: &ChainedProbes, soft: &mut Option<ServiceMatch>, buf: &mut Vec<u8>) -> Option<ServiceMatch> {
+    let deadline = Instant::now() + link.wait();
+
+    while buf.len() < MAX_RESPONSE {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left == Duration::from_secs(0) {
+            break
+        }
+
+        iface.set_timeout(left);
+        let room = MAX_RESPONSE - buf.len();
+        match iface.read_iface(buf, room).await {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+
+        let service = soft.as_ref().map(|x| x.name.as_str());
+        match probes.identify(link, buf, service) {
+            Some(found) if !found.soft => return Some(found),
+            Some(found) if soft.is_none() => *soft = Some(found),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::{
+        runtime::Runtime,
+        net::TcpListener,
+        io::{AsyncReadExt, AsyncWriteExt},
+    };
+
+    fn database() -> String {
+        format!("{}/../share/nmap/nmap-service-probes", env!("CARGO_MANIFEST_DIR"))
+    }
+
+    #[test]
+    fn identifies_http_after_null_probe() {
+        let rt = Runtime::new().unwrap();
+
+        rt.block_on(async move {
+            let config = VScanConfig {
+                probes: ChainedProbes::from_file(&database()).await.unwrap(),
+                intensity: 1,
+                connect_timeout: Duration::from_secs(1),
+            };
+
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+
+            tokio::spawn(async move {
+                loop {
+                    let (mut con, _) = listener.accept().await.unwrap();
+                    tokio::spawn(async move {
+                        let mut buf = [0; 512];
+                        if let Ok(n) = con.read(&mut buf).await {
+                            if buf[..n].starts_with(b"GET / HTTP/1.0\r\n") {
+                                let _ = con.write_all(b"HTTP/1.0 200 OK\r\nServer: nginx/1.18.0\r\n\r\n").await;
+                            }
+                        }
+                    });
+                }
+            });
+
+            // slow, the NULL probe waits 6s on silent services
+            let found = identify(addr, &config).await.unwrap().unwrap();
+
+            assert_eq!(found.name, "http");
+            assert_eq!(found.product.as_deref(), Some("nginx"));
+            assert_eq!(found.version.as_deref(), Some("1.18.0"));
+        });
+    }
+
+    #[test]
+    fn immediate_close_is_tcpwrapped() {
+        let rt = Runtime::new().unwrap();
+
+        rt.block_on(async move {
+            let config = VScanConfig {
+                probes: ChainedProbes::from_file(&database()).await.unwrap(),
+                intensity: 1,
+                connect_timeout: Duration::from_secs(1),
+            };
+
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+
+            tokio::spawn(async move {
+                loop {
+                    let (con, _) = listener.accept().await.unwrap();
+                    drop(con);
+                }
+            });
+
+            let found = identify(addr, &config).await.unwrap().unwrap();
+            assert_eq!(found.name, "tcpwrapped");
+        });
+    }
+}
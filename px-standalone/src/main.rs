@@ -8,23 +8,62 @@ use structopt::StructOpt;
 use tokio::runtime::Builder;
 
 use std::net::SocketAddr;
+use std::sync::Arc;
 use std::time::Duration;
+use std::collections::HashMap;
 use handlers::{
-	socks5::{ScanResult, Socks5Scanner},
-	tcp::TcpProbe
+	socks5::{ScanResult, Socks5Scanner, SocksConfig},
+	canary::Canary,
+	http_proxy::{HttpProxyScanner, HttpProxyConfig, ProxyReport},
+	http::{HttpScanner, HttpConfig, HttpFingerprint},
+	tls::{TlsScanner, TlsScanConfig, TlsInventory},
+	ssh::{SshScanner, SshConfig, SshInfo},
+	dns::{DnsScanner, DnsConfig, DnsReport},
+	smb::{SmbScanner, SmbConfig, SmbInfo},
+	datastore::{DatastoreScanner, DatastoreConfig, Exposure},
+	ftp::{FtpScanner, FtpConfig, FtpInfo},
+	smtp::{SmtpScanner, SmtpConfig, SmtpInfo},
+	tcp::{self, TcpProbe, ConnectConfig, TcpHold, HoldConfig, Hold},
+	udp::{UdpProbe, UdpConfig},
+	banner::{self, BannerGrab, BannerConfig, Banner},
+	vscan::{VScan, VScanConfig},
+	syn::{self, SynProbe, SynEngine},
+	Job,
+	ping::TcpPing,
+	icmp::{IcmpEcho, IcmpTimestamp, PingReply}
 };
 use cli::{
 	output::OutputType,
+	monitor::ChangePrinter,
+	diff::{ResultsDiff, load_results},
+	discovery::{discover_hosts, DEFAULT_PING_PORTS},
 	input::{
-		parser::{ScanMethod, Format},
+		parser::{ScanMethod, Format, DiscoveryMethod, AddressInput},
 		combine::Feeder,
 	}
 };
+use px_core::model::PortInput;
+use px_core::schedule::Scheduler;
+use px_core::wrapper::pivot::{ProxyChain, route_through};
+use px_nmap::service_probe::{ChainedProbes, ServiceMatch};
 
 fn main() -> Result<(), Error> {
 	//cli::opt::Arguments::clap().gen_completions(env!("CARGO_PKG_NAME"), Shell::Bash, "target");
 	let opt = cli::opt::Arguments::from_args();
 
+	if let Some(files) = &opt.diff {
+		let diff = ResultsDiff::new(&load_results(&files[0])?, &load_results(&files[1])?);
+		match opt.format {
+			Format::Json => println!("{}", serde_json::to_string_pretty(&diff)?),
+			Format::Stdout | Format::Stream => print!("{}", diff),
+		}
+		return Ok(())
+	}
+
+	if !opt.pivot.is_empty() {
+		pivot(&opt)?;
+	}
+
 	let mut runtime = Builder::new_multi_thread()
 		.worker_threads(opt.threads.unwrap_or(num_cpus::get()))
 		.enable_all()
@@ -33,31 +72,36 @@ fn main() -> Result<(), Error> {
 	let mut output_type: OutputType = opt.format.clone().into();	
 
 	return runtime.block_on(async move {
-		let mut generator = Feeder::new(&opt.ports, &opt.target, &opt.exclude);
-		match opt.method {
-			 ScanMethod::Complete { wait_flag } => cli::menu::run_handle::<TcpProbe, SocketAddr, SocketAddr>
-			(
-				&mut generator,
-				&mut output_type,
-				Duration::from_secs_f32(opt.timeout)
-			).await,
-			
-			ScanMethod::Socks => cli::menu::run_handle::<Socks5Scanner, ScanResult, SocketAddr>
-			(
-				&mut generator,
-				&mut output_type,
-				Duration::from_secs_f32(opt.timeout)
-			).await,
-			
-			_ => unimplemented!()
+		if let Some(schedule) = opt.every.clone() {
+			let mut scheduler = Scheduler::new(schedule);
+			scheduler.add_sink(Box::new(ChangePrinter::new(opt.format)));
+
+			loop {
+				scheduler.wait().await?;
 
-		};
+				// hosts that came up since the last run are picked up
+				let targets = targets(&opt).await?;
+				let mut run_output = OutputType::Map(HashMap::new());
+				scan(&opt, &targets, &mut run_output).await?;
+				
+				let changes = scheduler.update(run_output.snapshot());
+				eprintln!("run {} finished, {} change(s)", scheduler.runs(), changes.len());
+			}
+		}
+
+		let targets = targets(&opt).await?;
+		scan(&opt, &targets, &mut output_type).await?;
 
 		if let OutputType::Map(map) = output_type {
 			match opt.format {
-				Format::Stdout => map.into_iter().for_each(|(key, services)| {
-					print!("{}", key);
-					services.iter().for_each(|(port, netstate)| println!("\t\t\t{}\t{}", port, netstate));
+				Format::Stdout => map.into_iter().for_each(|(key, report)| {
+					if report.timed_out {
+						println!("{}\t(timed out)", key);
+					}
+					else {
+						print!("{}", key);
+					}
+					report.ports.iter().for_each(|port| println!("\t\t\t{}", port));
 				}),
 				Format::Json => println!("{}", serde_json::to_string_pretty(&map).unwrap()),
 				Format::Stream => unreachable!() 
@@ -66,4 +110,348 @@ fn main() -> Result<(), Error> {
 		
 		Ok(())
 	});
-}
\ No newline at end of file
+}
+
+/// Targets given on the command line, or only the ones that answer host discovery
+async fn targets(opt: &cli::opt::Arguments) -> Result<Vec<AddressInput>, Error> {
+	if opt.skip_discovery {
+		return Ok(opt.target.clone())
+	}
+
+	let ping_ports: Vec<PortInput> = match opt.discovery {
+		// icmp doesn't use ports, one job per host
+		DiscoveryMethod::Echo | DiscoveryMethod::Timestamp => vec![PortInput::Singleton(0)],
+		DiscoveryMethod::Tcp if opt.ping_ports.is_empty() =>
+			DEFAULT_PING_PORTS.iter().map(|port| PortInput::Singleton(*port)).collect(),
+		DiscoveryMethod::Tcp => opt.ping_ports.clone()
+	};
+
+	let mut pinger = Feeder::new(&ping_ports, &opt.target, &opt.exclude);
+	let timeout = Duration::from_secs_f32(opt.timeout);
+	
+	let alive = match opt.discovery {
+		DiscoveryMethod::Tcp => discover_hosts::<TcpPing, SocketAddr, SocketAddr>(&mut pinger, timeout).await?,
+		DiscoveryMethod::Echo => discover_hosts::<IcmpEcho, PingReply, SocketAddr>(&mut pinger, timeout).await?,
+		DiscoveryMethod::Timestamp => discover_hosts::<IcmpTimestamp, PingReply, SocketAddr>(&mut pinger, timeout).await?,
+	};
+
+	Ok(alive)
+}
+
+/// Runs the scan picked by `opt` against `targets` once
+async fn scan(opt: &cli::opt::Arguments, targets: &[AddressInput], output_type: &mut OutputType) -> Result<(), Error> {
+	let mut generator = Feeder::new(&opt.ports, targets, &opt.exclude);
+	let host_timeout = opt.host_timeout.map(Duration::from_secs_f32);
+
+	match opt.method {
+		ScanMethod::Complete { wait_flag: false } => {
+			let config = Arc::new(ConnectConfig {
+				timeout: Duration::from_secs_f32(opt.timeout),
+			});
+			// the connect is bounded by the timeout itself, the pool's is a backstop
+			cli::menu::run_handle_as::<TcpProbe, SocketAddr, Job<ConnectConfig>, _>
+			(
+				&mut generator,
+				output_type,
+				Duration::from_secs_f32(opt.timeout) * 2,
+				host_timeout,
+				move |addr| Job::new(addr, config.clone())
+			).await?
+		},
+		
+		ScanMethod::Complete { wait_flag: true } => {
+			let wrapped_wait = match ChainedProbes::from_file(&opt.probes).await {
+				Ok(probes) => probes.null().wrapped_wait(),
+				Err(_) => tcp::WRAPPED_WAIT,
+			};
+			let config = Arc::new(HoldConfig {
+				connect: Duration::from_secs_f32(opt.timeout),
+				hold: Duration::from_secs_f32(opt.timeout),
+				wrapped_wait,
+			});
+			// connecting, then holding the connection, each get a timeout
+			let budget = config.budget();
+			cli::menu::run_handle_as::<TcpHold, Option<Hold>, Job<HoldConfig>, _>
+			(
+				&mut generator,
+				output_type,
+				budget,
+				host_timeout,
+				move |addr| Job::new(addr, config.clone())
+			).await?
+		},
+		
+		ScanMethod::Socks => {
+			let config = Arc::new(SocksConfig {
+				canary: canary(opt).await?,
+				credentials: credentials(opt),
+				timeout: Duration::from_secs_f32(opt.timeout),
+			});
+			// socks5, socks4, and socks4a are tried one after the other
+			cli::menu::run_handle_as::<Socks5Scanner, ScanResult, Job<SocksConfig>, _>
+			(
+				&mut generator,
+				output_type,
+				Duration::from_secs_f32(opt.timeout) * 4,
+				host_timeout,
+				move |addr| Job::new(addr, config.clone())
+			).await?
+		},
+		
+		ScanMethod::HttpProxy => {
+			let canary = canary(opt).await?
+				.ok_or_else(|| Error::CliError("--method http-proxy needs a --canary to request".to_string()))?;
+			let config = Arc::new(HttpProxyConfig {
+				canary,
+				timeout: Duration::from_secs_f32(opt.timeout),
+			});
+			// a GET, then a CONNECT on another connection
+			cli::menu::run_handle_as::<HttpProxyScanner, ProxyReport, Job<HttpProxyConfig>, _>
+			(
+				&mut generator,
+				output_type,
+				Duration::from_secs_f32(opt.timeout) * 4,
+				host_timeout,
+				move |addr| Job::new(addr, config.clone())
+			).await?
+		},
+		
+		ScanMethod::Udp => {
+			let config = Arc::new(UdpConfig {
+				wait: Duration::from_secs_f32(opt.timeout),
+			});
+			// every datagram is waited on, the pool's bound is a backstop
+			let budget = config.budget();
+			cli::menu::run_handle_as::<UdpProbe, Vec<u8>, Job<UdpConfig>, _>
+			(
+				&mut generator,
+				output_type,
+				budget,
+				host_timeout,
+				move |addr| Job::new(addr, config.clone())
+			).await?
+		},
+		
+		ScanMethod::Syn => {
+			// every SYN is waited on for the timeout
+			let engine = Arc::new(SynEngine::new(Duration::from_secs_f32(opt.timeout), syn::ATTEMPTS)?);
+			let budget = engine.budget();
+			cli::menu::run_handle_as::<SynProbe, SocketAddr, Job<SynEngine>, _>
+			(
+				&mut generator,
+				output_type,
+				budget,
+				host_timeout,
+				move |addr| Job::new(addr, engine.clone())
+			).await?
+		},
+		
+		ScanMethod::Banner => {
+			let config = Arc::new(BannerConfig {
+				connect: Duration::from_secs_f32(opt.timeout),
+				wait: Duration::from_secs_f32(opt.banner_wait),
+				payload: opt.banner_payload.clone().map(|x| x.0),
+				max: banner::MAX_BANNER,
+			});
+			// connecting, then waiting on the greeting, and the payload's answer
+			let budget = config.budget();
+			cli::menu::run_handle_as::<BannerGrab, Banner, Job<BannerConfig>, _>
+			(
+				&mut generator,
+				output_type,
+				budget,
+				host_timeout,
+				move |addr| Job::new(addr, config.clone())
+			).await?
+		},
+		
+		ScanMethod::Http => {
+			let config = Arc::new(HttpConfig::new(Duration::from_secs_f32(opt.timeout)));
+			// plaintext and tls can both be tried, then ALPN/h2c on another connection
+			cli::menu::run_handle_as::<HttpScanner, Option<HttpFingerprint>, Job<HttpConfig>, _>
+			(
+				&mut generator,
+				output_type,
+				Duration::from_secs_f32(opt.timeout) * 6,
+				host_timeout,
+				move |addr| Job::new(addr, config.clone())
+			).await?
+		},
+		
+		ScanMethod::Tls => {
+			let config = Arc::new(TlsScanConfig::new(Duration::from_secs_f32(opt.timeout)));
+			// a handshake for the certificates, then one for every version
+			cli::menu::run_handle_as::<TlsScanner, Option<TlsInventory>, Job<TlsScanConfig>, _>
+			(
+				&mut generator,
+				output_type,
+				Duration::from_secs_f32(opt.timeout) * 6,
+				host_timeout,
+				move |addr| Job::new(addr, config.clone())
+			).await?
+		},
+		
+		ScanMethod::Ssh => {
+			let config = Arc::new(SshConfig {
+				timeout: Duration::from_secs_f32(opt.timeout),
+			});
+			cli::menu::run_handle_as::<SshScanner, Option<SshInfo>, Job<SshConfig>, _>
+			(
+				&mut generator,
+				output_type,
+				Duration::from_secs_f32(opt.timeout) * 3,
+				host_timeout,
+				move |addr| Job::new(addr, config.clone())
+			).await?
+		},
+		
+		ScanMethod::Dns => {
+			let config = Arc::new(DnsConfig {
+				timeout: Duration::from_secs_f32(opt.timeout),
+				name: opt.dns_name.clone(),
+			});
+			// three queries, each retried over udp, or asked again over tcp
+			let budget = config.budget();
+			cli::menu::run_handle_as::<DnsScanner, Option<DnsReport>, Job<DnsConfig>, _>
+			(
+				&mut generator,
+				output_type,
+				budget,
+				host_timeout,
+				move |addr| Job::new(addr, config.clone())
+			).await?
+		},
+		
+		ScanMethod::Smb => {
+			let config = Arc::new(SmbConfig {
+				timeout: Duration::from_secs_f32(opt.timeout),
+			});
+			// one connection per dialect, and one for SMB1
+			cli::menu::run_handle_as::<SmbScanner, Option<SmbInfo>, Job<SmbConfig>, _>
+			(
+				&mut generator,
+				output_type,
+				Duration::from_secs_f32(opt.timeout) * 12,
+				host_timeout,
+				move |addr| Job::new(addr, config.clone())
+			).await?
+		},
+		
+		ScanMethod::Datastore => {
+			let config = Arc::new(DatastoreConfig::new(Duration::from_secs_f32(opt.timeout), opt.datastore_login));
+			// every datastore gets its own connection, elasticsearch a second one over tls
+			cli::menu::run_handle_as::<DatastoreScanner, Option<Exposure>, Job<DatastoreConfig>, _>
+			(
+				&mut generator,
+				output_type,
+				Duration::from_secs_f32(opt.timeout) * 16,
+				host_timeout,
+				move |addr| Job::new(addr, config.clone())
+			).await?
+		},
+		
+		ScanMethod::Ftp => {
+			let config = Arc::new(FtpConfig {
+				timeout: Duration::from_secs_f32(opt.timeout),
+			});
+			// a handful of commands, then AUTH TLS on a second connection
+			cli::menu::run_handle_as::<FtpScanner, Option<FtpInfo>, Job<FtpConfig>, _>
+			(
+				&mut generator,
+				output_type,
+				Duration::from_secs_f32(opt.timeout) * 8,
+				host_timeout,
+				move |addr| Job::new(addr, config.clone())
+			).await?
+		},
+		
+		ScanMethod::Smtp => {
+			let config = Arc::new(SmtpConfig {
+				timeout: Duration::from_secs_f32(opt.timeout),
+				relay: opt.smtp_relay,
+			});
+			// EHLO, then MAIL/RCPT/RSET when checking for a relay
+			cli::menu::run_handle_as::<SmtpScanner, Option<SmtpInfo>, Job<SmtpConfig>, _>
+			(
+				&mut generator,
+				output_type,
+				Duration::from_secs_f32(opt.timeout) * 8,
+				host_timeout,
+				move |addr| Job::new(addr, config.clone())
+			).await?
+		},
+		
+		ScanMethod::VScan => {
+			let config = Arc::new(VScanConfig {
+				probes: ChainedProbes::from_file(&opt.probes).await?,
+				intensity: opt.version_intensity,
+				connect_timeout: Duration::from_secs_f32(opt.timeout),
+			});
+			let skipped = config.probes.skipped();
+			if !skipped.is_empty() {
+				let mut services: Vec<&str> = skipped.iter().map(|x| x.service.as_str()).collect();
+				services.dedup();
+				eprintln!(
+					"{} match line(s) in {} can't be compiled, and are never tried ({}{})",
+					skipped.len(),
+					opt.probes,
+					services.iter().take(5).copied().collect::<Vec<_>>().join(", "),
+					if services.len() > 5 { ", ..." } else { "" }
+				);
+			}
+			// every probe gets its own connection and wait, each job is held to its port's budget,
+			// the pool's timeout covers the slowest port
+			let budget = opt.ports.iter()
+				.flat_map(|ports| match ports {
+					PortInput::Singleton(port) => vec![*port],
+					PortInput::Range(range) => range.clone().collect(),
+				})
+				.map(|port| config.budget(port))
+				.max()
+				.unwrap_or(config.connect_timeout);
+			cli::menu::run_handle_as::<VScan, Option<ServiceMatch>, Job<VScanConfig>, _>
+			(
+				&mut generator,
+				output_type,
+				budget,
+				host_timeout,
+				move |addr| Job::new(addr, config.clone())
+			).await?
+		},
+	};
+
+	Ok(())
+}
+
+/// Listener proxies are asked to connect to, when `--canary` is set
+async fn canary(opt: &cli::opt::Arguments) -> Result<Option<Arc<Canary>>, Error> {
+	match opt.canary {
+		Some(addr) => Ok(Some(Arc::new(Canary::bind(addr).await?))),
+		None => Ok(None)
+	}
+}
+
+/// Routes every tcp connection through `--pivot`,
+/// methods that don't connect over tcp can't be routed
+fn pivot(opt: &cli::opt::Arguments) -> Result<(), Error> {
+	match opt.method {
+		ScanMethod::Syn | ScanMethod::Udp =>
+			return Err(Error::CliError("--pivot only routes tcp connections, not raw or udp scans".to_string())),
+		_ => {}
+	}
+
+	if !opt.skip_discovery && !matches!(opt.discovery, DiscoveryMethod::Tcp) {
+		return Err(Error::CliError("--pivot only works with --discovery tcp, or --skip-discovery".to_string()));
+	}
+
+	if let Some(chain) = ProxyChain::new(opt.pivot.clone()) {
+		route_through(chain)
+			.map_err(|_| Error::CliError("a pivot chain is already in place".to_string()))?;
+	}
+	Ok(())
+}
+
+fn credentials(opt: &cli::opt::Arguments) -> Option<(String, String)> {
+	opt.proxy_user.clone()
+		.map(|user| (user, opt.proxy_pass.clone().unwrap_or_default()))
+}